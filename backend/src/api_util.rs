@@ -0,0 +1,564 @@
+// Libs
+use anyhow::{anyhow, Context};
+use axum::{
+    extract::{Path, Query},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
+    Json, Router,
+};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use serde_json::json;
+
+use ufcr_libs::log_err;
+
+use crate::{
+    app_util::get_app_metadata,
+    bin_util::{get_vod_formats, preview_output_path, VodFormats},
+    config_util::{
+        get_config, get_redacted_config, is_debug, parse_config_data, update_config, ConfigUpdate,
+        Subscription,
+    },
+    dl_util::{
+        create_download_handlers, enqueue_collection, verify_urls, CollectionDownload,
+        OutputPathPreview,
+    },
+    fp_util::{
+        BrowsePage, InvalidRequestError, NotFoundError, Playlist, SearchRequest, SearchResult,
+    },
+    history_util::{
+        export_history, prune_history, query_history, ExportFormat, HistoryEntry, HistoryQuery,
+        PruneRequest,
+    },
+    net_util::{
+        get_browse_page, get_playlist, get_vod_meta, get_vod_stream_url, search_library,
+        search_vods, update_proxied_client, JSON,
+    },
+    sched_util::{
+        cancel_scheduled_download, enqueue_download, get_pending_dl_order, move_pending_download,
+        pause_scheduled_download, reschedule, resume_download,
+    },
+    state_util::{clear_inactive_dlq_vods, get_dlq, Vod},
+    txt_util::{create_uuid, create_vod_url},
+    watch_util::{check_watchlist, get_watchlist, remove_subscription, save_subscription},
+    ws_util::{emit_config_update, emit_pending_dl_order, emit_vod_download_progress, emit_warn},
+};
+
+// Types
+type ApiResult<T> = Result<T, ApiError>;
+
+// Structs
+/// An error response of the REST API, in the same format as the `WebSocket` error responses.
+struct ApiError {
+    status: StatusCode,
+    error: anyhow::Error,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, error: anyhow::Error) -> Self {
+        Self { status, error }
+    }
+
+    /// Creates an error response for a failed Fight Pass request. Invalid requests and content
+    /// that doesn't exist get their own status codes, other failures are bad gateway errors.
+    fn upstream(error: anyhow::Error) -> Self {
+        Self {
+            status: get_error_status(&error).unwrap_or(StatusCode::BAD_GATEWAY),
+            error,
+        }
+    }
+}
+
+impl<E> From<E> for ApiError
+where
+    E: Into<anyhow::Error>,
+{
+    fn from(error: E) -> Self {
+        let error = error.into();
+
+        Self {
+            status: get_error_status(&error).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            error,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let error_dbg = format!("{:#?}", self.error);
+        let error_msg = self.error.to_string();
+
+        if is_debug() {
+            log_err!("{error_dbg}\n");
+        } else {
+            log_err!("{error_msg}\n");
+        }
+
+        (
+            self.status,
+            Json(json!({
+                "error": {
+                    "name": "UFCRError",
+                    "message": error_dbg,
+                    "userMsg": error_msg
+                }
+            })),
+        )
+            .into_response()
+    }
+}
+
+/// Query parameters of the search endpoint.
+#[derive(Deserialize)]
+struct SearchQuery {
+    query: String,
+    #[serde(default)]
+    page: u64,
+}
+
+/// Request body of the download endpoint. Either the Fight Pass URL or the ID of the VOD is
+/// required.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DownloadRequest {
+    url: Option<String>,
+    id: Option<u64>,
+    #[serde(default)]
+    custom_format: String,
+    /// Name of the account profile used for the download. Defaults to the active account.
+    #[serde(default)]
+    account: String,
+}
+
+/// Request body of the batch verify endpoint. The URLs can be given as a list, as text with one URL
+/// per line, or both.
+#[derive(Deserialize)]
+#[serde(default)]
+struct VerifyRequest {
+    urls: Vec<String>,
+    text: String,
+}
+
+/// Query parameters of the history export endpoint.
+#[derive(Deserialize)]
+struct ExportQuery {
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+/// Request body of the download reorder endpoint.
+#[derive(Deserialize)]
+struct PositionRequest {
+    position: usize,
+}
+
+// Statics
+/// Holds the OpenAPI document of the REST API.
+static OPENAPI_DOC: Lazy<JSON> = Lazy::new(|| {
+    let mut doc: JSON = serde_json::from_str(include_str!("../../api/v1/openapi.json"))
+        .expect("Failed to parse the OpenAPI document");
+
+    doc["info"]["version"] = json!(get_app_metadata().version);
+
+    doc
+});
+
+/// Creates the router for the versioned REST API. The API is backed by the same functions as the
+/// `WebSocket` events, so the GUI is kept in sync with any changes made through it.
+pub fn create_api_router() -> Router {
+    Router::new()
+        .route("/openapi.json", get(|| async { Json(OPENAPI_DOC.clone()) }))
+        .route(
+            "/config",
+            get(handle_get_config_req).put(handle_put_config_req),
+        )
+        .route(
+            "/queue",
+            get(handle_get_queue_req).delete(handle_clear_queue_req),
+        )
+        .route(
+            "/queue/pending",
+            get(|| async { Json(get_pending_dl_order()) }),
+        )
+        .route("/vods/verify", post(handle_verify_vods_req))
+        .route("/vods/:id", get(handle_get_vod_req))
+        .route("/vods/:id/formats", get(handle_get_vod_formats_req))
+        .route("/vods/:id/output-path", get(handle_preview_output_path_req))
+        .route("/downloads", post(handle_create_download_req))
+        .route(
+            "/downloads/collection",
+            post(handle_download_collection_req),
+        )
+        .route(
+            "/downloads/:q_id",
+            get(handle_get_download_req).delete(handle_cancel_download_req),
+        )
+        .route("/downloads/:q_id/pause", post(handle_pause_download_req))
+        .route("/downloads/:q_id/resume", post(handle_resume_download_req))
+        .route("/downloads/:q_id/position", put(handle_move_download_req))
+        .route(
+            "/search",
+            get(handle_search_req).post(handle_search_library_req),
+        )
+        .route(
+            "/browse",
+            get(|| handle_get_browse_page_req(Path("home".into()))),
+        )
+        .route("/browse/*page", get(handle_get_browse_page_req))
+        .route("/playlists/:id", get(handle_get_playlist_req))
+        .route("/history", get(handle_get_history_req))
+        .route("/history/export", get(handle_export_history_req))
+        .route("/history/prune", post(handle_prune_history_req))
+        .route(
+            "/watchlist",
+            get(handle_get_watchlist_req).post(handle_save_subscription_req),
+        )
+        .route("/watchlist/check", post(handle_check_watchlist_req))
+        .route("/watchlist/:id", delete(handle_remove_subscription_req))
+}
+
+/// Handles `GET /config`.
+async fn handle_get_config_req() -> Json<JSON> {
+    Json(json!(get_redacted_config()))
+}
+
+/// Handles `PUT /config`.
+async fn handle_put_config_req(Json(data): Json<JSON>) -> ApiResult<Json<JSON>> {
+    let (new_config, _) =
+        parse_config_data(&data).map_err(|error| ApiError::new(StatusCode::BAD_REQUEST, error))?;
+
+    update_config(ConfigUpdate::Config(Box::new(new_config))).await;
+    reschedule();
+    emit_config_update();
+
+    if let Err(error) = update_proxied_client() {
+        log_err!("{error}\n");
+    }
+
+    Ok(Json(json!(get_redacted_config())))
+}
+
+/// Handles `GET /queue`.
+async fn handle_get_queue_req() -> Json<JSON> {
+    Json(json!(*get_dlq()))
+}
+
+/// Handles `DELETE /queue`, which removes the finished and failed downloads.
+async fn handle_clear_queue_req() -> Json<JSON> {
+    clear_inactive_dlq_vods();
+
+    Json(json!(*get_dlq()))
+}
+
+/// Handles `GET /vods/{id}`.
+async fn handle_get_vod_req(Path(id): Path<u64>) -> ApiResult<Json<Vod>> {
+    Ok(Json(get_vod_by_id(id, "").await?))
+}
+
+/// Handles `POST /vods/verify`, which verifies a batch of Fight Pass URLs or VOD IDs.
+async fn handle_verify_vods_req(Json(request): Json<VerifyRequest>) -> ApiResult<Json<Vec<JSON>>> {
+    let mut urls = request.urls;
+
+    // The text is verified after the listed URLs, so the line numbers continue from the list
+    urls.extend(request.text.lines().map(str::to_string));
+
+    let results = verify_urls(&urls.join("\n"))
+        .await
+        .map_err(|error| ApiError::new(StatusCode::BAD_REQUEST, error))?;
+
+    Ok(Json(results))
+}
+
+/// Handles `GET /vods/{id}/formats`.
+async fn handle_get_vod_formats_req(Path(id): Path<u64>) -> ApiResult<Json<JSON>> {
+    let vod = get_vod_by_id(id, "").await?;
+    let hls = get_vod_stream_url(vod.id, "")
+        .await
+        .map_err(ApiError::upstream)?;
+    let VodFormats {
+        formats,
+        audio_tracks,
+        subtitles,
+    } = get_vod_formats(&hls, |warning| {
+        emit_warn(&format!("{}: {warning}", vod.title))
+    })
+    .await?;
+
+    Ok(Json(json!({
+        "VOD": vod,
+        "formats": formats,
+        "audioTracks": audio_tracks,
+        "subtitles": subtitles
+    })))
+}
+
+/// Handles `GET /vods/{id}/output-path`, which shows where the VOD would be downloaded to with the
+/// given or the configured output templates.
+async fn handle_preview_output_path_req(
+    Path(id): Path<u64>,
+    Query(query): Query<OutputPathPreview>,
+) -> ApiResult<Json<JSON>> {
+    let vod = get_vod_by_id(id, "").await?;
+    let preview = preview_output_path(&vod, query.file_template, query.dir_template)
+        .map_err(|error| ApiError::new(StatusCode::BAD_REQUEST, error))?;
+
+    Ok(Json(preview))
+}
+
+/// Handles `POST /downloads`, which adds a new download to the downloads-queue.
+async fn handle_create_download_req(
+    Json(req): Json<DownloadRequest>,
+) -> ApiResult<(StatusCode, Json<Vod>)> {
+    let vod = match (req.url, req.id) {
+        (Some(url), _) => {
+            let mut vod = get_vod_meta(&url, &req.account)
+                .await
+                .map_err(ApiError::upstream)?;
+
+            vod.q_id = create_uuid();
+            vod
+        }
+        (None, Some(id)) => get_vod_by_id(id, &req.account).await?,
+        (None, None) => {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                anyhow!("Either the URL or the ID of the VOD is required"),
+            ))
+        }
+    };
+
+    if !vod.access {
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            anyhow!("You need a Fight Pass subscription to download this video"),
+        ));
+    }
+
+    let queued_vod = enqueue_download(
+        Vod {
+            custom_format: req.custom_format,
+            ..vod
+        },
+        false,
+        create_download_handlers(),
+    )
+    .map_err(|error| ApiError::new(StatusCode::CONFLICT, error))?;
+
+    // Lets the connected clients show the download before a download slot is available
+    emit_vod_download_progress(&queued_vod.q_id, json!(queued_vod));
+
+    Ok((StatusCode::ACCEPTED, Json(queued_vod)))
+}
+
+/// Handles `GET /downloads/{qID}`.
+async fn handle_get_download_req(Path(q_id): Path<String>) -> ApiResult<Json<Vod>> {
+    Ok(Json(get_dlq_vod(&q_id)?))
+}
+
+/// Handles `DELETE /downloads/{qID}`, which cancels a download.
+async fn handle_cancel_download_req(Path(q_id): Path<String>) -> ApiResult<Json<JSON>> {
+    let removed = cancel_scheduled_download(&get_dlq_vod(&q_id)?).await?;
+
+    emit_vod_download_progress(&q_id, json!({ "status": "cancelled" }));
+
+    Ok(Json(json!({
+        "removedFiles": removed
+    })))
+}
+
+/// Handles `POST /downloads/{qID}/pause`.
+async fn handle_pause_download_req(Path(q_id): Path<String>) -> ApiResult<Json<Vod>> {
+    pause_scheduled_download(&get_dlq_vod(&q_id)?)
+        .await
+        .map_err(|error| ApiError::new(StatusCode::CONFLICT, error))?;
+
+    let vod = get_dlq_vod(&q_id)?;
+
+    emit_vod_download_progress(&q_id, json!(vod));
+
+    Ok(Json(vod))
+}
+
+/// Handles `POST /downloads/{qID}/resume`.
+async fn handle_resume_download_req(Path(q_id): Path<String>) -> ApiResult<Json<Vod>> {
+    get_dlq_vod(&q_id)?;
+
+    let vod = resume_download(&q_id, create_download_handlers())
+        .map_err(|error| ApiError::new(StatusCode::CONFLICT, error))?;
+
+    emit_vod_download_progress(&vod.q_id, json!(vod));
+
+    Ok(Json(vod))
+}
+
+/// Handles `PUT /downloads/{qID}/position`, which moves a queued download to a new position among
+/// the pending downloads.
+async fn handle_move_download_req(
+    Path(q_id): Path<String>,
+    Json(req): Json<PositionRequest>,
+) -> ApiResult<Json<Vec<String>>> {
+    get_dlq_vod(&q_id)?;
+
+    let order = move_pending_download(&q_id, req.position)
+        .map_err(|error| ApiError::new(StatusCode::CONFLICT, error))?;
+
+    emit_pending_dl_order(&order);
+
+    Ok(Json(order))
+}
+
+/// Handles `GET /search`.
+async fn handle_search_req(Query(query): Query<SearchQuery>) -> ApiResult<Json<JSON>> {
+    let result = search_vods(&query.query, query.page)
+        .await
+        .map_err(ApiError::upstream)?;
+
+    Ok(Json(result))
+}
+
+/// Handles `GET /browse/{page}`.
+async fn handle_get_browse_page_req(Path(page): Path<String>) -> ApiResult<Json<BrowsePage>> {
+    let page = get_browse_page(&page, "")
+        .await
+        .map_err(ApiError::upstream)?;
+
+    Ok(Json(page))
+}
+
+/// Handles `GET /playlists/{id}`.
+async fn handle_get_playlist_req(Path(id): Path<u64>) -> ApiResult<Json<Playlist>> {
+    let playlist = get_playlist(id, "").await.map_err(ApiError::upstream)?;
+
+    Ok(Json(playlist))
+}
+
+/// Handles `POST /downloads/collection`, which adds every VOD of a playlist or an event page to
+/// the downloads-queue.
+async fn handle_download_collection_req(
+    Json(request): Json<CollectionDownload>,
+) -> ApiResult<(StatusCode, Json<JSON>)> {
+    let result = enqueue_collection(request)
+        .await
+        .map_err(ApiError::upstream)?;
+
+    Ok((StatusCode::ACCEPTED, Json(result)))
+}
+
+/// Handles `POST /search`, which searches with filters and returns typed results.
+async fn handle_search_library_req(
+    Json(request): Json<SearchRequest>,
+) -> ApiResult<Json<SearchResult>> {
+    request
+        .validate()
+        .map_err(|error| ApiError::new(StatusCode::BAD_REQUEST, error))?;
+
+    let result = search_library(request).await.map_err(ApiError::upstream)?;
+
+    Ok(Json(result))
+}
+
+/// Handles `GET /history`, which queries the download history.
+async fn handle_get_history_req(
+    Query(query): Query<HistoryQuery>,
+) -> ApiResult<Json<Vec<HistoryEntry>>> {
+    Ok(Json(query_history(query).await?))
+}
+
+/// Handles `GET /history/export`. Exports the download history as JSON unless the CSV format is
+/// requested.
+async fn handle_export_history_req(Query(query): Query<ExportQuery>) -> ApiResult<Response> {
+    let content_type = match query.format {
+        ExportFormat::Json => "application/json",
+        ExportFormat::Csv => "text/csv",
+    };
+
+    Ok((
+        [(header::CONTENT_TYPE, content_type)],
+        export_history(query.format).await?,
+    )
+        .into_response())
+}
+
+/// Handles `POST /history/prune`, which removes entries from the download history.
+async fn handle_prune_history_req(Json(request): Json<PruneRequest>) -> ApiResult<Json<JSON>> {
+    let removed = prune_history(request)
+        .await
+        .map_err(|error| ApiError::new(StatusCode::BAD_REQUEST, error))?;
+
+    Ok(Json(json!({ "removed": removed })))
+}
+
+/// Handles `GET /watchlist`.
+async fn handle_get_watchlist_req() -> ApiResult<Json<Vec<JSON>>> {
+    Ok(Json(get_watchlist().await?))
+}
+
+/// Handles `POST /watchlist`, which adds or replaces a watchlist subscription.
+async fn handle_save_subscription_req(
+    Json(subscription): Json<Subscription>,
+) -> ApiResult<Json<Subscription>> {
+    let subscription = save_subscription(subscription)
+        .await
+        .map_err(|error| ApiError::new(StatusCode::BAD_REQUEST, error))?;
+
+    emit_config_update();
+
+    Ok(Json(subscription))
+}
+
+/// Handles `DELETE /watchlist/{id}`.
+async fn handle_remove_subscription_req(Path(id): Path<String>) -> ApiResult<Json<JSON>> {
+    remove_subscription(&id)
+        .await
+        .map_err(|error| ApiError::new(StatusCode::NOT_FOUND, error))?;
+
+    emit_config_update();
+
+    Ok(Json(json!({ "removed": id })))
+}
+
+/// Handles `POST /watchlist/check`, which checks the watchlist for new VODs right away.
+async fn handle_check_watchlist_req() -> ApiResult<Json<Vec<Vod>>> {
+    let queued = check_watchlist().await.map_err(ApiError::upstream)?;
+
+    Ok(Json(queued))
+}
+
+/// Retrieves the metadata of a VOD using its Fight Pass ID and the given account. The active
+/// account is used if the account name is empty.
+async fn get_vod_by_id(id: u64, account_name: &str) -> ApiResult<Vod> {
+    let account = get_config()
+        .get_account(account_name)
+        .context(format!(r#"Account "{account_name}" does not exist"#))
+        .map_err(|error| ApiError::new(StatusCode::BAD_REQUEST, error))?;
+    let url = create_vod_url(&account.region, id);
+    let mut vod = get_vod_meta(&url, account_name)
+        .await
+        .map_err(ApiError::upstream)?;
+
+    vod.q_id = create_uuid();
+
+    Ok(vod)
+}
+
+/// Returns a copy of a VOD in the downloads-queue.
+fn get_dlq_vod(q_id: &str) -> ApiResult<Vod> {
+    get_dlq()
+        .get(q_id)
+        .cloned()
+        .context("VOD does not exist in the downloads-queue")
+        .map_err(|error| ApiError::new(StatusCode::NOT_FOUND, error))
+}
+
+/// Returns the status code of an error that was caused by the request, like an invalid page name
+/// or a VOD that doesn't exist.
+fn get_error_status(error: &anyhow::Error) -> Option<StatusCode> {
+    if error.is::<InvalidRequestError>() {
+        Some(StatusCode::BAD_REQUEST)
+    } else if error.is::<NotFoundError>() {
+        Some(StatusCode::NOT_FOUND)
+    } else {
+        None
+    }
+}
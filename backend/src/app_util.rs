@@ -59,11 +59,14 @@ pub fn is_container() -> bool {
     }
 }
 
-/// Returns the application's root directory, depending on the compiled mode.
+/// Returns the application's root directory, depending on the compiled mode. Can be overridden
+/// with the `UFCR_APP_ROOT_DIR` environment variable.
 pub fn get_app_root_dir() -> PathBuf {
     let err_msg = "Failed to determine the application's root directory";
 
-    if cfg!(debug_assertions) {
+    if let Ok(root_dir) = env::var("UFCR_APP_ROOT_DIR") {
+        PathBuf::from(root_dir)
+    } else if cfg!(debug_assertions) {
         PathBuf::from(env!("CARGO_MANIFEST_DIR"))
             .parent()
             .unwrap_or_quit(err_msg)
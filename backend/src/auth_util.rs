@@ -0,0 +1,473 @@
+// Libs
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use argon2::{
+    password_hash::{
+        rand_core::{OsRng, RngCore},
+        PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+    },
+    Argon2,
+};
+use axum::{
+    extract::Request,
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Form, Json, Router,
+};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use ufcr_libs::log_warn;
+
+use crate::{
+    config_util::{get_config, update_config, ConfigUpdate},
+    rt_util::QuitUnwrap,
+};
+
+// Structs
+/// Login form submitted by the login page.
+#[derive(Deserialize)]
+struct LoginForm {
+    password: String,
+}
+
+/// Request body of the set-password action.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SetPasswordReq {
+    /// The current password or access token. Only required when the authentication is enabled.
+    #[serde(default)]
+    current_password: String,
+    password: String,
+}
+
+/// Failed GUI login attempts, used to slow down password guessing.
+#[derive(Default)]
+struct LoginThrottle {
+    /// Number of consecutive failed login attempts.
+    failures: u32,
+    /// Time before which no login attempts are accepted.
+    blocked_until: Option<Instant>,
+}
+
+// Statics
+/// Name of the cookie that holds the GUI login session.
+const SESSION_COOKIE: &str = "ufcr_session";
+/// Paths that can be accessed without authentication.
+const PUBLIC_PATHS: [&str; 2] = ["/login", "/auth/login"];
+/// Minimum length of a GUI password.
+const MIN_PASSWORD_LEN: usize = 8;
+/// Number of failed login attempts that are allowed before the logins are throttled.
+const FREE_LOGIN_ATTEMPTS: u32 = 5;
+/// Longest time a client has to wait between login attempts.
+const MAX_LOGIN_DELAY: Duration = Duration::from_secs(15 * 60);
+/// Holds the active GUI login sessions with their expiry times.
+static SESSIONS: Lazy<Mutex<HashMap<String, Instant>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+/// Holds the access token hash that was last verified, with the SHA-256 digest of the token. A
+/// token is only verified with Argon2 once, instead of on every request.
+static VERIFIED_TOKEN: Lazy<Mutex<Option<(String, [u8; 32])>>> = Lazy::new(|| Mutex::new(None));
+static LOGIN_THROTTLE: Lazy<Mutex<LoginThrottle>> =
+    Lazy::new(|| Mutex::new(LoginThrottle::default()));
+
+/// Hashes a password or an access token using Argon2, to be stored in the configuration.
+pub fn hash_secret(secret: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Argon2::default()
+        .hash_password(secret.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|error| anyhow!("Failed to hash the secret: {error}"))
+}
+
+/// Verifies a password or an access token against a hash from the configuration.
+fn verify_secret(secret: &str, hash: &str) -> bool {
+    if hash.is_empty() {
+        return false;
+    }
+
+    PasswordHash::new(hash).is_ok_and(|parsed_hash| {
+        Argon2::default()
+            .verify_password(secret.as_bytes(), &parsed_hash)
+            .is_ok()
+    })
+}
+
+/// Generates a new random access token.
+pub fn create_access_token() -> String {
+    let mut bytes = [0u8; 32];
+
+    OsRng.fill_bytes(&mut bytes);
+
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Checks if a request is authorized, using the session cookie or the bearer access token in the
+/// headers. All requests are authorized when the authentication is disabled.
+pub fn is_request_authorized(headers: &HeaderMap) -> bool {
+    let config = get_config();
+    let gui_auth = &config.gui_auth;
+
+    if !gui_auth.enabled {
+        return true;
+    }
+
+    if let Some(session) = get_session_cookie(headers) {
+        if is_valid_session(session) {
+            return true;
+        }
+    }
+
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| verify_access_token(token.trim(), &gui_auth.token_hash))
+}
+
+/// Verifies a bearer access token. Only the first request with a token is verified with Argon2,
+/// the following ones are compared with the cached digest of the token in constant time. Tokens
+/// that have to be verified with Argon2 count as login attempts, so they can't be used to guess
+/// the token or to keep the server busy with hashing.
+fn verify_access_token(token: &str, token_hash: &str) -> bool {
+    let digest: [u8; 32] = Sha256::digest(token.as_bytes()).into();
+
+    if let Some((verified_hash, verified_digest)) = get_verified_token().as_ref() {
+        if verified_hash == token_hash && bool::from(digest[..].ct_eq(&verified_digest[..])) {
+            return true;
+        }
+    }
+
+    if get_login_wait().is_some() {
+        return false;
+    }
+
+    let is_verified = verify_secret(token, token_hash);
+
+    record_login_attempt(is_verified);
+
+    if !is_verified {
+        log_warn!("Rejected a request with an invalid access token\n");
+
+        return false;
+    }
+
+    *get_verified_token() = Some((token_hash.to_string(), digest));
+
+    true
+}
+
+/// Middleware that rejects unauthorized requests. Unauthorized GUI page requests are redirected to
+/// the login page.
+pub async fn handle_auth(req: Request, next: Next) -> Response {
+    let path = req.uri().path();
+
+    if PUBLIC_PATHS.contains(&path) || is_request_authorized(req.headers()) {
+        return next.run(req).await;
+    }
+
+    if path.starts_with("/api/")
+        || path.starts_with("/socket.io")
+        || path == "/auth/password"
+        || path == "/export_config"
+    {
+        create_error_response(
+            StatusCode::UNAUTHORIZED,
+            "Authentication is required to access UFC Ripper",
+        )
+    } else {
+        Redirect::to("/login").into_response()
+    }
+}
+
+/// Creates the router for the login page and the session endpoints.
+pub fn create_auth_router() -> Router {
+    Router::new()
+        .route("/login", get(|| async { Html(create_login_page(None)) }))
+        .route("/auth/login", post(handle_login_req))
+        .route("/auth/logout", post(handle_logout_req))
+        .route("/auth/password", post(handle_set_password_req))
+}
+
+/// Handles `POST /auth/login`, which starts a new GUI login session.
+async fn handle_login_req(Form(form): Form<LoginForm>) -> Response {
+    if let Some(wait) = get_login_wait() {
+        let error = format!(
+            "Too many failed login attempts. Please try again in {} seconds",
+            wait.as_secs().max(1)
+        );
+
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            Html(create_login_page(Some(&error))),
+        )
+            .into_response();
+    }
+
+    if !verify_secret(&form.password, &get_config().gui_auth.password_hash) {
+        log_warn!("Rejected a GUI login attempt with an invalid password\n");
+        record_login_attempt(false);
+
+        return (
+            StatusCode::UNAUTHORIZED,
+            Html(create_login_page(Some(
+                "Invalid password. Please try again",
+            ))),
+        )
+            .into_response();
+    }
+
+    record_login_attempt(true);
+
+    let ttl = get_config()
+        .gui_auth
+        .session_ttl
+        .max(1)
+        .saturating_mul(3600);
+    let cookie = format!(
+        "{SESSION_COOKIE}={}; Path=/; Max-Age={ttl}; HttpOnly; SameSite=Strict",
+        create_session(Duration::from_secs(ttl))
+    );
+
+    ([(header::SET_COOKIE, cookie)], Redirect::to("/")).into_response()
+}
+
+/// Handles `POST /auth/logout`, which ends the current GUI login session.
+async fn handle_logout_req(headers: HeaderMap) -> Response {
+    if let Some(session) = get_session_cookie(&headers) {
+        get_sessions().remove(session);
+    }
+
+    let cookie = format!("{SESSION_COOKIE}=; Path=/; Max-Age=0; HttpOnly; SameSite=Strict");
+
+    ([(header::SET_COOKIE, cookie)], Redirect::to("/login")).into_response()
+}
+
+/// Handles `POST /auth/password`, which sets the GUI password and enables the authentication. Once
+/// the authentication is enabled, the current password or the access token is required too.
+async fn handle_set_password_req(Json(req): Json<SetPasswordReq>) -> Response {
+    let mut gui_auth = get_config().gui_auth.clone();
+
+    if gui_auth.enabled {
+        // Password changes count as login attempts, so they can't be used to guess the password
+        if get_login_wait().is_some() {
+            return create_error_response(
+                StatusCode::TOO_MANY_REQUESTS,
+                "Too many failed login attempts. Please try again later",
+            );
+        }
+
+        let is_verified = verify_secret(&req.current_password, &gui_auth.password_hash)
+            || verify_secret(&req.current_password, &gui_auth.token_hash);
+
+        record_login_attempt(is_verified);
+
+        if !is_verified {
+            log_warn!("Rejected a GUI password change with invalid credentials\n");
+
+            return create_error_response(
+                StatusCode::UNAUTHORIZED,
+                "The current password is invalid",
+            );
+        }
+    }
+
+    if req.password.chars().count() < MIN_PASSWORD_LEN {
+        return create_error_response(
+            StatusCode::BAD_REQUEST,
+            &format!("The password should be at least {MIN_PASSWORD_LEN} characters long"),
+        );
+    }
+
+    gui_auth.password_hash = match hash_secret(&req.password) {
+        Ok(hash) => hash,
+        Err(error) => {
+            return create_error_response(StatusCode::INTERNAL_SERVER_ERROR, &error.to_string())
+        }
+    };
+    gui_auth.enabled = true;
+
+    update_config(ConfigUpdate::GuiAuth(gui_auth)).await;
+    get_sessions().clear();
+
+    Json(json!({ "enabled": true })).into_response()
+}
+
+/// Creates a JSON error response in the same format as the REST API errors.
+fn create_error_response(status: StatusCode, user_msg: &str) -> Response {
+    (
+        status,
+        Json(json!({
+            "error": {
+                "name": "UFCRError",
+                "message": user_msg,
+                "userMsg": user_msg
+            }
+        })),
+    )
+        .into_response()
+}
+
+/// Returns how long a client has to wait before the next login attempt, if the logins are being
+/// throttled.
+fn get_login_wait() -> Option<Duration> {
+    get_login_throttle()
+        .blocked_until
+        .and_then(|blocked_until| blocked_until.checked_duration_since(Instant::now()))
+}
+
+/// Records the result of a login attempt. Each failed attempt after the free ones doubles the time
+/// before the next attempt is accepted, while a successful one resets the throttling.
+fn record_login_attempt(success: bool) {
+    let mut throttle = get_login_throttle();
+
+    if success {
+        *throttle = LoginThrottle::default();
+    } else {
+        throttle.failures = throttle.failures.saturating_add(1);
+        throttle.blocked_until = Some(Instant::now() + get_login_delay(throttle.failures));
+    }
+}
+
+/// Returns the time to wait between login attempts after a number of consecutive failed attempts.
+fn get_login_delay(failures: u32) -> Duration {
+    if failures < FREE_LOGIN_ATTEMPTS {
+        return Duration::ZERO;
+    }
+
+    let exponent = (failures - FREE_LOGIN_ATTEMPTS).min(10);
+
+    Duration::from_secs(30 * 2u64.pow(exponent)).min(MAX_LOGIN_DELAY)
+}
+
+/// Creates a new GUI login session and returns its token.
+fn create_session(ttl: Duration) -> String {
+    let token = create_access_token();
+
+    get_sessions().insert(token.clone(), Instant::now() + ttl);
+
+    token
+}
+
+/// Checks if a GUI login session exists and hasn't expired. Expired sessions are removed.
+fn is_valid_session(token: &str) -> bool {
+    let mut sessions = get_sessions();
+    let now = Instant::now();
+
+    sessions.retain(|_, expiry| *expiry > now);
+    sessions.contains_key(token)
+}
+
+/// Returns the GUI login session token from the cookies in the headers.
+fn get_session_cookie(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|cookies| cookies.split(';'))
+        .find_map(|cookie| {
+            cookie
+                .trim()
+                .strip_prefix(SESSION_COOKIE)
+                .and_then(|value| value.strip_prefix('='))
+        })
+}
+
+/// Locks and returns a `MutexGuard` for the GUI login sessions.
+fn get_sessions() -> MutexGuard<'static, HashMap<String, Instant>> {
+    SESSIONS
+        .lock()
+        .unwrap_or_quit("Failed to exclusively access the login sessions")
+}
+
+/// Locks and returns a `MutexGuard` for the last verified access token.
+fn get_verified_token() -> MutexGuard<'static, Option<(String, [u8; 32])>> {
+    VERIFIED_TOKEN
+        .lock()
+        .unwrap_or_quit("Failed to exclusively access the verified access token")
+}
+
+/// Locks and returns a `MutexGuard` for the login throttling.
+fn get_login_throttle() -> MutexGuard<'static, LoginThrottle> {
+    LOGIN_THROTTLE
+        .lock()
+        .unwrap_or_quit("Failed to exclusively access the login throttling")
+}
+
+/// Creates the HTML for the login page, with an optional error message.
+fn create_login_page(error: Option<&str>) -> String {
+    let error = error
+        .map(|error| format!(r#"<p class="error">{error}</p>"#))
+        .unwrap_or_default();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="UTF-8">
+  <meta name="viewport" content="width=device-width, initial-scale=1.0">
+  <title>UFC Ripper - Login</title>
+  <style>
+    body {{ margin: 0; min-height: 100vh; display: flex; align-items: center; justify-content: center;
+      background: #121212; color: #e0e0e0; font-family: sans-serif; }}
+    form {{ display: flex; flex-direction: column; gap: 12px; width: 280px; }}
+    input, button {{ padding: 10px; border-radius: 4px; border: 1px solid #444; font-size: 1rem; }}
+    input {{ background: #1e1e1e; color: inherit; }}
+    button {{ background: #d20a0a; color: #fff; border: none; cursor: pointer; }}
+    .error {{ color: #ff6b6b; margin: 0; }}
+  </style>
+</head>
+<body>
+  <form method="post" action="/auth/login">
+    <h2>UFC Ripper</h2>
+    {error}
+    <input type="password" name="password" placeholder="Password" autofocus required>
+    <button type="submit">Log in</button>
+  </form>
+</body>
+</html>"#
+    )
+}
+
+/*************
+ *   Tests   *
+ *************/
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{get_login_delay, hash_secret, verify_access_token};
+
+    #[test]
+    fn unit_verify_access_token() {
+        let token_hash = hash_secret("token").unwrap();
+
+        assert!(verify_access_token("token", &token_hash));
+        assert!(verify_access_token("token", &token_hash));
+        assert!(!verify_access_token("other-token", &token_hash));
+
+        // A new token hash invalidates the cached token
+        assert!(!verify_access_token(
+            "token",
+            &hash_secret("new-token").unwrap()
+        ));
+    }
+
+    #[test]
+    fn unit_get_login_delay() {
+        assert_eq!(get_login_delay(4), Duration::ZERO);
+        assert_eq!(get_login_delay(5), Duration::from_secs(30));
+        assert_eq!(get_login_delay(7), Duration::from_secs(120));
+        assert_eq!(get_login_delay(u32::MAX), Duration::from_secs(15 * 60));
+    }
+}
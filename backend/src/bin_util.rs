@@ -1,38 +1,122 @@
 // Libs
 use std::{
     collections::HashMap,
+    error::Error,
     ffi::OsStr,
-    path::PathBuf,
-    process::Stdio,
+    fmt::{self, Display, Formatter},
+    path::{Path, PathBuf},
+    process::{Command as StdCommand, ExitStatus, Stdio},
     sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
 };
 
 use anyhow::{anyhow, Context};
 use colored::Colorize;
 use once_cell::sync::Lazy;
+use serde::Serialize;
 use serde_json::json;
 use tokio::{
+    fs,
     io::{AsyncBufReadExt, BufReader},
     process::{Child, Command},
+    sync::oneshot,
     task::JoinHandle,
-    time::Instant,
+    time::{sleep, Instant},
 };
 
 use ufcr_libs::{log_err, log_warn};
 
 use crate::{
     app_util::get_app_root_dir,
-    config_util::{get_config, inc_file_number, is_debug, UFCRConfig},
-    net_util::JSON,
+    config_util::{get_config, inc_file_number, is_debug, RetryPolicy, UFCRConfig},
+    fs_util::{find_download_output, get_download_dirs, remove_partial_downloads},
+    history_util::{create_history_entry, find_downloaded_vod, record_download},
+    library_util::write_library_files,
+    metadata_util::{create_metadata_args, embed_cover_and_chapters},
+    net_util::{get_vod_stream_url, JSON},
+    postproc_util::run_post_processing,
     rt_util::QuitUnwrap,
-    state_util::{add_vod_to_queue, update_dlq_vod_status, Vod},
-    txt_util::{process_yt_dlp_stderr, process_yt_dlp_stdout},
+    state_util::{
+        add_vod_to_queue, get_dlq, replace_dlq_vod_status, update_dlq_vod, update_dlq_vod_status,
+        Vod,
+    },
+    template_util::{create_output_path, validate_output_templates},
+    txt_util::{parse_yt_dlp_formats, process_yt_dlp_stderr, process_yt_dlp_stdout, YtDlpStderr},
+    verify_util::verify_download,
 };
 
 // Types
-type TaskMap = HashMap<String, JoinHandle<()>>;
+type TaskMap = HashMap<String, DownloadTask>;
 
 // Structs
+/// Holds the callbacks that get notified about the state changes of a download.
+#[derive(Clone)]
+pub struct DownloadHandlers {
+    /// Called with the updated VOD once the download process has been started.
+    pub on_start: Arc<dyn Fn(&Vod) + Send + Sync>,
+    /// Called with the VOD's queue ID and the latest progress of the download.
+    pub on_progress: Arc<dyn Fn(&str, JSON) + Send + Sync>,
+    /// Called with the VOD's queue ID and the message when the download process prints a warning.
+    pub on_warning: Arc<dyn Fn(&str, &str) + Send + Sync>,
+    /// Called with the VOD's queue ID when the download completes.
+    pub on_completion: Arc<dyn Fn(&str) + Send + Sync>,
+    /// Called with the VOD's queue ID and the error when the download fails.
+    pub on_fail: Arc<dyn Fn(&str, anyhow::Error) + Send + Sync>,
+}
+
+/// An error from a failed download attempt, along with the class of the failure.
+#[derive(Debug)]
+pub struct DownloadError {
+    pub class: FailureClass,
+    msg: String,
+}
+
+impl Display for DownloadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.msg)
+    }
+}
+
+impl Error for DownloadError {}
+
+/// Holds the handle to an active download task, along with the means to stop it.
+struct DownloadTask {
+    handle: JoinHandle<()>,
+    /// Taken once the download phase is over, since the post-download phases can't be stopped.
+    stop_sender: Option<oneshot::Sender<()>>,
+}
+
+/// Formats and tracks available in the HLS stream of a VOD.
+#[derive(Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VodFormats {
+    /// Every format of the stream, as reported by `yt-dlp`.
+    pub formats: JSON,
+    pub audio_tracks: Vec<AudioTrack>,
+    pub subtitles: Vec<SubtitleTrack>,
+}
+
+/// An audio-only format in the HLS stream of a VOD, like an alternate commentary language.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioTrack {
+    pub format_id: String,
+    /// Language code of the track. Empty if the stream doesn't specify it.
+    pub language: String,
+    pub acodec: String,
+    /// Average bitrate in KBit/s.
+    pub tbr: Option<f64>,
+}
+
+/// A subtitle track in the HLS stream of a VOD.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SubtitleTrack {
+    pub language: String,
+    pub name: String,
+    pub ext: String,
+}
+
 /// Holds all metadata for each helper media tool.
 pub struct MediaTools {
     ffmpeg: MediaToolMeta,
@@ -63,6 +147,62 @@ impl MediaToolMeta {
     }
 }
 
+// Enums
+/// Classifies why a download attempt failed, so the retry policy can decide if it's retryable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailureClass {
+    /// The download stream broke or was rejected.
+    Stream,
+    /// A network request failed.
+    Network,
+    /// The download process exited with an error or was killed.
+    Process,
+    /// The download process didn't produce a valid output file.
+    Output,
+    /// The requested format is not available.
+    Format,
+    /// The downloads location is invalid.
+    Location,
+    /// The output file didn't pass the verification.
+    Verification,
+    Unknown,
+}
+
+impl FailureClass {
+    /// Failure classes that can be retried. Verification only runs after the download attempts,
+    /// so its failures are never retried.
+    pub const RETRYABLE: [FailureClass; 7] = [
+        FailureClass::Stream,
+        FailureClass::Network,
+        FailureClass::Process,
+        FailureClass::Output,
+        FailureClass::Format,
+        FailureClass::Location,
+        FailureClass::Unknown,
+    ];
+
+    /// Returns the name of the failure class, as used in the retry policy configuration.
+    pub fn name(self) -> &'static str {
+        match self {
+            FailureClass::Stream => "stream",
+            FailureClass::Network => "network",
+            FailureClass::Process => "process",
+            FailureClass::Output => "output",
+            FailureClass::Format => "format",
+            FailureClass::Location => "location",
+            FailureClass::Verification => "verification",
+            FailureClass::Unknown => "unknown",
+        }
+    }
+}
+
+/// Describes how a download process ended.
+enum DownloadOutcome {
+    /// The download finished successfully and produced the output file at the given path.
+    Finished(PathBuf),
+    Stopped,
+}
+
 // Statics
 pub static BINS: MediaTools = MediaTools {
     ffmpeg: MediaToolMeta {
@@ -92,6 +232,14 @@ static DL_PROGRESS_TEMPLATE: Lazy<String> = Lazy::new(generate_yt_dlp_progress_t
 /// Holds the global yt-dlp download task handles.
 static DL_TASKS: Lazy<Arc<Mutex<TaskMap>>> = Lazy::new(|| Arc::new(Mutex::new(HashMap::new())));
 
+/// Creates a new `DownloadError` with the given class and message.
+fn download_error(class: FailureClass, msg: impl Into<String>) -> anyhow::Error {
+    anyhow::Error::new(DownloadError {
+        class,
+        msg: msg.into(),
+    })
+}
+
 /// Validates if the media tools exist and returns the validation for each binary as JSON.
 pub fn validate_bins() -> JSON {
     json!({
@@ -103,20 +251,23 @@ pub fn validate_bins() -> JSON {
 
 #[allow(clippy::too_many_lines)]
 /// Starts a download process using `yt-dlp` and updates the downloads-queue with progress.
-pub async fn start_download<P, C, F>(
+/// Returns `None` if the download was cancelled while it was being started.
+pub async fn start_download(
     vod: &Vod,
     is_restart: bool,
-    on_progress: P,
-    on_completion: C,
-    on_fail: F,
-) -> anyhow::Result<Vod>
-where
-    P: Fn(&str, JSON) + Send + 'static,
-    C: FnOnce(&str) + Send + 'static,
-    F: Fn(&str, anyhow::Error) + Send + 'static,
-{
+    handlers: DownloadHandlers,
+) -> anyhow::Result<Option<Vod>> {
     let config = get_config();
+    // Downloads that failed or were interrupted before their output path was rendered have no
+    // partially downloaded files to continue from
+    let is_restart = is_restart && !vod.out_base.is_empty();
+
+    if !is_restart {
+        check_duplicate_download(vod, &config.duplicate_downloads, &handlers).await?;
+    }
+
     let (final_title, dl_config) = generate_vod_download_config(config.as_ref(), vod, is_restart)?;
+    let dl_format = get_download_format(config.as_ref(), vod);
 
     println!(
         "\n{}",
@@ -143,106 +294,190 @@ where
         inc_file_number().await;
     };
 
+    let (stop_sender, mut stop_receiver) = oneshot::channel::<()>();
+    let (start_sender, start_receiver) = oneshot::channel::<()>();
+
     let download_process = {
         // Need these clones because it's not possible to clone values into a closure
         // Ref: https://github.com/rust-lang/rfcs/issues/2407
-        let q_id = vod.q_id.clone();
+        let dl_handlers = handlers.clone();
+        let dl_dir = PathBuf::from(&config.dl_path);
+        let embed_metadata = config.embed_metadata;
+        // Retries need to continue writing to the same output file
+        let dl_vod = Vod {
+            out_base: final_title.clone(),
+            ..vod.clone()
+        };
 
         async move {
-            let mut yt_dlp = start_yt_dlp_process(dl_config).context(
-                "Download failed: \
-                An error occurred while trying to launch the download process. \
-                Make sure that all of the media-tools are available in the \"bin\" directory",
-            )?;
-
-            let mut yt_dlp_stderr = BufReader::new(
-                yt_dlp
-                    .stderr
-                    .take()
-                    .context("Failed to capture the error output from download process")?,
-            )
-            .lines();
-
-            let mut yt_dlp_stdout = BufReader::new(
-                yt_dlp
-                    .stdout
-                    .take()
-                    .context("Failed to capture the output from download process")?,
+            let outcome = run_download_with_retries(
+                dl_config,
+                &dl_vod,
+                &dl_dir,
+                &dl_handlers,
+                &mut stop_receiver,
             )
-            .lines();
-
-            let stderr_task = async {
-                if let Some(line) = yt_dlp_stderr.next_line().await? {
-                    if let Some(error) = process_yt_dlp_stderr(&line) {
-                        return Err(anyhow!(error.to_string()));
-                    }
+            .await;
 
-                    return Err(anyhow!(line).context(
-                        "Download process failed with an error. \
-                        Check the browser console for more information",
-                    ));
-                }
+            // A download that was stopped right as it ended is left to whatever stopped it
+            if !matches!(outcome, Ok(DownloadOutcome::Stopped)) && !end_download_phase(&dl_vod.q_id)
+            {
+                return Ok(DownloadOutcome::Stopped);
+            }
 
-                Ok::<(), anyhow::Error>(())
-            };
+            let outcome = outcome?;
 
-            let stdout_task = async move {
-                let mut last = Instant::now();
+            // A failed embedding leaves the downloaded file as it is, so it doesn't fail the download
+            if let (true, DownloadOutcome::Finished(output)) = (embed_metadata, &outcome) {
+                (dl_handlers.on_progress)(&dl_vod.q_id, json!({ "task": "meta" }));
 
-                while let Some(line) = yt_dlp_stdout
-                    .next_line()
-                    .await
-                    .unwrap_or(Some(String::new()))
-                {
-                    if last.elapsed().as_millis() > 500 {
-                        last = Instant::now();
-                        on_progress(&q_id, process_yt_dlp_stdout(&line));
-                    }
+                if let Err(error) = embed_cover_and_chapters(&dl_vod, output).await {
+                    log_warn!(
+                        "Failed to embed the cover art and the chapters into the download:\n{error}\n"
+                    );
                 }
+            }
 
-                Ok::<(), anyhow::Error>(())
-            };
-
-            tokio::try_join!(stderr_task, stdout_task)
+            Ok(outcome)
         }
     };
 
     let dl_process = tokio::spawn({
         let q_id = vod.q_id.clone();
         let final_title = final_title.clone();
+        let history_vod = vod.clone();
+        let DownloadHandlers {
+            on_progress,
+            on_warning,
+            on_completion,
+            on_fail,
+            ..
+        } = handlers;
 
         async move {
+            // The download only starts once the VOD is in the downloads-queue and the task is
+            // registered, so the initial status can't overwrite the result of a quick failure
+            if start_receiver.await.is_err() {
+                return;
+            }
+
             let err_msg = "Unable to update the status of the VOD";
 
-            if let Err(error) = download_process.await {
-                if let Err(inner_error) = update_dlq_vod_status(&q_id, "failed") {
-                    log_err!("{err_msg}:\n{inner_error}\n");
-                }
+            match download_process.await {
+                Err(error) => {
+                    if let Err(inner_error) = update_dlq_vod_status(&q_id, "failed") {
+                        log_err!("{err_msg}:\n{inner_error}\n");
+                    }
 
-                println!(
-                    "\n{}\n",
-                    format!("Download failed - {final_title}")
-                        .bright_red()
-                        .bold()
-                        .on_black()
-                );
+                    println!(
+                        "\n{}\n",
+                        format!("Download failed - {final_title}")
+                            .bright_red()
+                            .bold()
+                            .on_black()
+                    );
 
-                on_fail(&q_id, error);
-            } else {
-                // TODO: Might need to check the exit code of yt-dlp here
-                if let Err(error) = update_dlq_vod_status(&q_id, "completed") {
-                    log_err!("{err_msg}:\n{error}\n");
+                    on_fail(&q_id, error);
                 }
+                Ok(DownloadOutcome::Finished(mut output)) => {
+                    let (verify_downloads, verify_tolerance, post_processing) = {
+                        let config = get_config();
+
+                        (
+                            config.verify_downloads,
+                            config.verify_tolerance,
+                            config.post_processing.clone(),
+                        )
+                    };
+                    let verification = if verify_downloads {
+                        on_progress(&q_id, json!({ "task": "verify" }));
+
+                        Some(
+                            verify_download(&history_vod, &dl_format, &output, verify_tolerance)
+                                .await,
+                        )
+                    } else {
+                        None
+                    };
+                    let issues = verification
+                        .as_ref()
+                        .map(|verification| verification.issues.clone())
+                        .unwrap_or_default();
+
+                    // Only verified downloads are post-processed, since the steps can change the
+                    // duration and the resolution that the verification checks
+                    if issues.is_empty() {
+                        if let Err(error) = run_post_processing(
+                            &history_vod,
+                            &mut output,
+                            &post_processing,
+                            |update| on_progress(&q_id, update),
+                        )
+                        .await
+                        {
+                            log_warn!("{error}\n");
+                            on_warning(&q_id, &error.to_string());
+                        }
+                    }
 
-                println!(
-                    "\n{}\n",
-                    format!("Completed download - {final_title}")
-                        .bright_green()
-                        .bold()
-                        .on_black()
-                );
+                    if let Err(error) = update_dlq_vod(&q_id, |vod| {
+                        vod.status = if issues.is_empty() {
+                            "completed"
+                        } else {
+                            "verificationFailed"
+                        }
+                        .to_string();
+                        vod.out_path = output.to_string_lossy().to_string();
+                        vod.verification.clone_from(&verification);
+                    }) {
+                        log_err!("{err_msg}:\n{error}\n");
+                    }
 
-                on_completion(&q_id);
+                    if !issues.is_empty() {
+                        println!(
+                            "\n{}\n",
+                            format!("Verification failed - {final_title}")
+                                .bright_red()
+                                .bold()
+                                .on_black()
+                        );
+
+                        on_fail(
+                            &q_id,
+                            download_error(
+                                FailureClass::Verification,
+                                format!("Download verification failed: {}", issues.join(". ")),
+                            ),
+                        );
+                    } else {
+                        let entry = create_history_entry(&history_vod, &dl_format, &output).await;
+
+                        if let Err(error) = record_download(entry).await {
+                            log_err!("Failed to add the download to the history:\n{error}\n");
+                        }
+
+                        if get_config().library_mode {
+                            if let Err(error) = write_library_files(&history_vod, &output).await {
+                                log_warn!(
+                                    "Failed to write the library files of the download:\n{error}\n"
+                                );
+                            }
+                        }
+
+                        println!(
+                            "\n{}\n",
+                            format!("Completed download - {final_title}")
+                                .bright_green()
+                                .bold()
+                                .on_black()
+                        );
+
+                        on_completion(&q_id);
+                    }
+                }
+                // The download task has already been removed by whatever stopped the download,
+                // and it's responsible for the state of the VOD from here on.
+                Ok(DownloadOutcome::Stopped) => return,
             }
 
             if let Err(error) = remove_dl_task(&q_id) {
@@ -251,10 +486,28 @@ where
         }
     });
 
+    // The task is registered before the download becomes active, so a cancel request always finds
+    // either a queued download or a download task to stop
+    add_dl_task(
+        &vod.q_id,
+        DownloadTask {
+            handle: dl_process,
+            stop_sender: Some(stop_sender),
+        },
+    );
+
+    // A download that was cancelled while it was being started is dropped along with its task
+    if !replace_dlq_vod_status(&vod.q_id, "queued", "downloading") {
+        remove_dl_task(&vod.q_id)?;
+
+        return Ok(None);
+    }
+
     let queued_vod = add_vod_to_queue(Vod {
-        title: final_title,
+        out_base: final_title,
         task: "prepare".to_string(),
         status: "downloading".to_string(),
+        attempt: 1,
         progress: 0.0,
         size: "N/A".to_string(),
         speed: "N/A".to_string(),
@@ -262,17 +515,372 @@ where
         ..vod.clone()
     });
 
-    add_dl_task(&vod.q_id, dl_process);
+    start_sender.send(()).ok();
+
+    Ok(Some(queued_vod))
+}
+
+/// Checks the download history for an earlier download of a VOD, and warns about it or refuses
+/// the download according to the duplicate downloads setting (allow, warn, refuse).
+async fn check_duplicate_download(
+    vod: &Vod,
+    policy: &str,
+    handlers: &DownloadHandlers,
+) -> anyhow::Result<()> {
+    if policy == "allow" {
+        return Ok(());
+    }
+
+    let Some(entry) = find_downloaded_vod(vod.id).await? else {
+        return Ok(());
+    };
+
+    let msg = format!(
+        "This video was already downloaded to \"{}\". \
+        Remove it from the download history to download it again",
+        entry.out_path
+    );
+
+    if policy == "refuse" {
+        return Err(anyhow!(msg));
+    }
+
+    (handlers.on_warning)(&vod.q_id, &msg);
+
+    Ok(())
+}
+
+/// Runs a download using `yt-dlp`, and retries failed attempts according to the retry policy in
+/// the configuration. Each retry continues the download using a fresh stream URL.
+async fn run_download_with_retries(
+    mut dl_args: Vec<String>,
+    vod: &Vod,
+    dl_dir: &Path,
+    handlers: &DownloadHandlers,
+    stop_receiver: &mut oneshot::Receiver<()>,
+) -> anyhow::Result<DownloadOutcome> {
+    let mut attempt = 1;
+
+    loop {
+        let error = match run_yt_dlp_download(
+            &dl_args,
+            &vod.q_id,
+            &vod.out_base,
+            dl_dir,
+            handlers,
+            stop_receiver,
+        )
+        .await
+        {
+            Err(error) => error,
+            outcome => return outcome,
+        };
+
+        let policy = get_config().retry_policy.clone();
+
+        if !should_retry_download(&policy, attempt, &error) {
+            return Err(error);
+        }
+
+        let delay = get_retry_delay(&policy, attempt);
+
+        attempt += 1;
+
+        log_warn!(
+            "{error}\nRetrying \"{}\" in {} second(s) (attempt {attempt} of {})..\n",
+            vod.title,
+            delay.as_secs(),
+            policy.max_attempts
+        );
+
+        (handlers.on_progress)(
+            &vod.q_id,
+            json!({
+                "task": "retry",
+                "attempt": attempt,
+                "maxAttempts": policy.max_attempts
+            }),
+        );
+
+        if let Err(error) = update_dlq_vod(&vod.q_id, |queued| queued.attempt = attempt) {
+            log_err!("Unable to update the download attempt of the VOD:\n{error}\n");
+        }
+
+        tokio::select! {
+            () = sleep(delay) => {}
+            _ = &mut *stop_receiver => return Ok(DownloadOutcome::Stopped),
+        }
+
+        // The signed stream URLs expire, so every attempt needs a fresh one
+        let hls = get_vod_stream_url(vod.id, &vod.account)
+            .await
+            .context("Failed to retry the download")?;
+
+        let retry_vod = Vod { hls, ..vod.clone() };
+
+        (_, dl_args) = generate_vod_download_config(get_config().as_ref(), &retry_vod, true)?;
+    }
+}
+
+/// Validates the failure classes of a retry policy.
+pub fn validate_retry_policy(policy: &RetryPolicy) -> anyhow::Result<()> {
+    let names = FailureClass::RETRYABLE.map(FailureClass::name);
+
+    match policy
+        .retry_on
+        .iter()
+        .find(|name| !names.contains(&name.as_str()))
+    {
+        Some(name) => Err(anyhow!(
+            "\"{name}\" failures can't be retried. Retryable failures are: {}",
+            names.join(", ")
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Checks that a configuration sent by a client doesn't add new `yt-dlp` arguments while the GUI
+/// authentication is disabled. Options like `--exec` run shell commands, so the arguments are
+/// protected the same way as the hook steps. Existing arguments can still be kept or removed.
+pub fn validate_dl_arg_changes(
+    dl_args: &[String],
+    current_dl_args: &[String],
+    is_auth_enabled: bool,
+) -> anyhow::Result<()> {
+    if is_auth_enabled || dl_args.iter().all(|arg| current_dl_args.contains(arg)) {
+        return Ok(());
+    }
+
+    Err(anyhow!(
+        r#"yt-dlp arguments can only be added when the GUI authentication is enabled. Enable it, or add the arguments to the "config.json" file"#
+    ))
+}
+
+/// Checks if a failed download attempt should be retried, according to the retry policy.
+fn should_retry_download(policy: &RetryPolicy, attempt: u32, error: &anyhow::Error) -> bool {
+    let class = error
+        .downcast_ref::<DownloadError>()
+        .map_or(FailureClass::Unknown, |dl_error| dl_error.class);
+
+    policy.enabled
+        && attempt < policy.max_attempts
+        && policy.retry_on.iter().any(|name| name == class.name())
+}
+
+/// Returns the delay before the next attempt of a download, which doubles with each attempt.
+fn get_retry_delay(policy: &RetryPolicy, attempt: u32) -> Duration {
+    let delay = policy
+        .base_delay
+        .saturating_mul(2u64.saturating_pow(attempt.saturating_sub(1)))
+        .min(policy.max_delay);
 
-    Ok(queued_vod)
+    Duration::from_secs(delay)
 }
 
-/// Starts a format query process using `yt-dlp` and returns the available formats as JSON.
-pub async fn get_vod_formats(hls: &str) -> anyhow::Result<JSON> {
+/// Runs a single `yt-dlp` download process till it exits or gets stopped.
+async fn run_yt_dlp_download(
+    dl_args: &[String],
+    q_id: &str,
+    out_base: &str,
+    dl_dir: &Path,
+    handlers: &DownloadHandlers,
+    stop_receiver: &mut oneshot::Receiver<()>,
+) -> anyhow::Result<DownloadOutcome> {
+    let mut yt_dlp = start_yt_dlp_process(dl_args).context(
+        "Download failed: \
+        An error occurred while trying to launch the download process. \
+        Make sure that all of the media-tools are available in the \"bin\" directory",
+    )?;
+
+    let outcome = tokio::select! {
+        result = monitor_yt_dlp_download(&mut yt_dlp, q_id, handlers) => result.map(|()| None),
+        _ = stop_receiver => Ok(Some(DownloadOutcome::Stopped)),
+    };
+
+    match outcome {
+        Ok(None) => {
+            let exit_status = yt_dlp
+                .wait()
+                .await
+                .context("Failed to get the exit status of the download process")?;
+
+            check_yt_dlp_exit_status(exit_status)?;
+
+            let output = verify_download_output(dl_dir, out_base).await?;
+
+            Ok(DownloadOutcome::Finished(output))
+        }
+        // yt-dlp spawns ffmpeg processes of its own, which would keep running and writing to the
+        // disk if only yt-dlp was killed.
+        Ok(Some(stopped)) => {
+            kill_process_tree(&mut yt_dlp).await;
+            Ok(stopped)
+        }
+        Err(error) => {
+            kill_process_tree(&mut yt_dlp).await;
+            Err(error)
+        }
+    }
+}
+
+/// Checks the exit status of a finished `yt-dlp` process, and returns an error describing why the
+/// process failed if it didn't exit successfully.
+fn check_yt_dlp_exit_status(exit_status: ExitStatus) -> anyhow::Result<()> {
+    if exit_status.success() {
+        return Ok(());
+    }
+
+    if let Some(code) = exit_status.code() {
+        return Err(download_error(
+            FailureClass::Process,
+            format!(
+                "Download failed: The download process exited with an error (exit code {code}). \
+                Check the browser console for more information"
+            ),
+        ));
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        if let Some(signal) = exit_status.signal() {
+            return Err(download_error(
+                FailureClass::Process,
+                if signal == libc::SIGKILL {
+                    format!(
+                        "Download failed: The download process was killed by the system (signal \
+                        {signal}). This usually happens when the system runs out of memory. Try \
+                        reducing the number of concurrent fragments or parallel downloads"
+                    )
+                } else {
+                    format!(
+                        "Download failed: The download process was terminated by signal {signal}"
+                    )
+                },
+            ));
+        }
+    }
+
+    Err(download_error(
+        FailureClass::Process,
+        format!("Download failed: The download process exited unexpectedly ({exit_status})"),
+    ))
+}
+
+/// Verifies that a finished download produced a non-empty output file, and returns its path.
+async fn verify_download_output(dl_dir: &Path, out_base: &str) -> anyhow::Result<PathBuf> {
+    let output = find_download_output(dl_dir, out_base, &get_config().merge_ext)
+        .await
+        .context("Download failed: Unable to read the downloads directory to verify the output")?
+        .ok_or_else(|| {
+            download_error(
+                FailureClass::Output,
+                "Download failed: The download process finished, but the output file is missing \
+                from the downloads directory",
+            )
+        })?;
+
+    let size = fs::metadata(&output)
+        .await
+        .context("Download failed: Unable to read the output file to verify the download")?
+        .len();
+
+    if size == 0 {
+        return Err(download_error(
+            FailureClass::Output,
+            "Download failed: The download process finished, but the output file is empty",
+        ));
+    }
+
+    Ok(output)
+}
+
+/// Monitors the output of a `yt-dlp` download process till it exits, and reports the download
+/// progress and warnings using the given handlers. Only the fatal errors printed by the process
+/// fail the download.
+async fn monitor_yt_dlp_download(
+    yt_dlp: &mut Child,
+    q_id: &str,
+    handlers: &DownloadHandlers,
+) -> anyhow::Result<()> {
+    let mut yt_dlp_stderr = BufReader::new(
+        yt_dlp
+            .stderr
+            .take()
+            .context("Failed to capture the error output from download process")?,
+    )
+    .lines();
+
+    let mut yt_dlp_stdout = BufReader::new(
+        yt_dlp
+            .stdout
+            .take()
+            .context("Failed to capture the output from download process")?,
+    )
+    .lines();
+
+    let stderr_task = async {
+        let mut fatal_error = None;
+
+        while let Some(line) = yt_dlp_stderr.next_line().await? {
+            match process_yt_dlp_stderr(&line) {
+                YtDlpStderr::Warning(warning) => (handlers.on_warning)(q_id, &warning),
+                YtDlpStderr::Error(error, class) => {
+                    // Only the first error is relevant since the rest are usually caused by it
+                    if fatal_error.is_none() {
+                        fatal_error = Some(if class == FailureClass::Unknown {
+                            download_error(class, error).context(
+                                "Download process failed with an error. \
+                                Check the browser console for more information",
+                            )
+                        } else {
+                            download_error(class, error)
+                        });
+                    }
+                }
+                YtDlpStderr::Info(info) => {
+                    if is_debug() {
+                        println!("[yt-dlp] {info}");
+                    }
+                }
+            }
+        }
+
+        fatal_error.map_or(Ok(()), Err)
+    };
+
+    let stdout_task = async {
+        let mut last = Instant::now();
+
+        while let Some(line) = yt_dlp_stdout
+            .next_line()
+            .await
+            .unwrap_or(Some(String::new()))
+        {
+            if last.elapsed().as_millis() > 500 {
+                last = Instant::now();
+                (handlers.on_progress)(q_id, process_yt_dlp_stdout(&line));
+            }
+        }
+
+        Ok::<(), anyhow::Error>(())
+    };
+
+    tokio::try_join!(stderr_task, stdout_task)?;
+
+    Ok(())
+}
+
+/// Starts a format query process using `yt-dlp` and returns the available formats, along with the
+/// audio and subtitle tracks of the stream. Warnings of `yt-dlp` are passed to `on_warning`.
+pub async fn get_vod_formats(hls: &str, on_warning: impl Fn(&str)) -> anyhow::Result<VodFormats> {
     let yt_dlp_args = vec![
         "--no-update",
         "--print",
-        "%(formats.:.{format_id,resolution,fps,tbr,vcodec,acodec})j",
+        "%(formats.:.{format_id,resolution,fps,tbr,vcodec,acodec,language})j",
+        "--print",
+        "%(subtitles)j",
         hls,
     ];
 
@@ -299,47 +907,72 @@ pub async fn get_vod_formats(hls: &str) -> anyhow::Result<JSON> {
     .lines();
 
     let stderr_task = async {
-        if let Some(line) = yt_dlp_stderr.next_line().await? {
-            if let Some(error) = process_yt_dlp_stderr(&line) {
-                return Err(anyhow!(error.to_string()));
+        let mut fatal_error = None;
+
+        while let Some(line) = yt_dlp_stderr.next_line().await? {
+            match process_yt_dlp_stderr(&line) {
+                YtDlpStderr::Warning(warning) => on_warning(&warning),
+                YtDlpStderr::Error(error, class) => {
+                    if fatal_error.is_none() {
+                        fatal_error = Some(if class == FailureClass::Unknown {
+                            download_error(class, error).context(
+                                "Formats query request failed with an error. \
+                                Check the browser console for more information",
+                            )
+                        } else {
+                            download_error(class, error)
+                        });
+                    }
+                }
+                YtDlpStderr::Info(info) => {
+                    if is_debug() {
+                        println!("[yt-dlp] {info}");
+                    }
+                }
             }
-
-            return Err(anyhow!(line).context(
-                "Formats query request failed with an error. \
-                Check the browser console for more information",
-            ));
         }
 
-        Ok::<(), anyhow::Error>(())
+        fatal_error.map_or(Ok(()), Err)
     };
 
     let stdout_task = async move {
         let mut formats = String::new();
+        let mut subtitles = String::new();
 
+        // The formats are printed as a JSON array, and the subtitles as a JSON object
         while let Some(line) = yt_dlp_stdout.next_line().await? {
-            formats = line;
+            if line.starts_with('[') {
+                formats = line;
+            } else {
+                subtitles = line;
+            }
         }
 
-        Ok::<String, anyhow::Error>(formats)
+        Ok::<(String, String), anyhow::Error>((formats, subtitles))
     };
 
-    match tokio::try_join!(stderr_task, stdout_task) {
-        Ok(((), formats)) => {
-            let err_msg = "Format output is invalid. Please try again or \
-                check the video on Fight Pass to verify that it actually streams";
-
-            if let Ok(formats_json) = serde_json::from_str::<JSON>(&formats) {
-                if formats_json.is_array() {
-                    Ok(formats_json)
-                } else {
-                    Err(anyhow!(err_msg))
-                }
-            } else {
-                Err(anyhow!(err_msg))
-            }
-        }
-        Err(error) => Err(error),
+    let (stderr_result, stdout_result) = tokio::join!(stderr_task, stdout_task);
+    let exit_status = yt_dlp
+        .wait()
+        .await
+        .context("Failed to get the exit status of the formats query process")?;
+
+    // The error reported by yt-dlp explains a failed query better than its exit status or output
+    stderr_result?;
+
+    if !exit_status.success() {
+        return Err(download_error(
+            FailureClass::Process,
+            format!(
+                "Formats query failed: The formats query process exited unexpectedly \
+                ({exit_status}). Check the browser console for more information"
+            ),
+        ));
     }
+
+    let (formats, subtitles) = stdout_result?;
+
+    parse_yt_dlp_formats(&formats, &subtitles)
 }
 
 /// Starts a new `yt-dlp` process and returns the `Child` handle to it.
@@ -348,17 +981,64 @@ where
     I: IntoIterator<Item = S>,
     S: AsRef<OsStr>,
 {
-    Command::new(BINS.yt_dlp.get_path())
+    let mut command = StdCommand::new(BINS.yt_dlp.get_path());
+
+    command
         .args(args)
         .stderr(Stdio::piped())
         .stdout(Stdio::piped())
-        .stdin(Stdio::null())
-        .spawn()
+        .stdin(Stdio::null());
+
+    // Runs yt-dlp as the leader of a new process group, so the processes spawned by yt-dlp can be
+    // killed along with it.
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+
+    Command::from(command).kill_on_drop(true).spawn()
+}
+
+/// Kills a process along with all of its child processes, and waits till it exits.
+async fn kill_process_tree(process: &mut Child) {
+    if let Some(pid) = process.id() {
+        #[cfg(unix)]
+        {
+            if let Ok(pgid) = libc::pid_t::try_from(pid) {
+                // SAFETY: `killpg` only sends a signal to the process group led by the given
+                // process, and doesn't touch any memory.
+                unsafe {
+                    libc::killpg(pgid, libc::SIGKILL);
+                }
+            }
+        }
+
+        #[cfg(windows)]
+        {
+            Command::new("taskkill")
+                .args(["/PID", &pid.to_string(), "/T", "/F"])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .await
+                .ok();
+        }
+    }
+
+    process.start_kill().ok();
+    process.wait().await.ok();
 }
 
-/// Cancels an active download.
-pub fn cancel_download(vod: &Vod) -> anyhow::Result<()> {
-    remove_dl_task(&vod.q_id)?.abort();
+/// Cancels an active, paused or interrupted download by stopping its processes if they're running,
+/// and removes the partially downloaded files. Returns the number of files removed.
+pub async fn cancel_download(vod: &Vod) -> anyhow::Result<usize> {
+    // Paused and interrupted downloads don't have a download task to stop
+    let is_stopped = get_dlq()
+        .get(&vod.q_id)
+        .is_some_and(|queued| queued.status == "paused" || queued.status == "interrupted");
+
+    if !is_stopped {
+        stop_download(&vod.q_id).await?;
+    }
+
     update_dlq_vod_status(&vod.q_id, "cancelled")?;
 
     println!(
@@ -369,9 +1049,158 @@ pub fn cancel_download(vod: &Vod) -> anyhow::Result<()> {
             .on_black()
     );
 
+    // Only the downloads-queue has the output path, and downloads that never started have none
+    let out_base = get_dlq()
+        .get(&vod.q_id)
+        .map(|queued| queued.out_base.clone())
+        .unwrap_or_default();
+
+    if out_base.is_empty() {
+        return Ok(0);
+    }
+
+    let dl_dirs = get_download_dirs();
+    let removed = remove_partial_downloads(&dl_dirs, &out_base)
+        .await
+        .context(
+            "The download was cancelled but failed to remove the partially downloaded files",
+        )?;
+
+    if is_debug() {
+        println!("Removed {removed} partially downloaded file(s) of \"{out_base}\"\n");
+    }
+
+    Ok(removed)
+}
+
+/// Pauses an active download by stopping its processes, while keeping the partially downloaded
+/// files, so the download can be continued later.
+pub async fn pause_download(vod: &Vod) -> anyhow::Result<()> {
+    stop_download(&vod.q_id).await?;
+    update_dlq_vod_status(&vod.q_id, "paused")?;
+
+    println!(
+        "\n{}\n",
+        format!("Download paused by user - {}", vod.title)
+            .bright_yellow()
+            .bold()
+            .on_black()
+    );
+
+    Ok(())
+}
+
+/// Stops an active download process along with all of its child processes, and waits till the
+/// download task exits. Downloads that are past the download phase (embedding, verification or
+/// post-processing) can't be stopped.
+async fn stop_download(q_id: &str) -> anyhow::Result<()> {
+    let (handle, stop_sender) = {
+        let mut dl_tasks = get_dl_tasks();
+        let stop_sender = dl_tasks
+            .get_mut(q_id)
+            .context("The download task is not actively available anymore")?
+            .stop_sender
+            .take()
+            .context("The download has finished and is being processed, so it can't be stopped")?;
+        let task = dl_tasks
+            .remove(q_id)
+            .context("The download task is not actively available anymore")?;
+
+        (task.handle, stop_sender)
+    };
+
+    // Sending fails only if the download task has already finished
+    stop_sender.send(()).ok();
+    handle
+        .await
+        .context("The download task exited unexpectedly while stopping the download")?;
+
     Ok(())
 }
 
+/// Creates the default `yt-dlp` format of the configured video and audio qualities for the given
+/// resolution. The audio tracks of the preferred audio languages are merged when available.
+pub fn create_default_format(config: &UFCRConfig, resolution: &str) -> String {
+    let UFCRConfig {
+        vid_quality,
+        aud_quality,
+        audio_langs,
+        ..
+    } = config;
+
+    let video = format!("{vid_quality}[height={resolution}]");
+    let langs: Vec<&String> = audio_langs
+        .iter()
+        .filter(|lang| {
+            !lang.is_empty()
+                && lang
+                    .chars()
+                    .all(|char| char.is_ascii_alphanumeric() || char == '-')
+        })
+        .collect();
+
+    // Falls back to fewer audio languages, dropping the least preferred ones first, when some of
+    // them aren't available
+    let mut alternatives: Vec<String> = (1..=langs.len())
+        .rev()
+        .map(|count| {
+            langs[..count].iter().fold(video.clone(), |format, lang| {
+                format!("{format}+{aud_quality}[language^={lang}]")
+            })
+        })
+        .collect();
+
+    alternatives.push(format!("{video}+{aud_quality}"));
+    alternatives.push(format!("{vid_quality}*[height={resolution}]"));
+
+    alternatives.join("/")
+}
+
+/// Returns the `yt-dlp` format of a VOD's download. The custom format of the VOD takes priority
+/// over the format ID and the default format of the configuration.
+pub fn get_download_format(config: &UFCRConfig, vod: &Vod) -> String {
+    if !vod.custom_format.is_empty() {
+        vod.custom_format.clone()
+    } else if config.cus_format {
+        config.format_id.clone()
+    } else {
+        create_default_format(config, &config.resolution)
+    }
+}
+
+/// Shows where a VOD would be downloaded to, using the given templates instead of the configured
+/// ones. Returns the path relative to the downloads directory and the full path of the file.
+pub fn preview_output_path(
+    vod: &Vod,
+    file_template: Option<String>,
+    dir_template: Option<String>,
+) -> anyhow::Result<JSON> {
+    let config = get_config();
+    let config = UFCRConfig {
+        file_template: file_template.unwrap_or_else(|| config.file_template.clone()),
+        dir_template: dir_template.unwrap_or_else(|| config.dir_template.clone()),
+        ..config.as_ref().clone()
+    };
+
+    validate_output_templates(&config.file_template, &config.dir_template)?;
+
+    let relative_path = format!(
+        "{}.{}",
+        create_output_path(&config, vod, &get_download_format(&config, vod))?,
+        config.merge_ext
+    );
+    let path = relative_path
+        .split('/')
+        .fold(PathBuf::from(&config.dl_path), |path, component| {
+            path.join(component)
+        });
+
+    Ok(json!({
+        "relativePath": relative_path,
+        "path": path
+    }))
+}
+
 /// Generates all CLI arguments for a `yt-dlp` download according to the configuration and VOD
 /// settings, and returns them as a `Vec<String>`.
 pub fn generate_vod_download_config(
@@ -380,41 +1209,30 @@ pub fn generate_vod_download_config(
     is_restart: bool,
 ) -> anyhow::Result<(String, Vec<String>)> {
     let UFCRConfig {
-        vid_quality,
-        aud_quality,
-        resolution,
         merge_ext,
         dl_path,
         use_temp_path,
         temp_path,
-        number_files,
-        cur_number,
         throttle,
         dl_rate,
         multi_frag,
         concur_frags,
-        cus_format,
-        format_id,
+        embed_metadata,
+        sub_langs,
         dl_args,
         ..
     } = config;
 
-    let Vod {
-        custom_format,
-        title,
-        hls,
-        ..
-    } = vod;
+    let Vod { out_base, hls, .. } = vod;
 
-    let final_title = if is_restart || !*number_files {
-        title.to_string()
+    let dl_format = get_download_format(config, vod);
+    // Restarted downloads continue writing to the output path rendered when they first started
+    let final_title = if is_restart {
+        out_base.to_string()
     } else {
-        format!("{cur_number}. {title}")
+        create_output_path(config, vod, &dl_format)?
     };
 
-    let default_format = format!(
-        "{vid_quality}[height={resolution}]+{aud_quality}/{vid_quality}*[height={resolution}]"
-    );
     let dl_path_buf = PathBuf::from(dl_path);
     let home_path = format!(
         "home:{}",
@@ -429,22 +1247,16 @@ pub fn generate_vod_download_config(
             "Failed to build the given temporary path. Try changing the temporary directory",
         )?
     );
-    let output_template = format!("{final_title}.%(ext)s");
+    // "%" starts an output template field of yt-dlp
+    let output_template = format!("{}.%(ext)s", final_title.replace('%', "%%"));
     let bin_path_buf = get_app_root_dir().join("bin");
     let concur_frags_string = concur_frags.to_string();
+    let sub_langs_string = sub_langs.join(",");
 
     let mut arg_setup = vec![
         "--no-update",
         "--format",
-        if custom_format.is_empty() {
-            if *cus_format {
-                format_id
-            } else {
-                &default_format
-            }
-        } else {
-            custom_format
-        },
+        &dl_format,
         "--merge-output-format",
         merge_ext,
         "--paths",
@@ -473,12 +1285,35 @@ pub fn generate_vod_download_config(
     if *multi_frag {
         arg_setup.extend(["--concurrent-fragments", &concur_frags_string]);
     }
+    if is_restart {
+        // Makes sure that a restarted download picks up from the partially downloaded files
+        arg_setup.push("--continue");
+    }
+    // Formats like "video+audio-en+audio-pt" merge more than one audio track
+    if dl_format
+        .split('/')
+        .any(|alternative| alternative.matches('+').count() > 1)
+    {
+        arg_setup.push("--audio-multistreams");
+    }
+    if !sub_langs.is_empty() {
+        arg_setup.extend([
+            "--write-subs",
+            "--sub-langs",
+            &sub_langs_string,
+            "--embed-subs",
+        ]);
+    }
 
     let mut arg_setup_final = arg_setup
         .iter()
         .map(std::string::ToString::to_string)
         .collect::<Vec<String>>();
 
+    if *embed_metadata {
+        arg_setup_final.extend(create_metadata_args(vod));
+    }
+
     arg_setup_final.extend(dl_args.clone());
     arg_setup_final.push(hls.to_string());
 
@@ -515,14 +1350,150 @@ fn get_dl_tasks() -> MutexGuard<'static, TaskMap> {
         .unwrap_or_quit("Failed to exclusively access the download tasks")
 }
 
-/// Adds a new task handle to the download tasks.
-fn add_dl_task(q_id: &str, task: JoinHandle<()>) {
+/// Adds a new task to the download tasks.
+fn add_dl_task(q_id: &str, task: DownloadTask) {
     get_dl_tasks().insert(q_id.to_string(), task);
 }
 
-/// Removes and returns a task handle from the download tasks.
-fn remove_dl_task(q_id: &str) -> anyhow::Result<JoinHandle<()>> {
+/// Ends the download phase of a download task, after which the download can't be stopped anymore.
+/// Returns `false` if the download is already being stopped.
+fn end_download_phase(q_id: &str) -> bool {
+    get_dl_tasks()
+        .get_mut(q_id)
+        .is_some_and(|task| task.stop_sender.take().is_some())
+}
+
+/// Removes and returns a task from the download tasks.
+fn remove_dl_task(q_id: &str) -> anyhow::Result<DownloadTask> {
     get_dl_tasks()
         .remove(q_id)
         .context("The download task is not actively available anymore")
 }
+
+/*************
+ *   Tests   *
+ *************/
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use anyhow::anyhow;
+
+    use crate::{
+        config_util::{RetryPolicy, UFCRConfig},
+        state_util::Vod,
+    };
+
+    use super::{
+        download_error, generate_vod_download_config, get_retry_delay, should_retry_download,
+        validate_dl_arg_changes, validate_retry_policy, FailureClass,
+    };
+
+    #[test]
+    fn unit_retry_policy() {
+        let policy = RetryPolicy::default();
+        let stream_error = download_error(FailureClass::Stream, "Stream broke");
+
+        assert!(should_retry_download(&policy, 1, &stream_error));
+        assert!(should_retry_download(&policy, 2, &stream_error));
+        assert!(!should_retry_download(&policy, 3, &stream_error));
+        assert!(!should_retry_download(
+            &policy,
+            1,
+            &download_error(FailureClass::Format, "Format not available")
+        ));
+        assert!(!should_retry_download(
+            &RetryPolicy {
+                enabled: false,
+                ..RetryPolicy::default()
+            },
+            1,
+            &stream_error
+        ));
+
+        // Errors that weren't classified are "unknown" failures
+        assert!(should_retry_download(
+            &RetryPolicy {
+                retry_on: vec!["unknown".into()],
+                ..RetryPolicy::default()
+            },
+            1,
+            &anyhow!("Something went wrong")
+        ));
+
+        assert!(validate_retry_policy(&policy).is_ok());
+        assert!(validate_retry_policy(&RetryPolicy {
+            retry_on: vec!["verification".into()],
+            ..RetryPolicy::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn unit_get_retry_delay() {
+        let policy = RetryPolicy::default();
+
+        assert_eq!(get_retry_delay(&policy, 1), Duration::from_secs(5));
+        assert_eq!(get_retry_delay(&policy, 2), Duration::from_secs(10));
+        assert_eq!(get_retry_delay(&policy, 4), Duration::from_secs(40));
+        assert_eq!(get_retry_delay(&policy, 5), Duration::from_secs(60));
+        assert_eq!(get_retry_delay(&policy, u32::MAX), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn unit_validate_dl_arg_changes() {
+        let current_args = ["--no-mtime".to_string(), "--retries".into(), "10".into()];
+
+        // Arguments from the config file can be kept or removed, but not added to
+        assert!(validate_dl_arg_changes(&current_args[..2], &current_args, false).is_ok());
+        assert!(validate_dl_arg_changes(
+            &["--exec".into(), "curl example.com".into()],
+            &current_args,
+            false
+        )
+        .is_err());
+
+        assert!(validate_dl_arg_changes(&["--exec".into()], &current_args, true).is_ok());
+    }
+
+    #[test]
+    fn unit_generate_vod_download_config() {
+        let config = UFCRConfig {
+            cur_number: 7,
+            embed_metadata: false,
+            ..UFCRConfig::default()
+        };
+        let vod = Vod {
+            title: "UFC 300: Pereira vs. Hill".into(),
+            hls: "https://example.com/master.m3u8".into(),
+            ..Vod::default()
+        };
+        let get_output = |args: &[String]| {
+            let idx = args.iter().position(|arg| arg == "--output").unwrap();
+
+            args[idx + 1].clone()
+        };
+
+        // New downloads render their output path from the title
+        let (out_base, args) = generate_vod_download_config(&config, &vod, false).unwrap();
+
+        assert_eq!(out_base, "7. UFC 300 - Pereira vs. Hill");
+        assert_eq!(get_output(&args), "7. UFC 300 - Pereira vs. Hill.%(ext)s");
+        assert!(!args.contains(&"--continue".to_string()));
+
+        // Restarted downloads keep their output path, even after the file number changed
+        let vod = Vod {
+            out_base: "Events/5. UFC 300 - Pereira vs. Hill".into(),
+            ..vod
+        };
+        let (out_base, args) = generate_vod_download_config(&config, &vod, true).unwrap();
+
+        assert_eq!(out_base, "Events/5. UFC 300 - Pereira vs. Hill");
+        assert_eq!(
+            get_output(&args),
+            "Events/5. UFC 300 - Pereira vs. Hill.%(ext)s"
+        );
+        assert!(args.contains(&"--continue".to_string()));
+    }
+}
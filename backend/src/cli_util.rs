@@ -0,0 +1,613 @@
+// Libs
+use std::{
+    collections::HashMap,
+    io::{stdin, IsTerminal},
+    net::Ipv4Addr,
+    process::ExitCode,
+    sync::Arc,
+};
+
+use anyhow::{anyhow, Context};
+use clap::{Parser, Subcommand};
+use tokio::{net::TcpStream, sync::mpsc};
+
+use ufcr_libs::{log_err, log_info, log_success, log_warn};
+
+use crate::{
+    auth_util::{create_access_token, hash_secret},
+    bin_util::{get_vod_formats, DownloadHandlers, VodFormats},
+    config_util::{get_config, is_debug, load_config, update_config, AccountProfile, ConfigUpdate},
+    net_util::{
+        get_vod_meta, get_vod_stream_url, login_to_fight_pass, search_vods, JsonTryGet, JSON,
+    },
+    rt_util::set_custom_panic,
+    sched_util::{
+        cancel_scheduled_download, enqueue_download, get_pending_dl_order, init_scheduler,
+        pause_scheduled_download,
+    },
+    state_util::{get_dlq, read_saved_dlq, Vod},
+    txt_util::{create_uuid, create_vod_url, strip_html_tags},
+};
+
+// Types
+type DownloadResult = (String, anyhow::Result<()>);
+
+// Structs
+/// Command-line arguments of UFC Ripper. The GUI server is started when no command is provided.
+#[derive(Parser)]
+#[command(version, about, long_about = None)]
+pub struct CLIArgs {
+    #[command(subcommand)]
+    pub command: Option<CLICommand>,
+}
+
+// Enums
+/// Defines the commands available in the headless CLI mode.
+#[derive(Subcommand)]
+pub enum CLICommand {
+    /// Logs into UFC Fight Pass and saves the login session to the configuration.
+    Login {
+        /// Email address of the Fight Pass account.
+        #[arg(short, long)]
+        email: String,
+        /// Password of the Fight Pass account. Read from the standard input when omitted.
+        #[arg(short, long)]
+        pass: Option<String>,
+        /// Fight Pass region. Defaults to the region in the configuration.
+        #[arg(short, long, value_name = "REGION (dce.ufc, dce.ufcbrazil)")]
+        region: Option<String>,
+        /// Name of the account profile to save the login session to. Defaults to the email
+        /// address.
+        #[arg(short, long)]
+        name: Option<String>,
+    },
+    /// Searches the UFC Fight Pass library for VODs.
+    Search {
+        /// Search query.
+        query: String,
+        /// Page of the search results.
+        #[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+        page: u64,
+    },
+    /// Lists the available formats of a VOD.
+    Formats {
+        /// Fight Pass URL of the VOD.
+        url: String,
+    },
+    /// Downloads one or more VODs using the current configuration.
+    Download {
+        /// Fight Pass URLs of the VODs.
+        #[arg(required = true)]
+        urls: Vec<String>,
+        /// Custom `yt-dlp` format to download, instead of the configured quality.
+        #[arg(short, long)]
+        format: Option<String>,
+        /// Name of the account profile to download with. Defaults to the active account.
+        #[arg(short, long)]
+        account: Option<String>,
+    },
+    /// Lists the VODs in the saved downloads-queue.
+    Queue,
+    /// Manages the authentication of the web GUI, the REST API and the `WebSocket` server.
+    Auth {
+        #[command(subcommand)]
+        action: AuthAction,
+    },
+}
+
+/// Defines the actions of the `auth` command.
+#[derive(Subcommand)]
+pub enum AuthAction {
+    /// Sets the GUI password and enables the authentication.
+    SetPassword {
+        /// New GUI password. Read from the standard input when omitted.
+        #[arg(short, long)]
+        pass: Option<String>,
+    },
+    /// Creates a new access token for the REST API and enables the authentication. Any previous
+    /// access token stops working.
+    CreateToken,
+    /// Disables the authentication.
+    Disable,
+}
+
+/// Defines the exit codes of the headless CLI mode. Exit code `2` is used by the argument parser
+/// for invalid usage.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CLIExitCode {
+    Success = 0,
+    Error = 1,
+    AuthFailed = 3,
+    DownloadFailed = 4,
+    Interrupted = 130,
+}
+
+impl From<CLIExitCode> for ExitCode {
+    fn from(code: CLIExitCode) -> Self {
+        ExitCode::from(code as u8)
+    }
+}
+
+/// Runs a headless CLI command and returns the exit code for the process.
+pub async fn run_cli(command: CLICommand) -> CLIExitCode {
+    load_config().await;
+    set_custom_panic(is_debug());
+
+    let result = match command {
+        CLICommand::Login {
+            email,
+            pass,
+            region,
+            name,
+        } => run_login(&email, pass, region, name).await,
+        CLICommand::Search { query, page } => run_search(&query, page).await,
+        CLICommand::Formats { url } => run_formats(&url).await,
+        CLICommand::Download {
+            urls,
+            format,
+            account,
+        } => run_download(&urls, format, &account.unwrap_or_default()).await,
+        CLICommand::Queue => run_queue().await,
+        CLICommand::Auth { action } => run_auth(action).await,
+    };
+
+    result.unwrap_or_else(|error| {
+        print_error(&error);
+        CLIExitCode::Error
+    })
+}
+
+/// Handles the `login` command.
+async fn run_login(
+    email: &str,
+    pass: Option<String>,
+    region: Option<String>,
+    name: Option<String>,
+) -> anyhow::Result<CLIExitCode> {
+    let region = region.unwrap_or_else(|| get_config().region.clone());
+    let name = name.unwrap_or_else(|| email.to_string());
+    let pass = match pass {
+        Some(pass) => pass,
+        None => read_password()?,
+    };
+
+    match login_to_fight_pass(&region, email, &pass).await {
+        Ok(session) => {
+            update_config(ConfigUpdate::Account(AccountProfile::from_session(
+                name, region, session,
+            )))
+            .await;
+            log_success!("Successfully logged in as \"{email}\"\n");
+
+            Ok(CLIExitCode::Success)
+        }
+        Err(error) => {
+            print_error(&error);
+
+            Ok(CLIExitCode::AuthFailed)
+        }
+    }
+}
+
+/// Handles the `search` command.
+async fn run_search(query: &str, page: u64) -> anyhow::Result<CLIExitCode> {
+    // Search result pages are zero-indexed
+    let result = search_vods(query, page - 1).await?;
+    let hits = result
+        .try_get("hits")
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    let config = get_config();
+
+    if hits.is_empty() {
+        log_warn!("No VODs found for \"{query}\"\n");
+
+        return Ok(CLIExitCode::Success);
+    }
+
+    for hit in &hits {
+        let title = hit
+            .try_get("_highlightResult")
+            .try_get("name")
+            .try_get("value")
+            .as_str()
+            .map_or_else(|| "Untitled".to_string(), strip_html_tags);
+
+        match hit.try_get("id").as_u64() {
+            Some(id) => println!("{title}\n  {}", create_vod_url(&config.region, id)),
+            None => println!("{title}"),
+        }
+    }
+
+    log_info!(
+        "\nPage {page} of {}\n",
+        result.try_get("nbPages").as_u64().unwrap_or(1)
+    );
+
+    Ok(CLIExitCode::Success)
+}
+
+/// Handles the `formats` command.
+async fn run_formats(url: &str) -> anyhow::Result<CLIExitCode> {
+    if !is_logged_in("") {
+        return Ok(CLIExitCode::AuthFailed);
+    }
+
+    let vod = get_vod_meta(url, "").await?;
+    let hls = get_vod_stream_url(vod.id, "").await?;
+    let VodFormats {
+        formats,
+        audio_tracks,
+        subtitles,
+    } = get_vod_formats(&hls, |warning| log_warn!("[yt-dlp] {warning}\n")).await?;
+
+    log_info!("Available formats for \"{}\":\n", vod.title);
+    println!(
+        "{:<24} {:<12} {:>6} {:>10}  {:<16} {:<16}",
+        "ID", "RESOLUTION", "FPS", "TBR", "VCODEC", "ACODEC"
+    );
+
+    for format in formats.as_array().into_iter().flatten() {
+        let get_str = |key: &str| match format.try_get(key) {
+            JSON::String(val) => val.clone(),
+            JSON::Null => "-".to_string(),
+            val => val.to_string(),
+        };
+
+        println!(
+            "{:<24} {:<12} {:>6} {:>10}  {:<16} {:<16}",
+            get_str("format_id"),
+            get_str("resolution"),
+            get_str("fps"),
+            get_str("tbr"),
+            get_str("vcodec"),
+            get_str("acodec")
+        );
+    }
+
+    if !audio_tracks.is_empty() {
+        println!("\nAudio tracks:");
+
+        for track in &audio_tracks {
+            println!(
+                "  {:<24} {:<8} {}",
+                track.format_id,
+                if track.language.is_empty() {
+                    "-"
+                } else {
+                    &track.language
+                },
+                track.acodec
+            );
+        }
+    }
+
+    if !subtitles.is_empty() {
+        println!("\nSubtitles:");
+
+        for track in &subtitles {
+            println!("  {:<8} {:<24} {}", track.language, track.name, track.ext);
+        }
+    }
+
+    Ok(CLIExitCode::Success)
+}
+
+/// Handles the `download` command. Downloads are started through the download scheduler, so the
+/// concurrent downloads limit in the configuration is respected.
+async fn run_download(
+    urls: &[String],
+    format: Option<String>,
+    account: &str,
+) -> anyhow::Result<CLIExitCode> {
+    if !is_logged_in(account) {
+        return Ok(CLIExitCode::AuthFailed);
+    }
+
+    // A running GUI server owns the saved downloads-queue and the configuration, which are
+    // updated by the downloads (the file number, for example)
+    if is_server_running().await {
+        return Err(anyhow!(
+            "UFC Ripper is already running on port {}. \
+            Add the downloads through the GUI or the REST API, or stop UFC Ripper first",
+            get_config().port
+        ));
+    }
+
+    // The CLI downloads only use the in-memory downloads-queue, which is never saved
+    init_scheduler();
+
+    let (result_sender, mut result_receiver) = mpsc::unbounded_channel::<DownloadResult>();
+    let mut active = HashMap::new();
+    let mut failed = 0;
+
+    for url in urls {
+        let vod = match get_vod_meta(url, account).await {
+            Ok(vod) => vod,
+            Err(error) => {
+                log_err!("Unable to download \"{url}\":");
+                print_error(&error);
+                failed += 1;
+                continue;
+            }
+        };
+
+        if !vod.access {
+            log_err!(
+                "Unable to download \"{}\". Your Fight Pass account doesn't have access to this VOD\n",
+                vod.title
+            );
+            failed += 1;
+            continue;
+        }
+
+        let queued_vod = match enqueue_download(
+            Vod {
+                q_id: create_uuid(),
+                custom_format: format.clone().unwrap_or_default(),
+                ..vod
+            },
+            false,
+            create_cli_download_handlers(result_sender.clone()),
+        ) {
+            Ok(queued_vod) => queued_vod,
+            Err(error) => {
+                log_err!("Unable to download \"{url}\":");
+                print_error(&error);
+                failed += 1;
+                continue;
+            }
+        };
+
+        log_info!("Queued \"{}\"\n", queued_vod.title);
+        active.insert(queued_vod.q_id, queued_vod.title);
+    }
+
+    while !active.is_empty() {
+        tokio::select! {
+            result = result_receiver.recv() => {
+                let Some((q_id, result)) = result else {
+                    break;
+                };
+                let title = active.remove(&q_id).unwrap_or_default();
+
+                match result {
+                    Ok(()) => log_success!("Finished downloading \"{title}\"\n"),
+                    Err(error) => {
+                        log_err!("Failed to download \"{title}\":");
+                        print_error(&error);
+                        failed += 1;
+                    }
+                }
+            }
+            _ = tokio::signal::ctrl_c() => {
+                stop_cli_downloads(active.keys()).await;
+
+                return Ok(CLIExitCode::Interrupted);
+            }
+        }
+    }
+
+    if failed > 0 {
+        log_err!("{failed} download(s) failed\n");
+
+        Ok(CLIExitCode::DownloadFailed)
+    } else {
+        Ok(CLIExitCode::Success)
+    }
+}
+
+/// Handles the `queue` command.
+async fn run_queue() -> anyhow::Result<CLIExitCode> {
+    let q = read_saved_dlq().await?;
+    let mut vods: Vec<&Vod> = q.values().collect();
+
+    if vods.is_empty() {
+        log_info!("The downloads-queue is empty\n");
+
+        return Ok(CLIExitCode::Success);
+    }
+
+    vods.sort_by_key(|vod| vod.idx);
+
+    for vod in vods {
+        println!(
+            "{:>4}  {:<12} {:>4}%  {}",
+            vod.idx, vod.status, vod.progress, vod.title
+        );
+    }
+
+    Ok(CLIExitCode::Success)
+}
+
+/// Handles the `auth` command. A running GUI server needs to be restarted to use the changes.
+async fn run_auth(action: AuthAction) -> anyhow::Result<CLIExitCode> {
+    let mut gui_auth = get_config().gui_auth.clone();
+
+    match action {
+        AuthAction::SetPassword { pass } => {
+            let pass = match pass {
+                Some(pass) => pass,
+                None => read_password()?,
+            };
+
+            gui_auth.password_hash = hash_secret(&pass)?;
+            gui_auth.enabled = true;
+            update_config(ConfigUpdate::GuiAuth(gui_auth)).await;
+            log_success!("GUI password updated. Authentication is enabled\n");
+        }
+        AuthAction::CreateToken => {
+            let token = create_access_token();
+
+            gui_auth.token_hash = hash_secret(&token)?;
+            gui_auth.enabled = true;
+            update_config(ConfigUpdate::GuiAuth(gui_auth)).await;
+            log_success!("Access token created. Authentication is enabled\n");
+            log_warn!("Save this token now, since it can't be displayed again:\n");
+            println!("{token}\n");
+        }
+        AuthAction::Disable => {
+            gui_auth.enabled = false;
+            update_config(ConfigUpdate::GuiAuth(gui_auth)).await;
+            log_success!("Authentication is disabled\n");
+        }
+    }
+
+    log_info!("Restart UFC Ripper if it's already running, for the changes to take effect\n");
+
+    Ok(CLIExitCode::Success)
+}
+
+/// Creates the handlers that print the progress of a download to the terminal, and report the
+/// result of the download through the given channel.
+fn create_cli_download_handlers(
+    result_sender: mpsc::UnboundedSender<DownloadResult>,
+) -> DownloadHandlers {
+    let fail_sender = result_sender.clone();
+
+    DownloadHandlers {
+        on_start: Arc::new(|vod| {
+            log_info!("Downloading \"{}\"\n", vod.title);
+        }),
+        on_progress: Arc::new(|q_id, updates| {
+            print_download_progress(q_id, &updates);
+        }),
+        on_warning: Arc::new(|q_id, warning| {
+            log_warn!("[{}] {warning}\n", get_dlq_vod_title(q_id));
+        }),
+        on_completion: Arc::new(move |q_id| {
+            result_sender.send((q_id.to_string(), Ok(()))).ok();
+        }),
+        on_fail: Arc::new(move |q_id, error| {
+            fail_sender.send((q_id.to_string(), Err(error))).ok();
+        }),
+    }
+}
+
+/// Prints a progress update of a download to the terminal.
+fn print_download_progress(q_id: &str, updates: &JSON) {
+    let title = get_dlq_vod_title(q_id);
+
+    match updates.try_get("task").as_str() {
+        Some(task @ ("video" | "audio")) => println!(
+            "[{title}] {task} {:>3}% of {} at {} (ETA {})",
+            updates.try_get("progress").as_f64().unwrap_or(0.0),
+            updates.try_get("size").as_str().unwrap_or("N/A"),
+            updates.try_get("speed").as_str().unwrap_or("N/A"),
+            updates.try_get("eta").as_str().unwrap_or("N/A")
+        ),
+        Some("retry") => println!(
+            "[{title}] Retrying (attempt {} of {})",
+            updates.try_get("attempt"),
+            updates.try_get("maxAttempts")
+        ),
+        Some("merge") => println!("[{title}] Merging video and audio"),
+        Some("meta") => println!("[{title}] Writing metadata"),
+        Some("fix") => println!("[{title}] Fixing the container"),
+        Some("verify") => println!("[{title}] Verifying the downloaded file"),
+        Some(task @ ("transcode" | "remux" | "hook")) => println!(
+            "[{title}] Post-processing step {} of {} ({task})",
+            updates.try_get("step"),
+            updates.try_get("steps")
+        ),
+        _ => {}
+    }
+}
+
+/// Stops the unfinished CLI downloads on an interrupt. Queued downloads are cancelled and active
+/// downloads are paused, which keeps their partially downloaded files.
+async fn stop_cli_downloads<'a>(q_ids: impl Iterator<Item = &'a String>) {
+    log_warn!("Interrupted. Stopping the downloads..\n");
+
+    let pending = get_pending_dl_order();
+    let (queued, active): (Vec<_>, Vec<_>) = q_ids
+        .filter_map(|q_id| get_dlq().get(q_id).cloned())
+        .partition(|vod| pending.contains(&vod.q_id));
+
+    // Queued downloads need to be removed first, or the scheduler would start them in the slots
+    // freed up by the paused downloads.
+    for vod in queued {
+        if let Err(error) = cancel_scheduled_download(&vod).await {
+            print_error(&error);
+        }
+    }
+
+    for vod in active {
+        match pause_scheduled_download(&vod).await {
+            Ok(()) => log_warn!(
+                "Paused \"{}\". The partially downloaded files were kept\n",
+                vod.title
+            ),
+            Err(error) => print_error(&error),
+        }
+    }
+}
+
+/// Returns the title of a VOD in the downloads-queue, or the queue ID if it doesn't exist.
+fn get_dlq_vod_title(q_id: &str) -> String {
+    get_dlq()
+        .get(q_id)
+        .map_or_else(|| q_id.to_string(), |vod| vod.title.clone())
+}
+
+/// Checks if there's a login session for the account, and prints an error if there isn't. The
+/// active account is checked if the account name is empty.
+fn is_logged_in(account_name: &str) -> bool {
+    match get_config().get_account(account_name) {
+        Some(account) if !account.auth_token.is_empty() => true,
+        Some(_) => {
+            log_err!(
+                "You need to log in first. Use the \"login\" command to log into UFC Fight Pass\n"
+            );
+
+            false
+        }
+        None => {
+            log_err!(
+                "Account \"{account_name}\" does not exist. Use the \"login\" command with \"--name {account_name}\" to add it\n"
+            );
+
+            false
+        }
+    }
+}
+
+/// Checks if another instance of UFC Ripper is serving the GUI on the configured port.
+async fn is_server_running() -> bool {
+    TcpStream::connect((Ipv4Addr::LOCALHOST, get_config().port))
+        .await
+        .is_ok()
+}
+
+/// Prompts for a password without echoing it to the terminal. A password piped into the standard
+/// input is read as it is.
+fn read_password() -> anyhow::Result<String> {
+    let pass = if stdin().is_terminal() {
+        rpassword::prompt_password("Password: ")
+            .context("Failed to read the password from the terminal")?
+    } else {
+        let mut pass = String::new();
+
+        stdin()
+            .read_line(&mut pass)
+            .context("Failed to read the password from the standard input")?;
+
+        pass.trim_end_matches(['\r', '\n']).to_string()
+    };
+
+    if pass.is_empty() {
+        return Err(anyhow!("Password can't be empty"));
+    }
+
+    Ok(pass)
+}
+
+/// Prints an error to the terminal, with the full details in debug mode.
+fn print_error(error: &anyhow::Error) {
+    if is_debug() {
+        log_err!("{error:#?}\n");
+    } else {
+        log_err!("{error}\n");
+    }
+}
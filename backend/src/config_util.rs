@@ -3,21 +3,28 @@
 // Libs
 use std::{path::PathBuf, sync::Arc};
 
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use arc_swap::{ArcSwap, Guard};
 use once_cell::sync::{Lazy, OnceCell};
 use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
 
-use ufcr_libs::{log_err, log_success, log_warn};
+use ufcr_libs::{log_err, log_info, log_success, log_warn};
 
 use crate::{
     app_util::get_app_root_dir,
+    bin_util::{validate_dl_arg_changes, validate_retry_policy},
     fs_util::{
         build_downloads_dir_path, create_config_backup, read_config_file_to_string,
         write_config_to_file,
     },
     net_util::{LoginSession, JSON},
+    postproc_util::{validate_hook_changes, validate_post_processing},
     rt_util::QuitUnwrap,
+    secret_util::{
+        read_secret_store, redact_secrets, restore_redacted_secrets, restore_secrets, take_secrets,
+    },
+    template_util::validate_output_templates,
 };
 
 // Structs
@@ -34,6 +41,9 @@ pub struct UFCRConfig {
     pub user: String,
     pub refresh_token: String,
     pub auth_token: String,
+    /// Name of the account profile, which the session above belongs to.
+    pub active_account: String,
+    pub accounts: Vec<AccountProfile>,
     pub search_title_only: bool,
     pub show_thumb: bool,
     pub show_duration: bool,
@@ -42,13 +52,39 @@ pub struct UFCRConfig {
     pub merge_ext: String,
     pub vid_quality: String,
     pub aud_quality: String,
+    /// Languages of the audio tracks to download, in the order of preference. The audio tracks of
+    /// every available language are merged into the downloaded file.
+    pub audio_langs: Vec<String>,
+    /// Languages of the subtitle tracks to embed into the downloaded file (`all` for every track).
+    pub sub_langs: Vec<String>,
     pub dl_path: String,
     pub use_temp_path: bool,
     pub temp_path: String,
     pub number_files: bool,
     pub cur_number: u64,
+    /// Template of the downloaded file names, without the extension.
+    pub file_template: String,
+    /// Template of the directories inside the downloads directory that the files are saved to.
+    pub dir_template: String,
+    /// Organizes the downloads for media servers, with metadata files and posters.
+    pub library_mode: bool,
+    /// Embeds the metadata, the cover art and the chapters of the VODs into the downloaded files.
+    pub embed_metadata: bool,
+    /// Verifies the downloaded files with `ffprobe` once the downloads complete.
+    pub verify_downloads: bool,
+    /// Allowed difference between the duration of a downloaded file and the duration of the VOD,
+    /// in seconds.
+    pub verify_tolerance: u64,
+    /// Steps run on every verified download, in the given order.
+    pub post_processing: Vec<PostProcessStep>,
     pub multi_frag: bool,
     pub concur_frags: u64,
+    #[serde(rename = "maxConcurDLs")]
+    pub max_concur_dls: u64,
+    pub retry_policy: RetryPolicy,
+    /// Determines what happens when a VOD in the download history is downloaded again (allow,
+    /// warn, refuse).
+    pub duplicate_downloads: String,
     pub throttle: bool,
     pub dl_rate: String,
     pub cus_format: bool,
@@ -57,6 +93,11 @@ pub struct UFCRConfig {
     pub use_proxy: bool,
     pub proxy_config: ProxyConfig,
     pub dl_args: Vec<String>,
+    pub gui_auth: GuiAuth,
+    /// Saved searches, whose new matches are downloaded automatically.
+    pub watchlist: Vec<Subscription>,
+    /// Time between the checks of the watchlist in minutes.
+    pub watch_interval: u64,
 }
 
 impl Default for UFCRConfig {
@@ -71,6 +112,8 @@ impl Default for UFCRConfig {
             user: String::new(),
             refresh_token: String::new(),
             auth_token: String::new(),
+            active_account: String::new(),
+            accounts: Vec::new(),
             search_title_only: false,
             show_thumb: true,
             show_duration: true,
@@ -79,13 +122,25 @@ impl Default for UFCRConfig {
             merge_ext: "mp4".into(),
             vid_quality: "bestvideo".into(),
             aud_quality: "bestaudio".into(),
+            audio_langs: Vec::new(),
+            sub_langs: Vec::new(),
             dl_path: String::new(),
             use_temp_path: false,
             temp_path: String::new(),
             number_files: true,
             cur_number: 1,
+            file_template: "{number}. {title}".into(),
+            dir_template: String::new(),
+            library_mode: false,
+            embed_metadata: true,
+            verify_downloads: true,
+            verify_tolerance: 10,
+            post_processing: Vec::new(),
             multi_frag: true,
             concur_frags: 64,
+            max_concur_dls: 3,
+            retry_policy: RetryPolicy::default(),
+            duplicate_downloads: "warn".into(),
             throttle: false,
             dl_rate: "100K".into(),
             cus_format: false,
@@ -101,6 +156,169 @@ impl Default for UFCRConfig {
                 "--ignore-config".into(),
                 "--no-check-certificate".into(),
             ],
+            gui_auth: GuiAuth::default(),
+            watchlist: Vec::new(),
+            watch_interval: 360,
+        }
+    }
+}
+
+impl UFCRConfig {
+    /// Returns the account profile with the given name. An empty name returns the active account.
+    pub fn get_account(&self, name: &str) -> Option<AccountProfile> {
+        if name.is_empty() || name == self.active_account {
+            return Some(self.get_active_account());
+        }
+
+        self.accounts
+            .iter()
+            .find(|account| account.name == name)
+            .cloned()
+    }
+
+    /// Returns all saved account profiles, including the active account.
+    pub fn get_accounts(&self) -> Vec<AccountProfile> {
+        let mut accounts: Vec<AccountProfile> = self
+            .accounts
+            .iter()
+            .filter(|account| account.name != self.active_account)
+            .cloned()
+            .collect();
+
+        if !self.user.is_empty() {
+            accounts.insert(0, self.get_active_account());
+        }
+
+        accounts
+    }
+
+    /// Returns the active account, using the session in the top-level fields.
+    fn get_active_account(&self) -> AccountProfile {
+        AccountProfile {
+            name: self.active_account.clone(),
+            region: self.region.clone(),
+            user: self.user.clone(),
+            refresh_token: self.refresh_token.clone(),
+            auth_token: self.auth_token.clone(),
+        }
+    }
+
+    /// Makes an account profile the active account.
+    fn activate_account(&mut self, account: AccountProfile) {
+        self.active_account = account.name;
+        self.region = account.region;
+        self.user = account.user;
+        self.refresh_token = account.refresh_token;
+        self.auth_token = account.auth_token;
+    }
+
+    /// Adds or replaces an account profile and makes it the active account.
+    fn save_account(&mut self, account: AccountProfile) {
+        match self
+            .accounts
+            .iter_mut()
+            .find(|saved| saved.name == account.name)
+        {
+            Some(saved) => *saved = account.clone(),
+            None => self.accounts.push(account.clone()),
+        }
+
+        self.activate_account(account);
+    }
+
+    /// Replaces the access token of an account profile.
+    fn set_account_auth(&mut self, name: &str, auth_token: String) {
+        if let Some(account) = self
+            .accounts
+            .iter_mut()
+            .find(|account| account.name == name)
+        {
+            account.auth_token.clone_from(&auth_token);
+        }
+
+        if name == self.active_account {
+            self.auth_token = auth_token;
+        }
+    }
+
+    /// Removes an account profile. If the active account is removed, the next saved account
+    /// becomes the active one.
+    fn remove_account(&mut self, name: &str) {
+        self.accounts.retain(|account| account.name != name);
+
+        if name == self.active_account {
+            let next_account = self.accounts.first().cloned().unwrap_or(AccountProfile {
+                region: self.region.clone(),
+                ..AccountProfile::default()
+            });
+
+            self.activate_account(next_account);
+        }
+    }
+
+    /// Adds or replaces a watchlist subscription.
+    fn save_subscription(&mut self, subscription: Subscription) {
+        match self
+            .watchlist
+            .iter_mut()
+            .find(|saved| saved.id == subscription.id)
+        {
+            Some(saved) => *saved = subscription,
+            None => self.watchlist.push(subscription),
+        }
+    }
+}
+
+/// A named UFC Fight Pass account with its region and login session.
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountProfile {
+    pub name: String,
+    /// Fight Pass region of the account (dce.ufc, dce.ufcbrazil).
+    pub region: String,
+    pub user: String,
+    pub refresh_token: String,
+    pub auth_token: String,
+}
+
+impl AccountProfile {
+    /// Creates an account profile from a successful login.
+    pub fn from_session(name: String, region: String, session: LoginSession) -> Self {
+        AccountProfile {
+            name,
+            region,
+            user: session.user,
+            refresh_token: session.refresh,
+            auth_token: session.auth,
+        }
+    }
+}
+
+/// Determines how failed downloads are retried automatically.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetryPolicy {
+    pub enabled: bool,
+    /// Maximum number of attempts for a download, including the first one.
+    pub max_attempts: u32,
+    /// Delay before the first retry in seconds, which doubles with each attempt.
+    pub base_delay: u64,
+    /// Upper limit for the delay between attempts in seconds.
+    pub max_delay: u64,
+    /// Failure classes that should be retried (stream, network, process, output, format,
+    /// location, unknown). Verification failures happen after the download attempts, so they
+    /// can't be retried.
+    pub retry_on: Vec<String>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            enabled: true,
+            max_attempts: 3,
+            base_delay: 5,
+            max_delay: 60,
+            retry_on: vec!["stream".into(), "network".into()],
         }
     }
 }
@@ -132,15 +350,92 @@ pub struct ProxyAuth {
     pub password: String,
 }
 
+/// Determines how clients are authenticated by the web GUI, the REST API and the `WebSocket`
+/// server.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GuiAuth {
+    pub enabled: bool,
+    /// Argon2 hash of the password used to log into the GUI.
+    pub password_hash: String,
+    /// Argon2 hash of the access token used by other tools as a bearer token.
+    pub token_hash: String,
+    /// Lifetime of a GUI login session in hours.
+    pub session_ttl: u64,
+}
+
+impl Default for GuiAuth {
+    fn default() -> Self {
+        GuiAuth {
+            enabled: false,
+            password_hash: String::new(),
+            token_hash: String::new(),
+            session_ttl: 168,
+        }
+    }
+}
+
+/// A saved search of the watchlist. New VODs matching the search are downloaded automatically.
+#[derive(Default, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Subscription {
+    pub id: String,
+    pub name: String,
+    pub query: String,
+    pub title_only: bool,
+    pub enabled: bool,
+    /// Name of the account profile used for the downloads. Defaults to the active account.
+    pub account: String,
+    /// Video resolution of the downloads. Defaults to the configured resolution.
+    pub resolution: String,
+    /// Custom `yt-dlp` format of the downloads, which takes priority over the resolution.
+    pub custom_format: String,
+}
+
 // Enums
+/// A step of the post-processing pipeline.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub enum PostProcessStep {
+    /// Re-encodes the downloaded file with an `ffmpeg` preset.
+    Transcode { preset: TranscodePreset },
+    /// Moves the streams of the downloaded file into another container without re-encoding.
+    Remux { ext: String },
+    /// Runs a shell command, with the output path and the VOD's metadata as environment variables.
+    Hook { command: String },
+}
+
+/// Presets of the transcode step of the post-processing pipeline.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TranscodePreset {
+    /// Re-encodes the video with H.265 to save space.
+    H265,
+    /// Normalizes the loudness of the audio.
+    NormalizeAudio,
+    /// Downscales the video to 720p, keeping smaller videos as they are.
+    Downscale720,
+    /// Downscales the video to 480p, keeping smaller videos as they are.
+    Downscale480,
+}
+
 /// Specifies which fields in the configuration are being updated.
 pub enum ConfigUpdate {
     Default,
     Config(Box<UFCRConfig>),
-    Region(String),
-    Auth(String),
-    Tokens(LoginSession),
-    FileNum(u64),
+    /// Adds or replaces an account profile and makes it the active account.
+    Account(AccountProfile),
+    /// Replaces the access token of an account profile (account name, access token).
+    Auth(String, String),
+    SwitchAccount(String),
+    RemoveAccount(String),
+    /// Increases the current file number by one.
+    IncFileNum,
+    GuiAuth(GuiAuth),
+    /// Adds or replaces a watchlist subscription.
+    Subscription(Subscription),
+    /// Removes a watchlist subscription (subscription ID).
+    RemoveSubscription(String),
 }
 
 // Statics
@@ -149,6 +444,9 @@ pub static CONFIG_PATH: Lazy<PathBuf> =
 static CONFIG: Lazy<ArcSwap<UFCRConfig>> =
     Lazy::new(|| ArcSwap::from_pointee(UFCRConfig::default()));
 static DEBUG_OVERRIDE: OnceCell<bool> = OnceCell::new();
+/// Serializes the configuration updates, so concurrent updates (token refreshes, file numbers of
+/// parallel downloads, the watchlist poller) can't overwrite each other's changes.
+static CONFIG_UPDATE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
 
 /// Loads the configuration into global CONFIG.
 pub async fn load_config() {
@@ -196,6 +494,47 @@ pub fn migrate_config(old_config_str: &str) -> anyhow::Result<UFCRConfig> {
     Ok(migrated_config)
 }
 
+/// Parses configuration data sent by a client. Outdated configurations are migrated, which is
+/// indicated by the returned flag. Redacted secrets are replaced with the current ones, the GUI
+/// authentication is always kept as it is, and new hooks or `yt-dlp` arguments are only accepted
+/// with the GUI authentication enabled.
+pub fn parse_config_data(data: &JSON) -> anyhow::Result<(UFCRConfig, bool)> {
+    let (mut config, migrated) =
+        if let Ok(config) = serde_json::from_value::<UFCRConfig>(data.clone()) {
+            (config, false)
+        } else {
+            let config_str = serde_json::to_string(data)
+                .context("Invalid configuration format. Configuration data is not valid JSON")?;
+            let migrated_config = migrate_config(&config_str)
+                .map_err(|error| anyhow!("Invalid configuration format. {error}"))?;
+
+            (migrated_config, true)
+        };
+
+    validate_output_templates(&config.file_template, &config.dir_template)?;
+    validate_post_processing(&config.post_processing)?;
+    validate_retry_policy(&config.retry_policy)?;
+
+    let current_config = get_config();
+
+    validate_hook_changes(
+        &config.post_processing,
+        &current_config.post_processing,
+        current_config.gui_auth.enabled,
+    )?;
+    validate_dl_arg_changes(
+        &config.dl_args,
+        &current_config.dl_args,
+        current_config.gui_auth.enabled,
+    )?;
+    restore_redacted_secrets(&mut config, &current_config);
+
+    // The GUI authentication can only be changed with the set-password action or the CLI
+    config.gui_auth = current_config.gui_auth.clone();
+
+    Ok((config, migrated))
+}
+
 /// Gets the config.json file content and turn it into a valid `UFCRConfig`.
 pub async fn get_config_from_file() -> UFCRConfig {
     let conf_file = read_config_file_to_string(&CONFIG_PATH).await;
@@ -226,6 +565,20 @@ pub async fn get_config_from_file() -> UFCRConfig {
         }
     };
 
+    // Plain-text secrets of older configurations are moved to the secret store with the next write
+    if !take_secrets(&mut config.clone()).is_empty() {
+        log_info!("Moving the plain-text credentials in \"config.json\" to the secret store..\n");
+    }
+
+    // Starting without the saved credentials would overwrite them with the next write
+    let secrets = read_secret_store().await.unwrap_or_quit(
+        r#"Failed to read the saved credentials. Set the passphrase, restore the "secret.key" file, or remove the "secrets.json" file to log in again"#,
+    );
+
+    restore_secrets(&mut config, &secrets);
+
+    import_legacy_session(&mut config);
+
     config.dl_path = build_downloads_dir_path(config.dl_path)
         .unwrap_or_quit("Failed to build the path for user's downloads directory");
 
@@ -237,6 +590,11 @@ pub fn get_config() -> Guard<Arc<UFCRConfig>> {
     CONFIG.load()
 }
 
+/// Returns the current configuration with its secrets redacted, so it can be sent to the clients.
+pub fn get_redacted_config() -> UFCRConfig {
+    redact_secrets(&get_config())
+}
+
 /// Returns the debug status.
 pub fn is_debug() -> bool {
     match DEBUG_OVERRIDE.get() {
@@ -245,21 +603,37 @@ pub fn is_debug() -> bool {
     }
 }
 
-/// Updates the configuration with new data and writes to config.json.
+/// Updates the configuration with new data and writes to config.json. Updates are applied one at a
+/// time, each on top of the configuration saved by the previous one.
 pub async fn update_config(update: ConfigUpdate) {
+    let _update_lock = CONFIG_UPDATE_LOCK.lock().await;
     let mut new_config = get_config().as_ref().clone();
 
     match update {
-        ConfigUpdate::Default => new_config = UFCRConfig::default(),
+        // Resetting the configuration shouldn't remove the authentication of the GUI
+        ConfigUpdate::Default => {
+            new_config = UFCRConfig {
+                gui_auth: new_config.gui_auth,
+                ..UFCRConfig::default()
+            }
+        }
         ConfigUpdate::Config(data) => new_config = *data,
-        ConfigUpdate::Region(data) => new_config.region = data,
-        ConfigUpdate::Auth(data) => new_config.auth_token = data,
-        ConfigUpdate::Tokens(data) => {
-            new_config.user = data.user;
-            new_config.refresh_token = data.refresh;
-            new_config.auth_token = data.auth;
+        ConfigUpdate::Account(data) => new_config.save_account(data),
+        ConfigUpdate::Auth(name, data) => new_config.set_account_auth(&name, data),
+        ConfigUpdate::SwitchAccount(name) => {
+            if let Some(account) = new_config.get_account(&name) {
+                new_config.activate_account(account);
+            }
+        }
+        ConfigUpdate::RemoveAccount(name) => new_config.remove_account(&name),
+        ConfigUpdate::IncFileNum => new_config.cur_number += 1,
+        ConfigUpdate::GuiAuth(data) => new_config.gui_auth = data,
+        ConfigUpdate::Subscription(data) => new_config.save_subscription(data),
+        ConfigUpdate::RemoveSubscription(id) => {
+            new_config
+                .watchlist
+                .retain(|subscription| subscription.id != id);
         }
-        ConfigUpdate::FileNum(data) => new_config.cur_number = data,
     }
 
     CONFIG.store(Arc::new(new_config));
@@ -268,12 +642,46 @@ pub async fn update_config(update: ConfigUpdate) {
         .unwrap_or_quit(r#"An error occurred while trying to update the "config.json" file"#);
 }
 
+/// Makes a saved account profile the active account.
+pub async fn switch_account(name: &str) -> anyhow::Result<()> {
+    if get_config().get_account(name).is_none() {
+        return Err(anyhow!(r#"Account "{name}" does not exist"#));
+    }
+
+    update_config(ConfigUpdate::SwitchAccount(name.to_string())).await;
+
+    Ok(())
+}
+
+/// Logs out of an account by removing its profile. An empty name logs out of the active account.
+pub async fn logout_account(name: &str) -> anyhow::Result<()> {
+    let name = match get_config().get_account(name) {
+        Some(account) => account.name,
+        None => return Err(anyhow!(r#"Account "{name}" does not exist"#)),
+    };
+
+    update_config(ConfigUpdate::RemoveAccount(name)).await;
+
+    Ok(())
+}
+
+/// Saves a login session of an older configuration, which didn't support multiple accounts, as
+/// an account profile.
+fn import_legacy_session(config: &mut UFCRConfig) {
+    if config.accounts.is_empty() && !config.user.is_empty() {
+        let account = AccountProfile {
+            name: config.user.clone(),
+            ..config.get_active_account()
+        };
+
+        config.save_account(account);
+    }
+}
+
 /// Increases the current file number by one
 pub async fn inc_file_number() {
-    let config = get_config();
-
-    if config.number_files {
-        update_config(ConfigUpdate::FileNum(config.cur_number + 1)).await;
+    if get_config().number_files {
+        update_config(ConfigUpdate::IncFileNum).await;
     }
 }
 
@@ -285,7 +693,7 @@ pub async fn inc_file_number() {
 mod tests {
     use crate::{fs_util::build_downloads_dir_path, rt_util::set_custom_panic};
 
-    use super::{get_config, is_debug, load_config, UFCRConfig};
+    use super::{get_config, is_debug, load_config, AccountProfile, UFCRConfig};
 
     #[tokio::test]
     async fn unit_load_config() {
@@ -306,6 +714,41 @@ mod tests {
         assert_eq!(config.as_ref(), &default_config);
     }
 
+    #[test]
+    fn unit_account_profiles() {
+        let mut config = UFCRConfig::default();
+        let create_account = |name: &str, region: &str| AccountProfile {
+            name: name.into(),
+            region: region.into(),
+            user: format!("{name}@example.com"),
+            refresh_token: format!("{name}-refresh"),
+            auth_token: format!("{name}-auth"),
+        };
+
+        config.save_account(create_account("main", "dce.ufc"));
+        config.save_account(create_account("brazil", "dce.ufcbrazil"));
+        assert_eq!(config.active_account, "brazil");
+        assert_eq!(config.region, "dce.ufcbrazil");
+        assert_eq!(config.get_accounts().len(), 2);
+
+        config.set_account_auth("main", "main-new-auth".into());
+        assert_eq!(config.auth_token, "brazil-auth");
+        assert_eq!(
+            config.get_account("main").unwrap().auth_token,
+            "main-new-auth"
+        );
+
+        config.remove_account("brazil");
+        assert_eq!(config.active_account, "main");
+        assert_eq!(config.user, "main@example.com");
+        assert_eq!(config.auth_token, "main-new-auth");
+        assert!(config.get_account("brazil").is_none());
+
+        config.remove_account("main");
+        assert!(config.get_accounts().is_empty());
+        assert!(config.auth_token.is_empty());
+    }
+
     #[test]
     fn unit_is_debug() {
         assert!(!is_debug());
@@ -0,0 +1,205 @@
+// Libs
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    bin_util::{DownloadError, DownloadHandlers, FailureClass},
+    net_util::{
+        get_browse_page, get_collection_vods, get_playlist, get_vod_meta, Collection, JSON,
+    },
+    sched_util::enqueue_download,
+    state_util::{get_dlq, Vod},
+    txt_util::{create_uuid, resolve_fight_pass_link, FightPassLink},
+    ws_util::{emit_config_update, emit_error, emit_vod_download_progress, emit_warn},
+};
+
+// Structs
+/// A request to download every VOD of a playlist or an event page.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectionDownload {
+    pub collection: Collection,
+    #[serde(default)]
+    pub custom_format: String,
+    /// Name of the account profile used for the downloads. Defaults to the active account.
+    #[serde(default)]
+    pub account: String,
+}
+
+/// A request to preview the output path of a VOD. The configured templates are used for the
+/// templates that aren't given.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputPathPreview {
+    #[serde(default)]
+    pub url: String,
+    pub file_template: Option<String>,
+    pub dir_template: Option<String>,
+}
+
+// Statics
+/// Maximum number of URLs verified by a single verify request.
+const MAX_VERIFY_URLS: usize = 200;
+
+/// Verifies a list of Fight Pass URLs or VOD IDs, one per line. Empty lines and lines starting
+/// with `#` are skipped. Returns a result for each line, with the error if the line couldn't be
+/// verified.
+pub async fn verify_urls(input: &str) -> anyhow::Result<Vec<JSON>> {
+    let lines: Vec<(usize, &str)> = input
+        .lines()
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+
+    if lines.is_empty() {
+        return Err(anyhow!("No URLs were provided"));
+    }
+
+    if lines.len() > MAX_VERIFY_URLS {
+        return Err(anyhow!(
+            "Too many URLs. Up to {MAX_VERIFY_URLS} URLs can be verified at once"
+        ));
+    }
+
+    let mut results = Vec::new();
+
+    for (idx, line) in lines {
+        let result = match resolve_fight_pass_link(line) {
+            Ok(FightPassLink::Vod(_)) => get_vod_meta(line, "").await.map(|vod| {
+                json!({
+                    "type": "vod",
+                    "vod": Vod {
+                        q_id: create_uuid(),
+                        ..vod
+                    }
+                })
+            }),
+            Ok(FightPassLink::Playlist(playlist_id)) => get_playlist(playlist_id, "")
+                .await
+                .map(|playlist| json!({ "type": "playlist", "playlist": playlist })),
+            Ok(FightPassLink::Event(page)) => get_browse_page(&page, "")
+                .await
+                .map(|page| json!({ "type": "event", "page": page })),
+            Err(error) => Err(error),
+        };
+
+        results.push(match result {
+            Ok(mut result) => {
+                result["line"] = json!(idx + 1);
+                result["input"] = json!(line);
+                result
+            }
+            Err(error) => json!({
+                "line": idx + 1,
+                "input": line,
+                "error": error.to_string()
+            }),
+        });
+    }
+
+    Ok(results)
+}
+
+/// Adds every VOD of a playlist or an event page to the downloads-queue. Returns the queued VODs,
+/// and the IDs of the VODs that couldn't be queued with the reasons.
+pub async fn enqueue_collection(request: CollectionDownload) -> anyhow::Result<JSON> {
+    let vods = get_collection_vods(&request.collection, &request.account).await?;
+    let mut queued = Vec::new();
+    let mut failed = Vec::new();
+
+    for (vod_id, vod) in vods {
+        match vod {
+            Ok(vod) if vod.access => match enqueue_download(
+                Vod {
+                    q_id: create_uuid(),
+                    custom_format: request.custom_format.clone(),
+                    ..vod
+                },
+                false,
+                create_download_handlers(),
+            ) {
+                Ok(queued_vod) => {
+                    // Lets the connected clients show the download before a download slot is
+                    // available
+                    emit_vod_download_progress(&queued_vod.q_id, json!(queued_vod));
+                    queued.push(queued_vod);
+                }
+                Err(error) => failed.push(json!({
+                    "id": vod_id,
+                    "error": error.to_string()
+                })),
+            },
+            Ok(_) => failed.push(json!({
+                "id": vod_id,
+                "error": "You need a Fight Pass subscription to download this video"
+            })),
+            Err(error) => failed.push(json!({
+                "id": vod_id,
+                "error": error.to_string()
+            })),
+        }
+    }
+
+    if !failed.is_empty() {
+        emit_warn(&format!(
+            "{} of the videos in the collection couldn't be downloaded",
+            failed.len()
+        ));
+    }
+
+    Ok(json!({
+        "queued": queued,
+        "failed": failed
+    }))
+}
+
+/// Creates a set of download handlers that report the state of a download to all connected
+/// clients.
+pub fn create_download_handlers() -> DownloadHandlers {
+    DownloadHandlers {
+        on_start: Arc::new(|vod| {
+            emit_config_update();
+            emit_vod_download_progress(&vod.q_id, json!(vod));
+        }),
+        on_progress: Arc::new(|q_id, updates| {
+            emit_vod_download_progress(q_id, updates);
+        }),
+        on_warning: Arc::new(|q_id, warning| {
+            let title = get_dlq()
+                .get(q_id)
+                .map_or_else(|| q_id.to_string(), |vod| vod.title.clone());
+
+            emit_warn(&format!("{title}: {warning}"));
+        }),
+        on_completion: Arc::new(|q_id| {
+            emit_vod_download_progress(
+                q_id,
+                json!({
+                    "status": "completed"
+                }),
+            );
+        }),
+        on_fail: Arc::new(|q_id, error| {
+            let reason = error.to_string();
+            let status = match error.downcast_ref::<DownloadError>() {
+                Some(dl_error) if dl_error.class == FailureClass::Verification => {
+                    "verificationFailed"
+                }
+                _ => "failed",
+            };
+
+            emit_error(error);
+            emit_vod_download_progress(
+                q_id,
+                json!({
+                    "status": status,
+                    "reason": reason
+                }),
+            );
+        }),
+    }
+}
@@ -0,0 +1,1079 @@
+#![allow(clippy::missing_errors_doc)]
+
+// Libs
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::{Display, Formatter},
+};
+
+use anyhow::{anyhow, Context};
+use once_cell::sync::Lazy;
+use reqwest::{header::HeaderMap, Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    config_util::UFCRConfig,
+    net_util::{JsonTryGet, LoginSession, JSON},
+    txt_util::strip_html_tags,
+};
+
+// Structs
+/// A client for the UFC Fight Pass APIs. The base URLs and the HTTP client can be replaced, so the
+/// client can be pointed to a proxy or a fake server.
+#[derive(Clone)]
+pub struct FightPassApi {
+    pub client: Client,
+    /// Base URL of the Fight Pass front-office API.
+    pub api_url: String,
+    /// Base URL of the Fight Pass library search API.
+    pub search_url: String,
+    /// Fight Pass region of the account (`Realm` header).
+    pub realm: String,
+    pub api_key: String,
+    pub search_api_key: String,
+}
+
+impl Default for FightPassApi {
+    fn default() -> Self {
+        let config = UFCRConfig::default();
+
+        FightPassApi {
+            client: Client::new(),
+            api_url: FIGHT_PASS_API_URL.clone(),
+            search_url: FIGHT_PASS_SEARCH_URL.clone(),
+            realm: config.region,
+            api_key: config.api_key,
+            search_api_key: config.search_api_key,
+        }
+    }
+}
+
+impl FightPassApi {
+    /// Logs into the UFC Fight Pass and returns the set of auth keys included in the response.
+    pub async fn login(&self, email: &str, pass: &str) -> anyhow::Result<LoginSession> {
+        let resp = self
+            .client
+            .post(format!("{}/api/v2/login", self.api_url))
+            .headers(generate_fight_pass_api_headers(&self.realm, &self.api_key)?)
+            .json(&json!({
+                "id": email,
+                "secret": pass
+            }))
+            .send()
+            .await
+            .context("An error occurred while trying to log into the Fight Pass")?;
+
+        if !resp.status().is_success() {
+            let err_msg = "Login failed. Check your credentials and try again";
+            let resp_error_messages = get_messages_from_response(resp).await.context(err_msg)?;
+
+            if resp_error_messages.contains(&"badLocation".to_string()) {
+                return Err(anyhow!(
+                    "Login was blocked because of the IP address your UFC Ripper backend is bound to. \
+                    Try disabling any active VPN connections, or use a proxy service (check configuration)"
+                ));
+            }
+
+            return Err(anyhow!(err_msg));
+        }
+
+        let err_msg = "Login information contains an invalid response";
+        let json_body: JSON = resp.json().await.context(err_msg)?;
+
+        if let (Some(auth), Some(refresh)) = (
+            json_body.try_get("authorisationToken").as_str(),
+            json_body.try_get("refreshToken").as_str(),
+        ) {
+            Ok(LoginSession {
+                user: email.to_string(),
+                auth: auth.to_string(),
+                refresh: refresh.to_string(),
+            })
+        } else {
+            Err(anyhow!(err_msg))
+        }
+    }
+
+    /// Refreshes an expired access token and returns the new one.
+    pub async fn refresh_access_token(
+        &self,
+        auth_token: &str,
+        refresh_token: &str,
+    ) -> anyhow::Result<String> {
+        let resp = self
+            .client
+            .post(format!("{}/api/v2/token/refresh", self.api_url))
+            .headers(generate_fight_pass_api_headers(&self.realm, &self.api_key)?)
+            .bearer_auth(auth_token)
+            .json(&json!({
+                "refreshToken": refresh_token
+            }))
+            .send()
+            .await
+            .context("An error occurred while trying to refresh access token")?;
+
+        if !resp.status().is_success() {
+            let err_msg = "Failed to refresh your login session. Please login with your UFC Fight Pass account again";
+            let resp_error_messages = get_messages_from_response(resp).await.context(err_msg)?;
+
+            if resp_error_messages.contains(&"badLocation".to_string()) {
+                return Err(anyhow!(
+                    "Session refresh request was blocked because of the IP address your UFC Ripper backend is bound to. \
+                    Try disabling any active VPN connections, or use a proxy service (check configuration)"
+                ));
+            } else if resp_error_messages.contains(&"errorRefreshingToken".to_string()) {
+                return Err(anyhow!(
+                    "Invalid refresh token. Please log in with your UFC Fight Pass account again"
+                ));
+            }
+
+            return Err(anyhow!(err_msg));
+        }
+
+        let json_body: JSON = resp
+            .json()
+            .await
+            .context("Login session refresh response contains invalid data")?;
+
+        match json_body.try_get("authorisationToken").as_str() {
+            Some(new_auth_token) => Ok(new_auth_token.to_string()),
+            None => Err(anyhow!(
+                "Server responded with an invalid response to the session refresh request"
+            )),
+        }
+    }
+
+    /// Searches the UFC Fight Pass library for VODs and returns the raw search result.
+    pub async fn search_vods(
+        &self,
+        query: &str,
+        page: u64,
+        title_only: bool,
+    ) -> anyhow::Result<JSON> {
+        self.run_search(&SearchRequest {
+            query: query.to_string(),
+            page,
+            title_only: Some(title_only),
+            ..SearchRequest::default()
+        })
+        .await
+    }
+
+    /// Searches the UFC Fight Pass library for VODs using the filters and the sort order of the
+    /// search request. The result includes the values of each facet for drill-down browsing.
+    ///
+    /// The date sorted replicas and the facet attributes of the search index aren't documented, so
+    /// a sort order or a filter that the search index doesn't provide fails the search, instead of
+    /// returning results that don't match the request.
+    pub async fn search(&self, request: &SearchRequest) -> anyhow::Result<SearchResult> {
+        request.validate()?;
+
+        let result = match self.run_search(request).await {
+            Err(error) if error.is::<MissingIndexError>() => {
+                return Err(error.context(
+                    "The Fight Pass library search can't sort the results by release date. \
+                    Sort the results by relevance instead",
+                ));
+            }
+            result => SearchResult::from_json(&result?),
+        };
+
+        request.check_facet_filters(&result)?;
+
+        Ok(result)
+    }
+
+    /// Sends a search request to the library search API and returns the first result.
+    async fn run_search(&self, request: &SearchRequest) -> anyhow::Result<JSON> {
+        let index_name = format!(
+            "prod-{}-livestreaming-events{}",
+            self.realm,
+            request.sort.get_index_suffix()
+        );
+        let resp = self
+            .client
+            .post(format!("{}/1/indexes/*/queries", self.search_url))
+            .header("x-algolia-application-id", "H99XLDR8MJ")
+            .header("x-algolia-api-key", &self.search_api_key)
+            .json(&json!({
+                "requests": [
+                    {
+                        "indexName": index_name,
+                        "params": request.create_search_params()
+                    }
+                ]
+            }))
+            .send()
+            .await
+            .context("An error occurred while trying to search the Fight Pass library")?;
+
+        if resp.status() == StatusCode::NOT_FOUND && !request.sort.get_index_suffix().is_empty() {
+            return Err(MissingIndexError(index_name).into());
+        }
+
+        if !resp.status().is_success() {
+            return Err(anyhow!(
+                "Server responded with an error for the search request"
+            ));
+        }
+
+        let json_body: JSON = resp
+            .json()
+            .await
+            .context("Search result contains an invalid response")?;
+
+        let result = json_body.try_get("results").try_get(0);
+
+        if result == &JSON::Null {
+            Err(anyhow!("Response does not contain any search results"))
+        } else {
+            Ok(result.clone())
+        }
+    }
+
+    /// Retrieves the raw metadata of a Fight Pass VOD. Fails with an `ExpiredTokenError` if the
+    /// access token needs to be refreshed.
+    pub async fn get_vod_meta(&self, vod_id: &str, auth_token: &str) -> anyhow::Result<JSON> {
+        let resp = self
+            .client
+            .get(format!("{}/api/v2/vod/{vod_id}", self.api_url))
+            .headers(generate_fight_pass_api_headers(&self.realm, &self.api_key)?)
+            .bearer_auth(auth_token)
+            .send()
+            .await
+            .context("An error occurred while trying fetch VOD metadata")?;
+
+        let status = resp.status();
+
+        if !status.is_success() {
+            let err_msg = "An unknown error occurred while trying fetch VOD metadata";
+
+            return match status.as_u16() {
+                401 => Err(get_unauthorized_error(resp, err_msg).await),
+                404 => Err(NotFoundError(
+                    "The video you requested does not exist. Please check the URL and try again"
+                        .to_string(),
+                )
+                .into()),
+                _ => Err(anyhow!(err_msg)),
+            };
+        }
+
+        resp.json()
+            .await
+            .context("VOD metadata response contains invalid data")
+    }
+
+    /// Retrieves a browse page of the Fight Pass catalogue, like the home page or an event page,
+    /// with its sections.
+    pub async fn get_browse_page(
+        &self,
+        page: &str,
+        auth_token: &str,
+    ) -> anyhow::Result<BrowsePage> {
+        validate_page_name(page)?;
+
+        let page_json = self
+            .get_content(
+                &format!("/api/v4/content/{page}?{}", BROWSE_PAGE_PARAMS.as_str()),
+                auth_token,
+                "The page you requested does not exist",
+            )
+            .await?;
+
+        Ok(BrowsePage {
+            title: page_json
+                .try_get("title")
+                .as_str()
+                .unwrap_or(page)
+                .to_string(),
+            sections: page_json
+                .try_get("buckets")
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(BrowseSection::from_json)
+                .collect(),
+        })
+    }
+
+    /// Retrieves a playlist with all of its VODs. The VODs are requested page by page.
+    pub async fn get_playlist(
+        &self,
+        playlist_id: u64,
+        auth_token: &str,
+    ) -> anyhow::Result<Playlist> {
+        let mut path = format!("/api/v4/playlist/{playlist_id}?rpp={PLAYLIST_PAGE_SIZE}");
+        let mut playlist: Option<Playlist> = None;
+
+        for _ in 0..PLAYLIST_MAX_PAGES {
+            let page_json = self
+                .get_content(
+                    &path,
+                    auth_token,
+                    "The playlist you requested does not exist",
+                )
+                .await?;
+            let vods = page_json
+                .try_get("vods")
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(BrowseItem::from_json);
+
+            match &mut playlist {
+                Some(playlist) => playlist.vods.extend(vods),
+                None => {
+                    playlist = Some(Playlist {
+                        id: playlist_id,
+                        title: page_json
+                            .try_get("title")
+                            .as_str()
+                            .unwrap_or_default()
+                            .to_string(),
+                        desc: page_json
+                            .try_get("description")
+                            .as_str()
+                            .unwrap_or_default()
+                            .to_string(),
+                        thumb: page_json
+                            .try_get("coverUrl")
+                            .as_str()
+                            .unwrap_or_default()
+                            .to_string(),
+                        vods: vods.collect(),
+                    });
+                }
+            }
+
+            let paging = page_json.try_get("paging");
+
+            match (
+                paging.try_get("moreDataAvailable").as_bool(),
+                paging.try_get("lastSeen").as_u64(),
+            ) {
+                (Some(true), Some(last_seen)) => {
+                    path = format!(
+                        "/api/v4/playlist/{playlist_id}?rpp={PLAYLIST_PAGE_SIZE}&lastSeen={last_seen}"
+                    );
+                }
+                _ => break,
+            }
+        }
+
+        playlist.context("Playlist response contains invalid data")
+    }
+
+    /// Sends an authenticated request to the front-office API and returns the response data.
+    /// Fails with an `ExpiredTokenError` if the access token needs to be refreshed.
+    async fn get_content(
+        &self,
+        path: &str,
+        auth_token: &str,
+        not_found_msg: &str,
+    ) -> anyhow::Result<JSON> {
+        let err_msg = "An error occurred while trying to fetch the Fight Pass catalogue";
+        let resp = self
+            .client
+            .get(format!("{}{path}", self.api_url))
+            .headers(generate_fight_pass_api_headers(&self.realm, &self.api_key)?)
+            .bearer_auth(auth_token)
+            .send()
+            .await
+            .context(err_msg)?;
+
+        match resp.status() {
+            StatusCode::UNAUTHORIZED => Err(get_unauthorized_error(resp, err_msg).await),
+            StatusCode::NOT_FOUND => Err(NotFoundError(not_found_msg.to_string()).into()),
+            status if !status.is_success() => Err(anyhow!(
+                "Server responded with an error to the catalogue request"
+            )),
+            _ => resp
+                .json()
+                .await
+                .context("Catalogue response contains invalid data"),
+        }
+    }
+
+    /// Fetches the HLS stream URL for a given Fight Pass video. Fails with an `ExpiredTokenError`
+    /// if the access token needs to be refreshed.
+    pub async fn get_vod_stream_url(
+        &self,
+        vod_id: u64,
+        auth_token: &str,
+    ) -> anyhow::Result<String> {
+        let resp = self
+            .client
+            .get(format!("{}/api/v3/stream/vod/{vod_id}", self.api_url))
+            .headers(generate_fight_pass_api_headers(&self.realm, &self.api_key)?)
+            .bearer_auth(auth_token)
+            .send()
+            .await
+            .context("An error occurred while trying request the callback URL for VOD stream")?;
+
+        if resp.status() == StatusCode::UNAUTHORIZED {
+            return Err(get_unauthorized_error(
+                resp,
+                "Server responded with an error to the callback URL request",
+            )
+            .await);
+        }
+
+        if !resp.status().is_success() {
+            return Err(anyhow!(
+                "Server responded with an error to the callback URL request"
+            ));
+        }
+
+        let json_body: JSON = resp
+            .json()
+            .await
+            .context("Callback response contains invalid information")?;
+
+        if let Some(url) = json_body.try_get("playerUrlCallback").as_str() {
+            let resp = self
+                .client
+                .get(url)
+                .send()
+                .await
+                .context("An error occurred while trying request VOD stream URL")?;
+
+            if !resp.status().is_success() {
+                return Err(anyhow!(
+                    "Server responded with an error to the VOD stream request"
+                ));
+            }
+
+            let json_body: JSON = resp
+                .json()
+                .await
+                .context("Stream response contains invalid information")?;
+
+            if let Some(url) = json_body.try_get("hls").try_get(0).try_get("url").as_str() {
+                Ok(url.to_string())
+            } else {
+                Err(anyhow!("No stream URL present in the response"))
+            }
+        } else {
+            Err(anyhow!("No callback request URL present in the response"))
+        }
+    }
+}
+
+/// Filters, sort order and paging of a VOD search.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SearchRequest {
+    pub query: String,
+    /// Zero-indexed page of the search results.
+    pub page: u64,
+    /// Number of results per page (1 - 100). Defaults to 12.
+    pub hits_per_page: Option<u64>,
+    /// Only searches the titles of the VODs. Defaults to the configuration.
+    pub title_only: Option<bool>,
+    /// Earliest and latest release dates as Unix timestamps.
+    pub date_from: Option<u64>,
+    pub date_to: Option<u64>,
+    /// Shortest and longest durations in seconds.
+    pub min_duration: Option<u64>,
+    pub max_duration: Option<u64>,
+    pub sort: SearchSort,
+    pub events: Vec<String>,
+    pub weight_classes: Vec<String>,
+    pub fighters: Vec<String>,
+    pub categories: Vec<String>,
+}
+
+impl SearchRequest {
+    /// Checks if the filters of the search request are valid.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let (Some(from), Some(to)) = (self.date_from, self.date_to) {
+            if from > to {
+                return Err(anyhow!("The start of the date range is after its end"));
+            }
+        }
+
+        if let (Some(min), Some(max)) = (self.min_duration, self.max_duration) {
+            if min > max {
+                return Err(anyhow!(
+                    "The minimum duration is longer than the maximum duration"
+                ));
+            }
+        }
+
+        if self
+            .hits_per_page
+            .is_some_and(|hits| !(1..=100).contains(&hits))
+        {
+            return Err(anyhow!(
+                "The number of results per page must be between 1 and 100"
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the selected values of each facet, with the facet attribute names.
+    fn get_facet_filters(&self) -> [(&'static str, &[String]); 4] {
+        [
+            (SEARCH_FACETS[0].1, &self.events),
+            (SEARCH_FACETS[1].1, &self.weight_classes),
+            (SEARCH_FACETS[2].1, &self.fighters),
+            (SEARCH_FACETS[3].1, &self.categories),
+        ]
+    }
+
+    /// Checks that the search index provides every facet that the search request filters by, since
+    /// the filters of a facet that the search index doesn't provide don't match any VODs.
+    fn check_facet_filters(&self, result: &SearchResult) -> anyhow::Result<()> {
+        for ((facet, _), (_, values)) in SEARCH_FACETS.iter().zip(self.get_facet_filters()) {
+            if !values.is_empty() && !result.facets.contains_key(*facet) {
+                return Err(anyhow!(
+                    "The Fight Pass library search can't filter the results by \"{facet}\". \
+                    Remove the filter and try again"
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Creates the URL encoded search parameters. Values of the same facet are combined with OR,
+    /// while different facets are combined with AND.
+    fn create_search_params(&self) -> String {
+        let mut facet_filters = vec![json!("type:VOD_VIDEO")];
+        let mut numeric_filters = Vec::new();
+
+        for (attribute, values) in self.get_facet_filters() {
+            if !values.is_empty() {
+                facet_filters.push(json!(values
+                    .iter()
+                    .map(|value| format!("{attribute}:{value}"))
+                    .collect::<Vec<String>>()));
+            }
+        }
+
+        for (filter, value) in [
+            (format!("{SEARCH_DATE_ATTRIBUTE}>="), self.date_from),
+            (format!("{SEARCH_DATE_ATTRIBUTE}<="), self.date_to),
+            ("duration>=".to_string(), self.min_duration),
+            ("duration<=".to_string(), self.max_duration),
+        ] {
+            if let Some(value) = value {
+                numeric_filters.push(format!("{filter}{value}"));
+            }
+        }
+
+        let mut params = form_urlencoded::Serializer::new(String::new());
+
+        params
+            .append_pair("facetFilters", &json!(facet_filters).to_string())
+            .append_pair("hitsPerPage", &self.hits_per_page.unwrap_or(12).to_string())
+            .append_pair("advancedSyntax", "true")
+            .append_pair("attributesToRetrieve", &SEARCH_ATTRIBUTES)
+            .append_pair(
+                "facets",
+                &json!(SEARCH_FACETS.map(|(_, attribute)| attribute)).to_string(),
+            );
+
+        if !numeric_filters.is_empty() {
+            params.append_pair("numericFilters", &json!(numeric_filters).to_string());
+        }
+
+        params
+            .append_pair("query", &self.query)
+            .append_pair("page", &self.page.to_string())
+            .append_pair(
+                "restrictSearchableAttributes",
+                if self.title_only.unwrap_or(false) {
+                    r#"["name"]"#
+                } else {
+                    "[]"
+                },
+            )
+            .finish()
+    }
+}
+
+/// A page of VOD search results.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchResult {
+    pub hits: Vec<SearchHit>,
+    pub page: u64,
+    pub pages: u64,
+    pub total_hits: u64,
+    pub hits_per_page: u64,
+    /// Number of matching VODs for each value of each facet. Facets that the search index doesn't
+    /// provide are left out, so clients only offer the filters that work.
+    pub facets: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+impl SearchResult {
+    /// Creates a search result from a raw search result.
+    fn from_json(result: &JSON) -> Self {
+        let hits = result
+            .try_get("hits")
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(SearchHit::from_json)
+            .collect();
+        let facets = SEARCH_FACETS
+            .iter()
+            .filter_map(|(facet, attribute)| {
+                let values = result
+                    .try_get("facets")
+                    .try_get(*attribute)
+                    .as_object()?
+                    .iter()
+                    .filter_map(|(value, count)| Some((value.clone(), count.as_u64()?)))
+                    .collect();
+
+                Some((facet.to_string(), values))
+            })
+            .collect();
+
+        SearchResult {
+            hits,
+            page: result.try_get("page").as_u64().unwrap_or(0),
+            pages: result.try_get("nbPages").as_u64().unwrap_or(0),
+            total_hits: result.try_get("nbHits").as_u64().unwrap_or(0),
+            hits_per_page: result.try_get("hitsPerPage").as_u64().unwrap_or(0),
+            facets,
+        }
+    }
+}
+
+/// A VOD in the search results.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub id: u64,
+    pub title: String,
+    /// Title with the matching parts of the query wrapped in `<em>` tags.
+    pub highlighted_title: String,
+    pub desc: String,
+    pub thumb: String,
+    /// Duration in seconds.
+    pub duration: u64,
+    /// Release date as a Unix timestamp.
+    pub date: Option<u64>,
+}
+
+impl SearchHit {
+    /// Creates a search hit from a raw hit. Hits without an ID are skipped.
+    fn from_json(hit: &JSON) -> Option<Self> {
+        let highlighted_title = hit
+            .try_get("_highlightResult")
+            .try_get("name")
+            .try_get("value")
+            .as_str()
+            .or_else(|| hit.try_get("name").as_str())
+            .unwrap_or_default()
+            .to_string();
+
+        Some(SearchHit {
+            id: hit.try_get("id").as_u64()?,
+            title: strip_html_tags(&highlighted_title),
+            highlighted_title,
+            desc: hit
+                .try_get("description")
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            thumb: hit
+                .try_get("thumbnailUrl")
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            duration: hit.try_get("duration").as_u64().unwrap_or(0),
+            date: hit.try_get(SEARCH_DATE_ATTRIBUTE).as_u64(),
+        })
+    }
+}
+
+/// A page of the Fight Pass catalogue, like the home page or an event page.
+#[derive(Clone, Debug, Serialize)]
+pub struct BrowsePage {
+    pub title: String,
+    pub sections: Vec<BrowseSection>,
+}
+
+impl BrowsePage {
+    /// Returns the IDs of all VODs on the page, without duplicates.
+    pub fn get_vod_ids(&self) -> Vec<u64> {
+        let mut vod_ids: Vec<u64> = Vec::new();
+
+        for item in self.sections.iter().flat_map(|section| &section.items) {
+            if item.kind == BrowseItemKind::Vod && !vod_ids.contains(&item.id) {
+                vod_ids.push(item.id);
+            }
+        }
+
+        vod_ids
+    }
+}
+
+/// A section (row) of a browse page.
+#[derive(Clone, Debug, Serialize)]
+pub struct BrowseSection {
+    pub id: String,
+    pub title: String,
+    pub items: Vec<BrowseItem>,
+}
+
+impl BrowseSection {
+    /// Creates a browse section from a raw content bucket.
+    fn from_json(bucket: &JSON) -> Self {
+        BrowseSection {
+            id: bucket
+                .try_get("exid")
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            title: bucket
+                .try_get("name")
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            items: bucket
+                .try_get("contentList")
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(BrowseItem::from_json)
+                .collect(),
+        }
+    }
+}
+
+/// A VOD or a playlist in the Fight Pass catalogue.
+#[derive(Clone, Debug, Serialize)]
+pub struct BrowseItem {
+    pub id: u64,
+    pub kind: BrowseItemKind,
+    pub title: String,
+    pub desc: String,
+    pub thumb: String,
+    /// Duration in seconds. Zero for playlists.
+    pub duration: u64,
+}
+
+impl BrowseItem {
+    /// Creates a browse item from raw catalogue content. Content other than VODs and playlists is
+    /// skipped.
+    fn from_json(content: &JSON) -> Option<Self> {
+        let kind = match content.try_get("type").as_str() {
+            Some("VOD" | "VOD_VIDEO") | None => BrowseItemKind::Vod,
+            Some("PLAYLIST") => BrowseItemKind::Playlist,
+            _ => return None,
+        };
+
+        Some(BrowseItem {
+            id: content.try_get("id").as_u64()?,
+            kind,
+            title: content.try_get("title").as_str()?.to_string(),
+            desc: content
+                .try_get("description")
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            thumb: content
+                .try_get("thumbnailUrl")
+                .as_str()
+                .or_else(|| content.try_get("coverUrl").as_str())
+                .unwrap_or_default()
+                .to_string(),
+            duration: content.try_get("duration").as_u64().unwrap_or(0),
+        })
+    }
+}
+
+/// A Fight Pass playlist (collection) with its VODs.
+#[derive(Clone, Debug, Serialize)]
+pub struct Playlist {
+    pub id: u64,
+    pub title: String,
+    pub desc: String,
+    pub thumb: String,
+    pub vods: Vec<BrowseItem>,
+}
+
+/// The error returned by the Fight Pass API when the access token has expired. The token needs
+/// to be refreshed before retrying the request.
+#[derive(Debug)]
+pub struct ExpiredTokenError;
+
+impl Display for ExpiredTokenError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Your login session has expired. Please log in with your UFC Fight Pass account again"
+        )
+    }
+}
+
+impl std::error::Error for ExpiredTokenError {}
+
+/// The error returned when the requested content doesn't exist in the Fight Pass (user message).
+#[derive(Debug)]
+pub struct NotFoundError(pub String);
+
+impl Display for NotFoundError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for NotFoundError {}
+
+/// The error returned when a request is invalid, so it isn't sent to the Fight Pass (user
+/// message).
+#[derive(Debug)]
+pub struct InvalidRequestError(pub String);
+
+impl Display for InvalidRequestError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidRequestError {}
+
+/// The error returned by the library search API when a search index doesn't exist (index name).
+#[derive(Debug)]
+struct MissingIndexError(String);
+
+impl Display for MissingIndexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The search index \"{}\" does not exist", self.0)
+    }
+}
+
+impl std::error::Error for MissingIndexError {}
+
+// Enums
+/// Type of the content in the Fight Pass catalogue.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BrowseItemKind {
+    Vod,
+    Playlist,
+}
+
+/// Sort order of the VOD search results.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SearchSort {
+    #[default]
+    Relevance,
+    Newest,
+    Oldest,
+}
+
+impl SearchSort {
+    /// Returns the suffix of the search index replica that's sorted in this order.
+    fn get_index_suffix(self) -> &'static str {
+        match self {
+            SearchSort::Relevance => "",
+            SearchSort::Newest => "_date_desc",
+            SearchSort::Oldest => "_date_asc",
+        }
+    }
+}
+
+// Statics
+/// Base URL of the Fight Pass front-office API. Can be overridden with the
+/// `UFCR_FIGHT_PASS_API_URL` environment variable.
+pub static FIGHT_PASS_API_URL: Lazy<String> = Lazy::new(|| {
+    env::var("UFCR_FIGHT_PASS_API_URL")
+        .unwrap_or_else(|_| "https://dce-frontoffice.imggaming.com".to_string())
+});
+/// Base URL of the Fight Pass library search API. Can be overridden with the
+/// `UFCR_FIGHT_PASS_SEARCH_URL` environment variable.
+pub static FIGHT_PASS_SEARCH_URL: Lazy<String> = Lazy::new(|| {
+    env::var("UFCR_FIGHT_PASS_SEARCH_URL")
+        .unwrap_or_else(|_| "https://h99xldr8mj-dsn.algolia.net".to_string())
+});
+/// Facets of the VOD search, with the names of their attributes in the search index.
+const SEARCH_FACETS: [(&str, &str); 4] = [
+    ("event", "event"),
+    ("weightClass", "weightClass"),
+    ("fighter", "fighters"),
+    ("category", "categories"),
+];
+/// Number of VODs requested per playlist page, and the maximum number of pages requested.
+const PLAYLIST_PAGE_SIZE: u64 = 50;
+const PLAYLIST_MAX_PAGES: u64 = 20;
+/// Attribute of the release date in the search index.
+const SEARCH_DATE_ATTRIBUTE: &str = "startDate";
+static BROWSE_PAGE_PARAMS: Lazy<String> = Lazy::new(|| {
+    form_urlencoded::Serializer::new(String::new())
+        .append_pair("bpp", "20")
+        .append_pair("rpp", "25")
+        .append_pair("displaySectionLinkBuckets", "HIDE")
+        .append_pair("displayEpgBuckets", "HIDE")
+        .append_pair("displayEmptyBucketShortcuts", "HIDE")
+        .append_pair("displayContentAvailableOnSignIn", "SHOW")
+        .append_pair("displayGeoblocked", "HIDE")
+        .finish()
+});
+static SEARCH_ATTRIBUTES: Lazy<String> = Lazy::new(|| {
+    json!([
+        "id",
+        "name",
+        "description",
+        "thumbnailUrl",
+        "duration",
+        SEARCH_DATE_ATTRIBUTE
+    ])
+    .to_string()
+});
+
+/// Generates and returns a set of request headers required by the UFC Fight Pass.
+fn generate_fight_pass_api_headers(realm: &str, api_key: &str) -> anyhow::Result<HeaderMap> {
+    let err_msg = r#"Invalid request-header configuration. Please check your "config.json" file"#;
+    let mut headers = HeaderMap::new();
+
+    headers.insert("app", "dice".parse().context(err_msg)?);
+    headers.insert("Realm", realm.parse().context(err_msg)?);
+    headers.insert("x-app-var", "6.0.1.f8add0e".parse().context(err_msg)?);
+    headers.insert("x-api-key", api_key.parse().context(err_msg)?);
+
+    Ok(headers)
+}
+
+/// Returns the error for an "Unauthorized" response. An `ExpiredTokenError` is returned if the
+/// access token has expired.
+async fn get_unauthorized_error(resp: Response, err_msg: &str) -> anyhow::Error {
+    match get_messages_from_response(resp).await {
+        Ok(messages) if messages.contains(&"Bearer token is not valid".to_string()) => {
+            anyhow!(ExpiredTokenError)
+        }
+        Ok(_) => anyhow!(
+            r#"The server responded to the request as "Unauthorized". Please try logging in with your UFC Fight Pass account again"#
+        ),
+        Err(error) => error.context(err_msg.to_string()),
+    }
+}
+
+/// Deserializes and returns the `messages` array from a response.
+async fn get_messages_from_response(resp: Response) -> anyhow::Result<Vec<String>> {
+    let resp_messages = serde_json::from_value::<Vec<String>>(
+        resp.json::<JSON>()
+            .await?
+            .try_get_mut("messages", &mut JSON::Null)
+            .take(),
+    )?;
+
+    Ok(resp_messages)
+}
+
+/// Checks if a page name is a plain slug, so it can't change the path or the query of the
+/// catalogue request it's put into.
+fn validate_page_name(page: &str) -> anyhow::Result<()> {
+    let is_slug = !page.is_empty()
+        && page
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '-' || char == '_');
+
+    if is_slug {
+        Ok(())
+    } else {
+        Err(InvalidRequestError(format!("The page name \"{page}\" is invalid")).into())
+    }
+}
+
+/*************
+ *   Tests   *
+ *************/
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde_json::json;
+
+    use super::{validate_page_name, SearchRequest, SearchResult};
+
+    #[test]
+    fn unit_create_search_params() {
+        let request = SearchRequest {
+            query: "UFC 300".into(),
+            date_from: Some(1_700_000_000),
+            max_duration: Some(3600),
+            events: vec!["UFC 300".into()],
+            fighters: vec!["Alex Pereira".into(), "Jamahal Hill".into()],
+            ..SearchRequest::default()
+        };
+        let params: HashMap<String, String> =
+            form_urlencoded::parse(request.create_search_params().as_bytes())
+                .into_owned()
+                .collect();
+
+        assert_eq!(
+            params["facetFilters"],
+            r#"["type:VOD_VIDEO",["event:UFC 300"],["fighters:Alex Pereira","fighters:Jamahal Hill"]]"#
+        );
+        assert_eq!(
+            params["numericFilters"],
+            r#"["startDate>=1700000000","duration<=3600"]"#
+        );
+        assert_eq!(params["hitsPerPage"], "12");
+        assert_eq!(params["query"], "UFC 300");
+        assert_eq!(params["restrictSearchableAttributes"], "[]");
+    }
+
+    #[test]
+    fn unit_search_result() {
+        let result = SearchResult::from_json(&json!({
+            "hits": [
+                { "id": 1, "startDate": 1_600_000_000u64 },
+                { "id": 2 },
+                { "id": 3, "startDate": 1_700_000_000u64 }
+            ],
+            "facets": {
+                "fighters": { "Alex Pereira": 2 }
+            }
+        }));
+
+        // Facets missing from the search index aren't offered as filters
+        assert_eq!(result.facets.len(), 1);
+        assert_eq!(result.facets["fighter"]["Alex Pereira"], 2);
+        assert_eq!(result.hits[1].date, None);
+
+        // Filters of the facets missing from the search index fail the search
+        let request = SearchRequest {
+            fighters: vec!["Alex Pereira".into()],
+            ..SearchRequest::default()
+        };
+        assert!(request.check_facet_filters(&result).is_ok());
+
+        let request = SearchRequest {
+            events: vec!["UFC 300".into()],
+            ..request
+        };
+        assert!(request.check_facet_filters(&result).is_err());
+    }
+
+    #[test]
+    fn unit_validate_page_name() {
+        for page in ["home", "ufc-300", "UFC_Fight_Night_240"] {
+            assert!(validate_page_name(page).is_ok());
+        }
+
+        for page in [
+            "",
+            "../account",
+            "ufc-300/..",
+            "ufc-300?rpp=1000",
+            "ufc%2F300",
+            "ufc 300",
+        ] {
+            assert!(validate_page_name(page).is_err());
+        }
+    }
+}
@@ -1,18 +1,25 @@
 // Libs
-use std::path::PathBuf;
+use std::{
+    fs as std_fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
 
 use anyhow::Context;
 use bytes::Bytes;
 use dirs::home_dir;
 use futures_util::{Stream, StreamExt};
 use path_absolutize::Absolutize;
+use regex_lite::Regex;
 use rust_embed::RustEmbed;
-use tokio::{fs, io::AsyncWriteExt};
+use serde::de::DeserializeOwned;
+use tokio::{fs, io::AsyncWriteExt, task};
 
 use crate::{
     app_util::is_container,
     config_util::{get_config, is_debug},
     rt_util::QuitUnwrap,
+    secret_util::{take_secrets, write_secret_store},
 };
 
 // Structs
@@ -21,6 +28,10 @@ use crate::{
 #[folder = "$CARGO_MANIFEST_DIR/../dist/"]
 pub struct WebAssets;
 
+// Statics
+/// Extensions of the video files that a download can produce.
+const VIDEO_EXTENSIONS: [&str; 7] = ["mp4", "mkv", "webm", "mov", "m4v", "ts", "flv"];
+
 /// Reads the config.json file from the disk and returns the content as `String`.
 /// Will create the default config file if it doesn't exist.
 pub async fn read_config_file_to_string(path: &PathBuf) -> String {
@@ -53,15 +64,21 @@ pub async fn read_config_file_to_string(path: &PathBuf) -> String {
     }
 }
 
-/// Writes the current configuration to config.json file.
-pub async fn write_config_to_file(path: &PathBuf) -> anyhow::Result<()> {
-    let mut conf_file = fs::File::create(path).await?;
+/// Writes the current configuration to config.json file through a journal file, so a crash can't
+/// leave a truncated file behind. The secrets of the configuration are saved to the encrypted
+/// secret store instead.
+pub async fn write_config_to_file(path: &Path) -> anyhow::Result<()> {
+    let mut config = get_config().as_ref().clone();
+    let secrets = take_secrets(&mut config);
 
-    conf_file
-        .write_all(serde_json::to_string_pretty(get_config().as_ref())?.as_bytes())
-        .await?;
+    write_secret_store(secrets)
+        .await
+        .context("Failed to save the credentials to the secret store")?;
 
-    Ok(())
+    let data = serde_json::to_vec_pretty(&config)?;
+    let path = path.to_path_buf();
+
+    task::spawn_blocking(move || write_file_journaled(&path, &data)).await?
 }
 
 /// Creates a backup of the config.json file in the same directory.
@@ -78,6 +95,65 @@ pub async fn create_config_backup(
     Ok(())
 }
 
+/// Returns the path to the journal file that belongs to the given file.
+fn get_journal_path(path: &Path) -> PathBuf {
+    let mut journal_path = path.as_os_str().to_owned();
+
+    journal_path.push(".journal");
+
+    PathBuf::from(journal_path)
+}
+
+/// Writes data to a file through a journal file. The data is fully written and synced to the
+/// journal first, which then replaces the original file, so an interrupted write can never leave
+/// a half-written file behind.
+pub fn write_file_journaled(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    let journal_path = get_journal_path(path);
+
+    if let Some(dir_tree) = path.parent() {
+        std_fs::create_dir_all(dir_tree)?;
+    }
+
+    let mut journal = std_fs::File::create(&journal_path)?;
+
+    journal.write_all(data)?;
+    journal.sync_all()?;
+
+    std_fs::rename(&journal_path, path)?;
+
+    Ok(())
+}
+
+/// Reads a file written by `write_file_journaled` and deserializes it. A complete journal left
+/// behind by an interrupted write is committed before reading, while an incomplete one is
+/// discarded. Returns `None` if the file doesn't exist.
+pub async fn read_journaled_file<T>(path: &Path) -> anyhow::Result<Option<T>>
+where
+    T: DeserializeOwned,
+{
+    let journal_path = get_journal_path(path);
+
+    if journal_path.exists() {
+        let journal = fs::read_to_string(&journal_path).await?;
+
+        if let Ok(data) = serde_json::from_str::<T>(&journal) {
+            fs::rename(&journal_path, path).await?;
+
+            return Ok(Some(data));
+        }
+
+        fs::remove_file(&journal_path).await?;
+    }
+
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let data = serde_json::from_str(&fs::read_to_string(path).await?)?;
+
+    Ok(Some(data))
+}
+
 /// Creates a file on the disk using the given byte-stream.
 pub async fn write_file_to_disk<S>(
     path: PathBuf,
@@ -118,6 +194,118 @@ where
     Ok(())
 }
 
+/// Returns the directories that a download could write files to, according to the configuration.
+pub fn get_download_dirs() -> Vec<PathBuf> {
+    let config = get_config();
+    let mut dirs = vec![PathBuf::from(&config.dl_path)];
+
+    if config.use_temp_path && !config.temp_path.is_empty() {
+        dirs.push(PathBuf::from(&config.temp_path));
+    }
+
+    dirs
+}
+
+/// Splits the output path of a download (without the extension) into the directory of the download
+/// inside the given directory, and the file name without the extension.
+fn split_output_path(dir: &Path, out_base: &str) -> (PathBuf, String) {
+    match out_base.rsplit_once('/') {
+        Some((sub_dirs, file_name)) => (dir.join(sub_dirs), file_name.to_string()),
+        None => (dir.to_path_buf(), out_base.to_string()),
+    }
+}
+
+/// Removes the partially downloaded files of a download (`.part`, `.ytdl`, fragments and unmerged
+/// formats) from the given directories, and returns the number of files removed.
+pub async fn remove_partial_downloads(dirs: &[PathBuf], out_base: &str) -> anyhow::Result<usize> {
+    let mut removed = 0;
+
+    for dir in dirs {
+        let (dir, file_name) = split_output_path(dir, out_base);
+        let prefix = format!("{file_name}.");
+
+        // A directory that doesn't exist can't contain any partial files
+        let Ok(mut entries) = fs::read_dir(&dir).await else {
+            continue;
+        };
+
+        while let Some(entry) = entries.next_entry().await? {
+            let filename = entry.file_name();
+            let filename = filename.to_string_lossy();
+
+            if let Some(suffix) = filename.strip_prefix(&prefix) {
+                if is_partial_download_suffix(suffix) {
+                    fs::remove_file(entry.path()).await?;
+                    removed += 1;
+                }
+            }
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Finds the final output file of a download in the given directory, ignoring any partially
+/// downloaded files and the files saved next to it. Returns `None` if there's no such file.
+pub async fn find_download_output(
+    dir: &Path,
+    out_base: &str,
+    merge_ext: &str,
+) -> anyhow::Result<Option<PathBuf>> {
+    let (dir, file_name) = split_output_path(dir, out_base);
+    let mut entries = fs::read_dir(&dir).await?;
+    let mut filenames = Vec::new();
+
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_file() {
+            filenames.push(entry.file_name().to_string_lossy().to_string());
+        }
+    }
+
+    Ok(
+        select_download_output(&filenames, &file_name, merge_ext)
+            .map(|filename| dir.join(filename)),
+    )
+}
+
+/// Selects the output file of a download from the names of the files in its directory. Only video
+/// files are considered, so subtitles and library files (`.nfo`, posters) with the same name are
+/// never picked, and a file with the merge extension is preferred.
+fn select_download_output<'a>(
+    filenames: &'a [String],
+    file_name: &str,
+    merge_ext: &str,
+) -> Option<&'a String> {
+    let prefix = format!("{file_name}.");
+    let outputs: Vec<(&String, &str)> = filenames
+        .iter()
+        .filter_map(|filename| {
+            let ext = filename.strip_prefix(&prefix)?;
+            let is_video = VIDEO_EXTENSIONS.contains(&ext.to_lowercase().as_str());
+
+            is_video.then_some((filename, ext))
+        })
+        .collect();
+
+    outputs
+        .iter()
+        .find(|(_, ext)| ext.eq_ignore_ascii_case(merge_ext))
+        .or_else(|| outputs.first())
+        .map(|(filename, _)| *filename)
+}
+
+/// Checks if the part of a filename that follows the title belongs to a partially downloaded file.
+/// Only the names `yt-dlp` uses are matched: `<ext>.part`, `<ext>.ytdl`, fragments
+/// (`<ext>.part-Frag<n>`), and unmerged formats (`f<format ID>.<ext>`) with their own partial
+/// files. Format IDs always contain a number, like `137` or `hls-4628`.
+fn is_partial_download_suffix(suffix: &str) -> bool {
+    Regex::new(
+        r"^(f[0-9A-Za-z_-]*[0-9][0-9A-Za-z_-]*\.[0-9A-Za-z]+(\.part|\.ytdl|\.part-Frag[0-9]+(\.part)?)?|[0-9A-Za-z]+(\.part|\.ytdl|\.part-Frag[0-9]+(\.part)?))$",
+    )
+    .unwrap()
+    .is_match(suffix)
+}
+
 /// Opens the downloads directory in the default file explorer.
 pub fn open_downloads_dir() -> anyhow::Result<()> {
     open::that_detached(&get_config().dl_path)
@@ -148,3 +336,67 @@ pub fn build_downloads_dir_path(org_dl_path: String) -> anyhow::Result<String> {
         Ok(org_dl_path)
     }
 }
+
+/*************
+ *   Tests   *
+ *************/
+
+#[cfg(test)]
+mod tests {
+    use super::{is_partial_download_suffix, select_download_output};
+
+    #[test]
+    fn unit_select_download_output() {
+        let filenames: Vec<String> = [
+            "UFC 300.nfo",
+            "UFC 300.jpg",
+            "UFC 300.en.vtt",
+            "UFC 300.f137.mp4",
+            "UFC 300.mp4.part",
+            "UFC 300.webm",
+            "UFC 300.mp4",
+            "UFC 301.mp4",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+        assert_eq!(
+            select_download_output(&filenames, "UFC 300", "mp4").unwrap(),
+            "UFC 300.mp4"
+        );
+        assert_eq!(
+            select_download_output(&filenames, "UFC 300", "mkv").unwrap(),
+            "UFC 300.webm"
+        );
+        assert!(select_download_output(&filenames[..5], "UFC 300", "mp4").is_none());
+    }
+
+    #[test]
+    fn unit_is_partial_download_suffix() {
+        for suffix in [
+            "mp4.part",
+            "mp4.ytdl",
+            "mp4.part-Frag12",
+            "mp4.part-Frag12.part",
+            "f137.mp4",
+            "fhls-4628.mp4.part",
+            "fhls-audio-128000.m4a.part-Frag3",
+        ] {
+            assert!(is_partial_download_suffix(suffix), "{suffix}");
+        }
+
+        // Finished downloads and the files saved next to them are kept
+        for suffix in [
+            "mp4",
+            "final.mkv",
+            "en.vtt",
+            "temp.mp4",
+            "mp4.partial",
+            "mp4.part-Frag",
+            "mp4.bak.part",
+        ] {
+            assert!(!is_partial_download_suffix(suffix), "{suffix}");
+        }
+    }
+}
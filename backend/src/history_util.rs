@@ -0,0 +1,354 @@
+// Libs
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
+
+use anyhow::{anyhow, Context};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tokio::task;
+
+use ufcr_libs::log_warn;
+
+use crate::{
+    app_util::get_app_root_dir, fs_util::write_file_journaled, net_util::get_unix_time,
+    rt_util::QuitUnwrap, state_util::Vod,
+};
+
+// Structs
+/// A completed download, as it's recorded in the download history.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    /// Fight Pass ID of the VOD.
+    pub id: u64,
+    pub title: String,
+    /// `yt-dlp` format used for the download.
+    pub format: String,
+    pub out_path: String,
+    /// Size of the output file in bytes.
+    pub size: u64,
+    /// Completion date of the download as a Unix timestamp.
+    pub date: u64,
+    #[serde(default)]
+    pub account: String,
+}
+
+/// Filters of a download history query. Empty filters match every entry.
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HistoryQuery {
+    /// Case-insensitive part of the title.
+    pub query: String,
+    pub id: Option<u64>,
+    /// Earliest and latest completion dates as Unix timestamps.
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    /// Maximum number of entries returned, starting from the newest.
+    pub limit: Option<usize>,
+}
+
+impl HistoryQuery {
+    /// Checks if a history entry matches the filters.
+    fn matches(&self, entry: &HistoryEntry) -> bool {
+        (self.query.is_empty()
+            || entry
+                .title
+                .to_lowercase()
+                .contains(&self.query.to_lowercase()))
+            && self.id.map_or(true, |id| entry.id == id)
+            && self.from.map_or(true, |from| entry.date >= from)
+            && self.to.map_or(true, |to| entry.date <= to)
+    }
+}
+
+/// Selects the entries removed from the download history. An entry is removed if it matches any
+/// of the given criteria.
+#[derive(Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PruneRequest {
+    /// Fight Pass IDs of the VODs to forget.
+    pub ids: Vec<u64>,
+    /// Removes the entries completed before this Unix timestamp.
+    pub before: Option<u64>,
+    /// Removes the entries whose output files no longer exist.
+    pub missing_files: bool,
+}
+
+impl PruneRequest {
+    /// Checks if a history entry should be removed.
+    fn matches(&self, entry: &HistoryEntry) -> bool {
+        self.ids.contains(&entry.id)
+            || self.before.is_some_and(|before| entry.date < before)
+            || (self.missing_files && !Path::new(&entry.out_path).exists())
+    }
+}
+
+// Enums
+/// File formats of an exported download history.
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+// Statics
+pub static HISTORY_PATH: Lazy<PathBuf> =
+    Lazy::new(|| get_app_root_dir().join("config").join("history.jsonl"));
+/// Serializes the access to the download history file.
+static HISTORY_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// Locks and returns a `MutexGuard` for the download history file.
+fn lock_history() -> MutexGuard<'static, ()> {
+    HISTORY_LOCK
+        .lock()
+        .unwrap_or_quit("Failed to exclusively access the download history")
+}
+
+/// Runs a function that accesses the download history file on a blocking thread, while the file
+/// is locked.
+async fn with_history<T, F>(access: F) -> anyhow::Result<T>
+where
+    F: FnOnce() -> anyhow::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    task::spawn_blocking(move || {
+        let _lock = lock_history();
+
+        access()
+    })
+    .await
+    .context("The download history task exited unexpectedly")?
+}
+
+/// Appends a completed download to the download history.
+pub async fn record_download(entry: HistoryEntry) -> anyhow::Result<()> {
+    let mut line =
+        serde_json::to_string(&entry).context("Failed to serialize the history entry")?;
+
+    line.push('\n');
+
+    with_history(move || {
+        if let Some(dir_tree) = HISTORY_PATH.parent() {
+            fs::create_dir_all(dir_tree)?;
+        }
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(HISTORY_PATH.as_path())
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .context("Failed to write to the download history")
+    })
+    .await
+}
+
+/// Creates a history entry for a completed download of a VOD.
+pub async fn create_history_entry(vod: &Vod, format: &str, output: &Path) -> HistoryEntry {
+    HistoryEntry {
+        id: vod.id,
+        title: vod.title.clone(),
+        format: format.to_string(),
+        out_path: output.to_string_lossy().to_string(),
+        size: tokio::fs::metadata(output)
+            .await
+            .map_or(0, |meta| meta.len()),
+        date: get_unix_time(),
+        account: vod.account.clone(),
+    }
+}
+
+/// Reads every entry of the download history, from the oldest to the newest. Lines that can't be
+/// read are skipped, so a damaged line doesn't hide the rest of the history.
+fn read_history() -> anyhow::Result<Vec<HistoryEntry>> {
+    if !HISTORY_PATH.exists() {
+        return Ok(Vec::new());
+    }
+
+    let history = fs::read_to_string(HISTORY_PATH.as_path())
+        .context("Failed to read the download history")?;
+    let mut entries = Vec::new();
+
+    for (idx, line) in history.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match serde_json::from_str(line) {
+            Ok(entry) => entries.push(entry),
+            Err(error) => log_warn!(
+                "Skipping line {} of the download history:\n{error}\n",
+                idx + 1
+            ),
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Returns the latest download of a VOD from the download history, if it was downloaded before.
+pub async fn find_downloaded_vod(vod_id: u64) -> anyhow::Result<Option<HistoryEntry>> {
+    with_history(move || {
+        Ok(read_history()?
+            .into_iter()
+            .rev()
+            .find(|entry| entry.id == vod_id))
+    })
+    .await
+}
+
+/// Returns the entries of the download history matching the query, from the newest to the oldest.
+pub async fn query_history(query: HistoryQuery) -> anyhow::Result<Vec<HistoryEntry>> {
+    with_history(move || {
+        Ok(read_history()?
+            .into_iter()
+            .rev()
+            .filter(|entry| query.matches(entry))
+            .take(query.limit.unwrap_or(usize::MAX))
+            .collect())
+    })
+    .await
+}
+
+/// Exports the whole download history in the given format.
+pub async fn export_history(format: ExportFormat) -> anyhow::Result<String> {
+    let entries = query_history(HistoryQuery::default()).await?;
+
+    match format {
+        ExportFormat::Json => {
+            serde_json::to_string_pretty(&entries).context("Failed to serialize the history")
+        }
+        ExportFormat::Csv => Ok(create_history_csv(&entries)),
+    }
+}
+
+/// Creates a CSV document with a header row from history entries.
+fn create_history_csv(entries: &[HistoryEntry]) -> String {
+    let mut csv = String::from("id,title,format,outPath,size,date,account\n");
+
+    for entry in entries {
+        let row = [
+            entry.id.to_string(),
+            escape_csv_field(&entry.title),
+            escape_csv_field(&entry.format),
+            escape_csv_field(&entry.out_path),
+            entry.size.to_string(),
+            entry.date.to_string(),
+            escape_csv_field(&entry.account),
+        ];
+
+        csv.push_str(&row.join(","));
+        csv.push('\n');
+    }
+
+    csv
+}
+
+/// Quotes a CSV field if it contains separators, quotes or line breaks. Fields that spreadsheet
+/// applications would run as formulas are prefixed with an apostrophe.
+fn escape_csv_field(field: &str) -> String {
+    let field = if field.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{field}")
+    } else {
+        field.to_string()
+    };
+
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+/// Removes the matching entries from the download history and returns the number of removed
+/// entries.
+pub async fn prune_history(request: PruneRequest) -> anyhow::Result<usize> {
+    if request.ids.is_empty() && request.before.is_none() && !request.missing_files {
+        return Err(anyhow!(
+            "No entries were selected to be removed from the history"
+        ));
+    }
+
+    with_history(move || {
+        let (removed, kept): (Vec<HistoryEntry>, Vec<HistoryEntry>) = read_history()?
+            .into_iter()
+            .partition(|entry| request.matches(entry));
+        let mut data = Vec::new();
+
+        for entry in &kept {
+            serde_json::to_writer(&mut data, entry)?;
+            data.push(b'\n');
+        }
+
+        write_file_journaled(&HISTORY_PATH, &data)
+            .context("Failed to save the download history")?;
+
+        Ok(removed.len())
+    })
+    .await
+}
+
+/*************
+ *   Tests   *
+ *************/
+
+#[cfg(test)]
+mod tests {
+    use super::{create_history_csv, escape_csv_field, HistoryEntry, HistoryQuery, PruneRequest};
+
+    #[test]
+    fn unit_history_filters() {
+        let entry = HistoryEntry {
+            id: 123_456,
+            title: "UFC 300: Pereira vs. Hill".into(),
+            format: "bestvideo+bestaudio".into(),
+            out_path: "/nonexistent/UFC 300.mp4".into(),
+            size: 1024,
+            date: 1_713_000_000,
+            account: String::new(),
+        };
+
+        assert!(HistoryQuery::default().matches(&entry));
+        assert!(HistoryQuery {
+            query: "pereira".into(),
+            from: Some(1_712_000_000),
+            ..HistoryQuery::default()
+        }
+        .matches(&entry));
+        assert!(!HistoryQuery {
+            id: Some(1),
+            ..HistoryQuery::default()
+        }
+        .matches(&entry));
+
+        assert!(!PruneRequest {
+            before: Some(1_712_000_000),
+            ..PruneRequest::default()
+        }
+        .matches(&entry));
+        assert!(PruneRequest {
+            missing_files: true,
+            ..PruneRequest::default()
+        }
+        .matches(&entry));
+
+        assert_eq!(
+            create_history_csv(&[entry]),
+            "id,title,format,outPath,size,date,account\n\
+            123456,UFC 300: Pereira vs. Hill,bestvideo+bestaudio,/nonexistent/UFC 300.mp4,1024,\
+            1713000000,\n"
+        );
+
+        assert_eq!(
+            escape_csv_field("=HYPERLINK(\"x\")"),
+            "\"'=HYPERLINK(\"\"x\"\")\""
+        );
+        assert_eq!(escape_csv_field("@SUM(A1)"), "'@SUM(A1)");
+        assert_eq!(escape_csv_field("-1+1"), "'-1+1");
+    }
+}
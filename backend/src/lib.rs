@@ -1,10 +1,24 @@
 // Module exports
+mod api_util;
 pub mod app_util;
+mod auth_util;
 mod bin_util;
+pub mod cli_util;
 pub mod config_util;
+pub mod fp_util;
 pub mod net_util;
 pub mod rt_util;
-mod state_util;
+pub mod sched_util;
+pub mod state_util;
+pub mod watch_util;
 mod ws_util;
+mod dl_util;
 mod fs_util;
+mod history_util;
+mod library_util;
+mod metadata_util;
+mod postproc_util;
+mod secret_util;
+mod template_util;
 mod txt_util;
+mod verify_util;
@@ -0,0 +1,139 @@
+// Libs
+use std::{fmt::Write, path::Path};
+
+use anyhow::Context;
+use tokio::fs;
+
+use crate::{
+    net_util::download_vod_thumbnail,
+    state_util::Vod,
+    template_util::{format_date, get_series_name},
+};
+
+// Statics
+/// File name of the poster image in the directory of a VOD.
+const POSTER_FILENAME: &str = "poster.jpg";
+
+/// Writes the metadata file (`.nfo`) and the poster image of a downloaded VOD next to its output
+/// file, so media servers like Plex and Jellyfin can pick up its metadata.
+pub async fn write_library_files(vod: &Vod, output: &Path) -> anyhow::Result<()> {
+    fs::write(output.with_extension("nfo"), create_nfo(vod))
+        .await
+        .context("Failed to write the metadata file of the download")?;
+
+    if !vod.thumb.is_empty() {
+        let poster = download_vod_thumbnail(&vod.thumb).await?;
+        let poster_path = output
+            .parent()
+            .context("Invalid output path")?
+            .join(POSTER_FILENAME);
+
+        fs::write(poster_path, poster)
+            .await
+            .context("Failed to write the poster image of the download")?;
+    }
+
+    Ok(())
+}
+
+/// Creates the Kodi style `.nfo` metadata of a VOD, which is supported by most media servers.
+fn create_nfo(vod: &Vod) -> String {
+    let title = vod.title.as_str();
+    let mut nfo = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n");
+
+    nfo.push_str("<movie>\n");
+    write_nfo_tag(&mut nfo, "title", title);
+    write_nfo_tag(&mut nfo, "plot", &vod.desc);
+    write_nfo_tag(&mut nfo, "set", get_series_name(title));
+
+    if let Some(date) = vod.date.map(format_date) {
+        write_nfo_tag(&mut nfo, "premiered", &date);
+        write_nfo_tag(&mut nfo, "year", &date[..4]);
+    }
+
+    if vod.duration > 0 {
+        write_nfo_tag(&mut nfo, "runtime", &(vod.duration / 60).to_string());
+    }
+
+    for fighter in get_fighter_names(title) {
+        nfo.push_str("  <actor>\n  ");
+        write_nfo_tag(&mut nfo, "name", fighter);
+        nfo.push_str("  </actor>\n");
+    }
+
+    if !vod.thumb.is_empty() {
+        write_nfo_tag(&mut nfo, "thumb", &vod.thumb);
+    }
+
+    let _ = writeln!(
+        nfo,
+        "  <uniqueid type=\"ufcfightpass\" default=\"true\">{}</uniqueid>",
+        vod.id
+    );
+    nfo.push_str("</movie>\n");
+
+    nfo
+}
+
+/// Appends an XML element with escaped text to the `.nfo` metadata.
+fn write_nfo_tag(nfo: &mut String, tag: &str, text: &str) {
+    let _ = writeln!(nfo, "  <{tag}>{}</{tag}>", escape_xml(text));
+}
+
+/// Escapes the characters with a special meaning in XML.
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Returns the names of the fighters of a bout from a VOD's title, which is the part after the
+/// event name ("UFC 300: Pereira vs. Hill" -> ["Pereira", "Hill"]).
+fn get_fighter_names(title: &str) -> Vec<&str> {
+    let Some((_, bout)) = title.split_once(':') else {
+        return Vec::new();
+    };
+
+    if !bout.contains(" vs") {
+        return Vec::new();
+    }
+
+    bout.split(" vs. ")
+        .flat_map(|part| part.split(" vs "))
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/*************
+ *   Tests   *
+ *************/
+
+#[cfg(test)]
+mod tests {
+    use crate::state_util::Vod;
+
+    use super::{create_nfo, get_fighter_names};
+
+    #[test]
+    fn unit_create_nfo() {
+        let vod = Vod {
+            id: 123_456,
+            title: "UFC 300: Pereira vs. Hill".into(),
+            desc: "Light heavyweight title bout & more".into(),
+            duration: 3600,
+            date: Some(1_713_052_800),
+            ..Vod::default()
+        };
+        let nfo = create_nfo(&vod);
+
+        assert_eq!(get_fighter_names(&vod.title), ["Pereira", "Hill"]);
+        assert!(get_fighter_names("UFC 300 Embedded").is_empty());
+        assert!(nfo.contains("  <plot>Light heavyweight title bout &amp; more</plot>\n"));
+        assert!(nfo.contains("  <set>UFC</set>\n  <premiered>2024-04-14</premiered>\n"));
+        assert!(nfo.contains("  <runtime>60</runtime>\n"));
+        assert!(nfo.contains("  <actor>\n    <name>Hill</name>\n  </actor>\n"));
+    }
+}
@@ -1,15 +1,33 @@
 // Libs
+use std::process::ExitCode;
+
+use clap::Parser;
 use ufcr_util::{
     app_util::is_container,
+    cli_util::{run_cli, CLIArgs},
     config_util::{is_debug, load_config},
-    net_util::init_server,
-    rt_util::{ExitHandler, set_custom_panic}
+    net_util::{init_server, init_token_refresher},
+    rt_util::{ExitHandler, set_custom_panic},
+    sched_util::init_scheduler,
+    state_util::load_dlq,
+    watch_util::init_watchlist_poller,
 };
 
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     set_custom_panic(true);
 
+    // Unknown arguments are rejected on purpose, so mistyped commands don't start the GUI instead
+    let cli_args = CLIArgs::parse();
+
+    #[cfg(target_os = "windows")]
+    ufcr_libs::log_util::enable_win32_conhost_support();
+
+    // Commands run headless, without the GUI server or halting the process on exit
+    if let Some(command) = cli_args.command {
+        return run_cli(command).await.into();
+    }
+
     // This needs to be here, so it would be the last thing that will be dropped
     let _exit_handler = if is_container() {
         None
@@ -17,14 +35,18 @@ async fn main() {
         Some(ExitHandler)
     };
 
-    #[cfg(target_os = "windows")]
-    ufcr_libs::log_util::enable_win32_conhost_support();
     start_ufcr().await;
+
+    ExitCode::SUCCESS
 }
 
 /// Initializes the configuration and starts the application process.
 async fn start_ufcr() {
     load_config().await;
     set_custom_panic(is_debug());
+    load_dlq().await;
+    init_scheduler();
+    init_token_refresher();
+    init_watchlist_poller();
     init_server().await;
 }
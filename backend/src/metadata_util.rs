@@ -0,0 +1,276 @@
+// Libs
+use std::{ffi::OsString, fmt::Write, path::Path, process::Stdio};
+
+use anyhow::{anyhow, Context};
+use tokio::{fs, process::Command};
+
+use crate::{
+    bin_util::BINS, net_util::download_vod_thumbnail, state_util::Vod, template_util::format_date,
+};
+
+// Statics
+/// Extensions of the containers that can hold cover art and chapters.
+const COVER_ART_EXTENSIONS: [&str; 4] = ["mp4", "m4v", "mov", "mkv"];
+
+/// Creates the `yt-dlp` arguments that embed the metadata of a VOD into the downloaded file.
+///
+/// `yt-dlp` only knows the HLS stream of a download, so every field is set from a literal value
+/// with `--parse-metadata`, and the signed stream URL is replaced with the VOD's URL.
+pub fn create_metadata_args(vod: &Vod) -> Vec<String> {
+    let date = vod.date.map(format_date).unwrap_or_default();
+    let fields = [
+        ("meta_title", vod.title.as_str()),
+        ("meta_description", vod.desc.as_str()),
+        ("meta_synopsis", vod.desc.as_str()),
+        ("meta_date", date.as_str()),
+        ("meta_comment", vod.vod_url.as_str()),
+        ("meta_purl", vod.vod_url.as_str()),
+    ];
+    let mut args = vec!["--embed-metadata".to_string()];
+
+    for (field, value) in fields {
+        let value = escape_metadata_value(value);
+
+        if !value.is_empty() {
+            args.push("--parse-metadata".to_string());
+            args.push(format!("{value}:(?P<{field}>.+)"));
+        }
+    }
+
+    args
+}
+
+/// Escapes a literal value for the `FROM` part of `--parse-metadata`, which is an output template
+/// that ends at the first unescaped colon and can't span multiple lines.
+fn escape_metadata_value(value: &str) -> String {
+    let value = value
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .trim_end_matches('\\')
+        .replace('%', "%%")
+        .replace(':', "\\:");
+
+    // A single word would be read as the name of a field instead
+    if !value.is_empty()
+        && value
+            .chars()
+            .all(|char| char.is_ascii_alphabetic() || char == '_')
+    {
+        format!("%(ufcr_literal|{value})s")
+    } else {
+        value
+    }
+}
+
+/// Embeds the thumbnail as cover art and the bout and round markers as chapters into a downloaded
+/// file. Only MP4 and Matroska files get cover art.
+pub async fn embed_cover_and_chapters(vod: &Vod, output: &Path) -> anyhow::Result<()> {
+    let ext = output
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    let chapters = create_chapters_metadata(vod);
+    let cover = if COVER_ART_EXTENSIONS.contains(&ext.as_str()) && !vod.thumb.is_empty() {
+        Some(download_vod_thumbnail(&vod.thumb).await?)
+    } else {
+        None
+    };
+
+    if chapters.is_none() && cover.is_none() {
+        return Ok(());
+    }
+
+    let chapters_path = output.with_extension("chapters.txt");
+    let cover_path = output.with_extension("cover.jpg");
+    let temp_output = output.with_extension(format!("embed.{ext}"));
+    let result = async {
+        let mut args: Vec<OsString> = vec![
+            "-y".into(),
+            "-loglevel".into(),
+            "error".into(),
+            "-i".into(),
+            output.into(),
+        ];
+        let mut map_args: Vec<OsString> = vec!["-map".into(), "0".into()];
+
+        if let Some(chapters) = &chapters {
+            fs::write(&chapters_path, chapters)
+                .await
+                .context("Failed to write the chapters")?;
+            args.extend(["-i".into(), (&chapters_path).into()]);
+            map_args.extend(["-map_chapters".into(), "1".into()]);
+        }
+
+        if let Some(cover) = &cover {
+            fs::write(&cover_path, cover)
+                .await
+                .context("Failed to write the cover art")?;
+
+            if ext == "mkv" {
+                map_args.extend([
+                    "-attach".into(),
+                    (&cover_path).into(),
+                    "-metadata:s:t".into(),
+                    "mimetype=image/jpeg".into(),
+                    "-metadata:s:t".into(),
+                    "filename=cover.jpg".into(),
+                ]);
+            } else {
+                let input_idx = if chapters.is_some() { "2" } else { "1" };
+
+                args.extend(["-i".into(), (&cover_path).into()]);
+                map_args.extend([
+                    "-map".into(),
+                    input_idx.into(),
+                    "-disposition:v:1".into(),
+                    "attached_pic".into(),
+                ]);
+            }
+        }
+
+        args.extend(map_args);
+        args.extend(["-c".into(), "copy".into(), (&temp_output).into()]);
+
+        run_ffmpeg(&args).await?;
+        fs::rename(&temp_output, output)
+            .await
+            .context("Failed to replace the download with the embedded file")?;
+
+        Ok::<(), anyhow::Error>(())
+    }
+    .await;
+
+    for path in [&chapters_path, &cover_path, &temp_output] {
+        let _ = fs::remove_file(path).await;
+    }
+
+    result
+}
+
+/// Runs `ffmpeg` with the given arguments till it exits.
+pub async fn run_ffmpeg(args: &[OsString]) -> anyhow::Result<()> {
+    let ffmpeg = Command::new(BINS.ffmpeg.get_path())
+        .args(args)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .context("An error occurred while trying to launch ffmpeg")?;
+
+    if ffmpeg.status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "ffmpeg exited with an error:\n{}",
+            String::from_utf8_lossy(&ffmpeg.stderr).trim()
+        ))
+    }
+}
+
+/// Creates an `ffmetadata` document with the chapters of a VOD for `ffmpeg`, if the VOD has any.
+/// Each chapter ends where the next one starts, and the last one ends with the VOD.
+fn create_chapters_metadata(vod: &Vod) -> Option<String> {
+    if vod.chapters.is_empty() {
+        return None;
+    }
+
+    let mut chapters = vod.chapters.clone();
+    let mut metadata = String::from(";FFMETADATA1\n");
+
+    chapters.sort_by_key(|chapter| chapter.start);
+
+    for (idx, chapter) in chapters.iter().enumerate() {
+        let end = chapters
+            .get(idx + 1)
+            .map_or(vod.duration, |next| next.start)
+            .max(chapter.start);
+
+        let _ = write!(
+            metadata,
+            "[CHAPTER]\nTIMEBASE=1/1\nSTART={}\nEND={end}\ntitle={}\n",
+            chapter.start,
+            escape_ffmetadata_value(&chapter.title)
+        );
+    }
+
+    Some(metadata)
+}
+
+/// Escapes the characters with a special meaning in `ffmetadata` documents.
+fn escape_ffmetadata_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for char in value.chars() {
+        if matches!(char, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+
+        escaped.push(char);
+    }
+
+    escaped
+}
+
+/*************
+ *   Tests   *
+ *************/
+
+#[cfg(test)]
+mod tests {
+    use crate::state_util::{Vod, VodChapter};
+
+    use super::{create_chapters_metadata, create_metadata_args};
+
+    #[test]
+    fn unit_create_metadata_args() {
+        let vod = Vod {
+            title: "UFC 300: Pereira vs. Hill".into(),
+            desc: "Prelims".into(),
+            date: Some(1_713_052_800),
+            ..Vod::default()
+        };
+
+        assert_eq!(
+            create_metadata_args(&vod),
+            [
+                "--embed-metadata",
+                "--parse-metadata",
+                "UFC 300\\: Pereira vs. Hill:(?P<meta_title>.+)",
+                "--parse-metadata",
+                "%(ufcr_literal|Prelims)s:(?P<meta_description>.+)",
+                "--parse-metadata",
+                "%(ufcr_literal|Prelims)s:(?P<meta_synopsis>.+)",
+                "--parse-metadata",
+                "2024-04-14:(?P<meta_date>.+)",
+            ]
+        );
+    }
+
+    #[test]
+    fn unit_create_chapters_metadata() {
+        let vod = Vod {
+            duration: 900,
+            chapters: vec![
+                VodChapter {
+                    title: "Round 2".into(),
+                    start: 300,
+                },
+                VodChapter {
+                    title: "Round 1; Walkouts".into(),
+                    start: 0,
+                },
+            ],
+            ..Vod::default()
+        };
+
+        assert!(create_chapters_metadata(&Vod::default()).is_none());
+        assert_eq!(
+            create_chapters_metadata(&vod).unwrap(),
+            ";FFMETADATA1\n\
+            [CHAPTER]\nTIMEBASE=1/1\nSTART=0\nEND=300\ntitle=Round 1\\; Walkouts\n\
+            [CHAPTER]\nTIMEBASE=1/1\nSTART=300\nEND=900\ntitle=Round 2\n"
+        );
+    }
+}
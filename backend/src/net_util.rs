@@ -1,37 +1,53 @@
 #![allow(clippy::missing_errors_doc)]
 
 // Libs
-use std::{net::SocketAddr, sync::Arc};
+use std::{
+    future::Future,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use anyhow::{anyhow, Context};
 use arc_swap::ArcSwap;
 use axum::{
     body::Body,
     http::{header, Method, StatusCode},
+    middleware,
     response::IntoResponse,
     routing::get,
     Router,
 };
 use axum_embed::{FallbackBehavior::Redirect, ServeEmbed};
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 use reqwest::{
-    header::{HeaderMap, CACHE_CONTROL, PRAGMA},
-    Client, Proxy, Response,
+    header::{CACHE_CONTROL, PRAGMA},
+    Client, Proxy,
 };
-use serde_json::{json, value::Index, Value};
-use tokio::net::TcpListener;
+use serde::Deserialize;
+use serde_json::{value::Index, Value};
+use tokio::{net::TcpListener, sync::Mutex, task::JoinHandle, time::sleep};
 use tower_http::cors::{Any, CorsLayer};
 
-use ufcr_libs::{log_err, log_success};
+use ufcr_libs::{log_err, log_success, log_warn};
 
 use crate::{
+    api_util::create_api_router,
     app_util::{get_app_metadata, get_os_arch, get_os_id, is_container},
+    auth_util::{create_auth_router, handle_auth},
     bin_util::BINS,
-    config_util::{get_config, is_debug, update_config, ConfigUpdate, UFCRConfig},
+    config_util::{
+        get_config, get_redacted_config, is_debug, update_config, AccountProfile, ConfigUpdate,
+        UFCRConfig,
+    },
+    fp_util::{
+        BrowsePage, ExpiredTokenError, FightPassApi, InvalidRequestError, Playlist, SearchRequest,
+        SearchResult, FIGHT_PASS_API_URL, FIGHT_PASS_SEARCH_URL,
+    },
     fs_util::{write_file_to_disk, WebAssets},
     rt_util::QuitUnwrap,
-    state_util::Vod,
-    txt_util::get_vod_id_from_url,
+    state_util::{Vod, VodChapter},
+    txt_util::{create_vod_url, get_jwt_expiry, get_vod_id_from_url},
     ws_util::{create_ws_layer, emit_config_update},
 };
 
@@ -46,6 +62,16 @@ pub struct LoginSession {
 // Types
 pub type JSON = Value;
 
+// Enums
+/// A playlist or an event page, whose VODs can be downloaded at once.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", content = "id", rename_all = "camelCase")]
+pub enum Collection {
+    Playlist(u64),
+    /// An event page, identified by the path of the page.
+    Event(String),
+}
+
 // Traits
 /// Allows getting a value out of a JSON using index but returns a `Value` instead of an Option.
 /// Returns `Value::Null` if failed to grab the value at index.
@@ -66,21 +92,21 @@ impl JsonTryGet for JSON {
 }
 
 // Statics
+/// Seconds before the expiry of the access token, when it should be refreshed.
+const TOKEN_REFRESH_MARGIN: u64 = 300;
+/// Limits for the delay between access token expiry checks, in seconds.
+const TOKEN_CHECK_MIN_DELAY: u64 = 30;
+const TOKEN_CHECK_MAX_DELAY: u64 = 900;
+/// Seconds to wait before retrying a failed background access token refresh.
+const TOKEN_REFRESH_RETRY_DELAY: u64 = 300;
 static HTTP_CLIENT: Lazy<Client> = Lazy::new(Client::new);
 static HTTP_PROXIED_CLIENT: Lazy<ArcSwap<Client>> = Lazy::new(|| {
     ArcSwap::from_pointee(create_proxied_client().expect("Failed to create a proxied HTTP client"))
 });
-static VOD_SEARCH_PARAMS: Lazy<String> = Lazy::new(|| {
-    form_urlencoded::Serializer::new(String::new())
-        .append_pair("facetFilters", r#"["type:VOD_VIDEO"]"#)
-        .append_pair("hitsPerPage", "12")
-        .append_pair("advancedSyntax", "true")
-        .append_pair(
-            "attributesToRetrieve",
-            r#"["id","description","thumbnailUrl","duration"]"#,
-        )
-        .finish()
-});
+/// Serializes access token refreshes, so concurrent requests don't refresh the same token.
+static TOKEN_REFRESH_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+/// Holds the handle to the background access token refresher task.
+static TOKEN_REFRESHER: OnceCell<JoinHandle<()>> = OnceCell::new();
 
 /// Creates a new server that serves the UFC Ripper GUI and the `WebSocket` server.
 ///
@@ -92,9 +118,17 @@ pub async fn init_server() {
     let UFCRConfig {
         port,
         open_in_browser,
+        gui_auth,
         ..
     } = config.as_ref();
 
+    if gui_auth.enabled && gui_auth.password_hash.is_empty() && gui_auth.token_hash.is_empty() {
+        log_warn!(
+            "GUI authentication is enabled without a password or an access token. \
+            Use the \"auth\" command to set them up\n"
+        );
+    }
+
     let index_file = Some("index.html".to_string());
     let web_assets =
         ServeEmbed::<WebAssets>::with_parameters(index_file.clone(), Redirect, index_file);
@@ -103,7 +137,10 @@ pub async fn init_server() {
     let app = Router::new()
         .nest_service("/", web_assets)
         .route("/export_config", get(handle_config_dl_req))
+        .nest("/api/v1", create_api_router())
+        .merge(create_auth_router())
         .layer(create_ws_layer())
+        .layer(middleware::from_fn(handle_auth))
         .layer(create_cors_layer());
 
     // TCP listener
@@ -169,7 +206,7 @@ pub fn update_proxied_client() -> anyhow::Result<()> {
 
 /// Sends the config file as a download.
 async fn handle_config_dl_req() -> impl IntoResponse {
-    let config_json = match serde_json::to_string_pretty(get_config().as_ref()) {
+    let config_json = match serde_json::to_string_pretty(&get_redacted_config()) {
         Ok(config) => config,
         Err(err) => {
             return Err((
@@ -296,368 +333,371 @@ pub async fn download_media_tools(
     Ok(())
 }
 
-/// Logs into the UFC Fight Pass and returns the set of auth keys included in the response.
-pub async fn login_to_fight_pass(
-    region: &str,
-    email: &str,
-    pass: &str,
-) -> anyhow::Result<LoginSession> {
+/// Downloads the thumbnail image of a VOD.
+pub async fn download_vod_thumbnail(url: &str) -> anyhow::Result<Vec<u8>> {
     let client = if get_config().use_proxy {
-        &HTTP_PROXIED_CLIENT.load()
+        HTTP_PROXIED_CLIENT.load().as_ref().clone()
     } else {
-        &*HTTP_CLIENT
+        HTTP_CLIENT.clone()
     };
-
-    let mut headers = generate_fight_pass_api_headers()?;
-
-    headers.insert("Realm", region.parse().context("Invalid region setting")?);
-
     let resp = client
-        .post("https://dce-frontoffice.imggaming.com/api/v2/login")
-        .headers(headers)
-        .json(&json!({
-            "id": email,
-            "secret": pass
-        }))
+        .get(url)
         .send()
         .await
-        .context("An error occurred while trying to log into the Fight Pass")?;
+        .context("An error occurred while trying to download the thumbnail")?;
 
     if !resp.status().is_success() {
-        let err_msg = "Login failed. Check your credentials and try again";
-        let resp_error_messages = get_messages_from_response(resp).await.context(err_msg)?;
-
-        if resp_error_messages.contains(&"badLocation".to_string()) {
-            return Err(anyhow!(
-                "Login was blocked because of the IP address your UFC Ripper backend is bound to. \
-                Try disabling any active VPN connections, or use a proxy service (check configuration)"
-            ));
-        }
-
-        return Err(anyhow!(err_msg));
+        return Err(anyhow!(
+            "Server responded with an error for the thumbnail download"
+        ));
     }
 
-    let err_msg = "Login information contains an invalid response";
-    let json_body: JSON = resp.json().await.context(err_msg)?;
-
-    if let (Some(auth), Some(refresh)) = (
-        json_body.try_get("authorisationToken").as_str(),
-        json_body.try_get("refreshToken").as_str(),
-    ) {
-        Ok(LoginSession {
-            user: email.to_string(),
-            auth: auth.to_string(),
-            refresh: refresh.to_string(),
-        })
+    let thumbnail = resp
+        .bytes()
+        .await
+        .context("Thumbnail response contains invalid data")?;
+
+    Ok(thumbnail.to_vec())
+}
+
+/// Returns a Fight Pass API client for the current configuration.
+pub fn get_fight_pass_api() -> FightPassApi {
+    let config = get_config();
+    let client = if config.use_proxy {
+        HTTP_PROXIED_CLIENT.load().as_ref().clone()
     } else {
-        Err(anyhow!(err_msg))
+        HTTP_CLIENT.clone()
+    };
+
+    FightPassApi {
+        client,
+        api_url: FIGHT_PASS_API_URL.clone(),
+        search_url: FIGHT_PASS_SEARCH_URL.clone(),
+        realm: config.region.clone(),
+        api_key: config.api_key.clone(),
+        search_api_key: config.search_api_key.clone(),
     }
 }
 
-/// Refreshes an expired access token and returns a new one.
-pub async fn refresh_access_token() -> anyhow::Result<()> {
-    if is_debug() {
-        println!("Refreshing access token..\n");
+/// Logs into the UFC Fight Pass and returns the set of auth keys included in the response.
+pub async fn login_to_fight_pass(
+    region: &str,
+    email: &str,
+    pass: &str,
+) -> anyhow::Result<LoginSession> {
+    FightPassApi {
+        realm: region.to_string(),
+        ..get_fight_pass_api()
     }
+    .login(email, pass)
+    .await
+}
 
-    let client = if get_config().use_proxy {
-        &HTTP_PROXIED_CLIENT.load()
-    } else {
-        &*HTTP_CLIENT
-    };
+/// Returns a Fight Pass API client for an account profile.
+fn get_account_fight_pass_api(account: &AccountProfile) -> FightPassApi {
+    FightPassApi {
+        realm: account.region.clone(),
+        ..get_fight_pass_api()
+    }
+}
 
-    let resp = client
-        .post("https://dce-frontoffice.imggaming.com/api/v2/token/refresh")
-        .headers(generate_fight_pass_api_headers()?)
-        .bearer_auth(&get_config().auth_token)
-        .json(&json!({
-            "refreshToken": &get_config().refresh_token
-        }))
-        .send()
-        .await
-        .context("An error occurred while trying to refresh access token")?;
+/// Returns the account profile with the given name. An empty name returns the active account.
+fn get_account(name: &str) -> anyhow::Result<AccountProfile> {
+    get_config().get_account(name).ok_or_else(|| {
+        InvalidRequestError(format!(
+            r#"Account "{name}" does not exist. Please log in with it again"#
+        ))
+        .into()
+    })
+}
 
-    if !resp.status().is_success() {
-        let err_msg = "Failed to refresh your login session. Please login with your UFC Fight Pass account again";
-        let resp_error_messages = get_messages_from_response(resp).await.context(err_msg)?;
-
-        if resp_error_messages.contains(&"badLocation".to_string()) {
-            return Err(anyhow!(
-                "Session refresh request was blocked because of the IP address your UFC Ripper backend is bound to. \
-                Try disabling any active VPN connections, or use a proxy service (check configuration)"
-            ));
-        } else if resp_error_messages.contains(&"errorRefreshingToken".to_string()) {
-            return Err(anyhow!(
-                "Invalid refresh token. Please log in with your UFC Fight Pass account again"
-            ));
-        }
+/// Starts the background task that refreshes the access tokens of the saved accounts shortly
+/// before they expire. Calling this more than once has no effect.
+pub fn init_token_refresher() {
+    TOKEN_REFRESHER.get_or_init(|| {
+        tokio::spawn(async {
+            loop {
+                sleep(get_token_refresh_delay()).await;
+
+                let mut failed = false;
+
+                for account in get_config().get_accounts() {
+                    if let Err(error) = ensure_fresh_access_token(&account.name).await {
+                        log_err!(
+                            "Failed to refresh the login session of \"{}\":\n{error}\n",
+                            account.user
+                        );
+                        failed = true;
+                    }
+                }
 
-        return Err(anyhow!(err_msg));
-    }
+                if failed {
+                    sleep(Duration::from_secs(TOKEN_REFRESH_RETRY_DELAY)).await;
+                }
+            }
+        })
+    });
+}
 
-    let json_body: JSON = resp
-        .json()
-        .await
-        .context("Login session refresh response contains invalid data")?;
+/// Returns the time to wait before the first access token of the saved accounts needs to be
+/// refreshed.
+fn get_token_refresh_delay() -> Duration {
+    let secs = get_config()
+        .get_accounts()
+        .iter()
+        .filter_map(|account| get_jwt_expiry(&account.auth_token))
+        .min()
+        .map_or(TOKEN_CHECK_MAX_DELAY, |expiry| {
+            expiry.saturating_sub(get_unix_time() + TOKEN_REFRESH_MARGIN)
+        });
+
+    Duration::from_secs(secs.clamp(TOKEN_CHECK_MIN_DELAY, TOKEN_CHECK_MAX_DELAY))
+}
 
-    let auth_token = json_body.try_get("authorisationToken").as_str();
+/// Returns the current time as a Unix timestamp.
+pub fn get_unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
 
-    match auth_token {
-        Some(new_auth_token) => {
-            update_config(ConfigUpdate::Auth(new_auth_token.to_string())).await;
-            emit_config_update();
-            Ok(())
-        }
-        None => Err(anyhow!(
-            "Server responded with an invalid response to the session refresh request"
-        )),
-    }
+/// Checks if an access token expires within the refresh margin. Tokens without a readable expiry
+/// are only refreshed when the Fight Pass rejects them.
+fn is_token_expiring(auth_token: &str) -> bool {
+    get_jwt_expiry(auth_token)
+        .is_some_and(|expiry| expiry <= get_unix_time() + TOKEN_REFRESH_MARGIN)
 }
 
-/// Searches the UFC Fight Pass library for VODs.
-pub async fn search_vods(query: &str, page: u64) -> anyhow::Result<JSON> {
-    let client = if get_config().use_proxy {
-        &HTTP_PROXIED_CLIENT.load()
-    } else {
-        &*HTTP_CLIENT
-    };
+/// Refreshes the access token of an account ahead of time if it's about to expire.
+async fn ensure_fresh_access_token(account_name: &str) -> anyhow::Result<()> {
+    let account = get_account(account_name)?;
 
-    let index_name = format!("prod-{}-livestreaming-events", get_config().region);
-    let search_params = format!(
-        "{}&{}",
-        VOD_SEARCH_PARAMS.as_str(),
-        form_urlencoded::Serializer::new(String::new())
-            .append_pair("query", query)
-            .append_pair("page", &page.to_string())
-            .append_pair(
-                "restrictSearchableAttributes",
-                if get_config().search_title_only {
-                    r#"["name"]"#
-                } else {
-                    "[]"
-                }
-            )
-            .finish()
-    );
-    let resp = client
-        .post("https://h99xldr8mj-dsn.algolia.net/1/indexes/*/queries")
-        .header("x-algolia-application-id", "H99XLDR8MJ")
-        .header("x-algolia-api-key", &get_config().search_api_key)
-        .json(&json!({
-            "requests": [
-                {
-                    "indexName": index_name,
-                    "params": search_params
-                }
-            ]
-        }))
-        .send()
-        .await
-        .context("An error occurred while trying to search the Fight Pass library")?;
+    if account.auth_token.is_empty() || !is_token_expiring(&account.auth_token) {
+        return Ok(());
+    }
 
-    if !resp.status().is_success() {
-        return Err(anyhow!(
-            "Server responded with an error for the search request"
-        ));
+    let _lock = TOKEN_REFRESH_LOCK.lock().await;
+    let account = get_account(account_name)?;
+
+    // The token could've been refreshed by another request while waiting for the lock
+    if is_token_expiring(&account.auth_token) {
+        refresh_access_token(&account).await?;
     }
 
-    let json_body: JSON = resp
-        .json()
-        .await
-        .context("Search result contains an invalid response")?;
+    Ok(())
+}
 
-    let result = json_body.try_get("results").try_get(0);
+/// Refreshes an access token that was rejected by the Fight Pass. Concurrent requests rejected
+/// with the same token only cause a single refresh.
+async fn refresh_stale_access_token(account_name: &str, stale_token: &str) -> anyhow::Result<()> {
+    let _lock = TOKEN_REFRESH_LOCK.lock().await;
+    let account = get_account(account_name)?;
 
-    if result == &JSON::Null {
-        Err(anyhow!("Response does not contain any search results"))
-    } else {
-        Ok(result.clone())
+    if account.auth_token == stale_token {
+        refresh_access_token(&account).await?;
     }
+
+    Ok(())
 }
 
-/// Retrieves metadata for the given Fight Pass VOD.
-pub async fn get_vod_meta(url: &str) -> anyhow::Result<Vod> {
-    enum ReqStatus {
-        Success(JSON),
-        NeedsRefresh,
+/// Refreshes the access token of an account and saves the new one to the configuration. Callers
+/// must hold the `TOKEN_REFRESH_LOCK`.
+async fn refresh_access_token(account: &AccountProfile) -> anyhow::Result<()> {
+    if is_debug() {
+        println!("Refreshing access token of \"{}\"..\n", account.user);
     }
 
-    let vod_id = get_vod_id_from_url(url)?;
+    let new_auth_token = get_account_fight_pass_api(account)
+        .refresh_access_token(&account.auth_token, &account.refresh_token)
+        .await?;
 
-    // Runs the metadata request and returns the status of that request.
-    // Having this as a closure allows this process to be run multiple times.
-    let run_request = || async {
-        let client = if get_config().use_proxy {
-            &HTTP_PROXIED_CLIENT.load()
-        } else {
-            &*HTTP_CLIENT
-        };
+    update_config(ConfigUpdate::Auth(account.name.clone(), new_auth_token)).await;
+    emit_config_update();
 
-        let resp = client
-            .get(format!(
-                "https://dce-frontoffice.imggaming.com/api/v2/vod/{vod_id}"
-            ))
-            .headers(generate_fight_pass_api_headers()?)
-            .bearer_auth(&get_config().auth_token)
-            .send()
-            .await
-            .context("An error occurred while trying fetch VOD metadata")?;
+    Ok(())
+}
+
+/// Runs an authenticated Fight Pass request with the access token of an account. The token is
+/// refreshed ahead of time if it's about to expire, and the request is retried once with a new
+/// token if the Fight Pass rejects it as expired.
+async fn run_authenticated_request<T, F, Fut>(account_name: &str, request: F) -> anyhow::Result<T>
+where
+    F: Fn(FightPassApi, String) -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    // The current token could still be usable, so a failed refresh shouldn't fail the request
+    if let Err(error) = ensure_fresh_access_token(account_name).await {
+        log_warn!("Failed to refresh the login session ahead of time:\n{error}\n");
+    }
 
-        let status = resp.status();
+    let account = get_account(account_name)?;
+    let fp_api = get_account_fight_pass_api(&account);
 
-        if !status.is_success() {
-            let err_msg = "An unknown error occurred while trying fetch VOD metadata";
+    match request(fp_api, account.auth_token.clone()).await {
+        Err(error) if error.is::<ExpiredTokenError>() => {
+            refresh_stale_access_token(account_name, &account.auth_token).await?;
 
-            return match status.as_u16() {
-                401 => {
-                    let resp_error_messages =
-                        get_messages_from_response(resp).await.context(err_msg)?;
+            let account = get_account(account_name)?;
 
-                    if resp_error_messages.contains(&"Bearer token is not valid".to_string()) {
-                        Ok(ReqStatus::NeedsRefresh)
-                    } else {
-                        Err(anyhow!(
+            request(get_account_fight_pass_api(&account), account.auth_token)
+                .await
+                .map_err(|error| {
+                    if error.is::<ExpiredTokenError>() {
+                        anyhow!(
                             r#"The server responded to the request as "Unauthorized". Please try logging in with your UFC Fight Pass account again"#
-                        ))
+                        )
+                    } else {
+                        error
                     }
-                }
-                404 => Err(anyhow!(
-                    "The video you requested does not exist. Please check the URL and try again"
-                )),
-                _ => Err(anyhow!(err_msg)),
-            };
+                })
         }
+        result => result,
+    }
+}
 
-        let json_body: JSON = resp
-            .json()
-            .await
-            .context("VOD metadata response contains invalid data")?;
+/// Searches the UFC Fight Pass library for VODs, using the active account.
+pub async fn search_vods(query: &str, page: u64) -> anyhow::Result<JSON> {
+    let title_only = get_config().search_title_only;
 
-        Ok::<ReqStatus, anyhow::Error>(ReqStatus::Success(json_body))
-    };
+    run_authenticated_request("", |fp_api, _| async move {
+        fp_api.search_vods(query, page, title_only).await
+    })
+    .await
+}
 
-    // Creates and returns a `Vod` instance from JSON
-    let create_vod_from_json_meta = |meta: &JSON| {
-        let err_msg = "VOD metadata response does not match the expected format";
-        let vod = Vod {
-            id: meta.try_get("id").as_u64().context(err_msg)?,
-            title: meta
-                .try_get("title")
-                .as_str()
-                .context(err_msg)?
-                .to_string()
-                .replace(':', " -"),
-            desc: meta
-                .try_get("description")
-                .as_str()
-                .context(err_msg)?
-                .to_string(),
-            thumb: meta
-                .try_get("thumbnailUrl")
-                .as_str()
-                .context(err_msg)?
-                .to_string(),
-            access: meta.try_get("accessLevel").as_str().context(err_msg)? != "DENIED",
-            vod_url: url.to_string(),
-            ..Vod::default()
-        };
-
-        Ok::<Vod, anyhow::Error>(vod)
+/// Searches the UFC Fight Pass library for VODs with filters, using the active account. The title
+/// only search setting of the configuration is used if the request doesn't specify it.
+pub async fn search_library(request: SearchRequest) -> anyhow::Result<SearchResult> {
+    let request = &SearchRequest {
+        title_only: request.title_only.or(Some(get_config().search_title_only)),
+        ..request
     };
 
-    match run_request().await? {
-        ReqStatus::Success(vod_meta) => Ok(create_vod_from_json_meta(&vod_meta)?),
-        ReqStatus::NeedsRefresh => {
-            refresh_access_token().await?;
+    run_authenticated_request("", |fp_api, _| async move { fp_api.search(request).await }).await
+}
 
-            match run_request().await? {
-                ReqStatus::Success(vod_meta) => Ok(create_vod_from_json_meta(&vod_meta)?),
-                ReqStatus::NeedsRefresh => Err(anyhow!(
-                    r#"The server responded to the request as "Unauthorized". Please try logging in with your UFC Fight Pass account again"#
-                )),
-            }
-        }
-    }
+/// Retrieves a browse page of the Fight Pass catalogue. The active account is used if the account
+/// name is empty.
+pub async fn get_browse_page(page: &str, account_name: &str) -> anyhow::Result<BrowsePage> {
+    run_authenticated_request(account_name, |fp_api, auth_token| async move {
+        fp_api.get_browse_page(page, &auth_token).await
+    })
+    .await
 }
 
-/// Fetches the HLS stream URL for a given Fight Pass video.
-pub async fn get_vod_stream_url(vod_id: u64) -> anyhow::Result<String> {
-    let client = if get_config().use_proxy {
-        &HTTP_PROXIED_CLIENT.load()
-    } else {
-        &*HTTP_CLIENT
-    };
+/// Retrieves a Fight Pass playlist with all of its VODs. The active account is used if the account
+/// name is empty.
+pub async fn get_playlist(playlist_id: u64, account_name: &str) -> anyhow::Result<Playlist> {
+    run_authenticated_request(account_name, |fp_api, auth_token| async move {
+        fp_api.get_playlist(playlist_id, &auth_token).await
+    })
+    .await
+}
 
-    let resp = client
-        .get(format!(
-            "https://dce-frontoffice.imggaming.com/api/v3/stream/vod/{vod_id}"
-        ))
-        .headers(generate_fight_pass_api_headers()?)
-        .bearer_auth(&get_config().auth_token)
-        .send()
-        .await
-        .context("An error occurred while trying request the callback URL for VOD stream")?;
+/// Retrieves the metadata of every VOD in a playlist or on an event page, so they can be
+/// downloaded at once. Every request is made with the given account. The metadata of each VOD is
+/// retrieved separately, so a single unavailable VOD doesn't fail the whole collection.
+pub async fn get_collection_vods(
+    collection: &Collection,
+    account_name: &str,
+) -> anyhow::Result<Vec<(u64, anyhow::Result<Vod>)>> {
+    let vod_ids = match collection {
+        Collection::Playlist(playlist_id) => get_playlist(*playlist_id, account_name)
+            .await?
+            .vods
+            .iter()
+            .map(|vod| vod.id)
+            .collect(),
+        Collection::Event(page) => get_browse_page(page, account_name).await?.get_vod_ids(),
+    };
 
-    if !resp.status().is_success() {
-        return Err(anyhow!(
-            "Server responded with an error to the callback URL request"
-        ));
+    if vod_ids.is_empty() {
+        return Err(anyhow!("The collection doesn't contain any videos"));
     }
 
-    let json_body: JSON = resp
-        .json()
-        .await
-        .context("Callback response contains invalid information")?;
-
-    if let Some(url) = json_body.try_get("playerUrlCallback").as_str() {
-        let resp = client
-            .get(url)
-            .send()
-            .await
-            .context("An error occurred while trying request VOD stream URL")?;
-
-        if !resp.status().is_success() {
-            return Err(anyhow!(
-                "Server responded with an error to the VOD stream request"
-            ));
-        }
+    let region = get_account(account_name)?.region;
+    let mut vods = Vec::new();
 
-        let json_body: JSON = resp
-            .json()
-            .await
-            .context("Stream response contains invalid information")?;
+    for vod_id in vod_ids {
+        let vod = get_vod_meta(&create_vod_url(&region, vod_id), account_name).await;
 
-        if let Some(url) = json_body.try_get("hls").try_get(0).try_get("url").as_str() {
-            Ok(url.to_string())
-        } else {
-            Err(anyhow!("No stream URL present in the response"))
-        }
-    } else {
-        Err(anyhow!("No callback request URL present in the response"))
+        vods.push((vod_id, vod));
     }
+
+    Ok(vods)
 }
 
-/// Generates and returns a set of request headers required by the UFC Fight Pass.
-fn generate_fight_pass_api_headers() -> anyhow::Result<HeaderMap> {
-    let err_msg = r#"Invalid request-header configuration. Please check your "config.json" file"#;
-    let mut headers = HeaderMap::new();
+/// Retrieves metadata for the given Fight Pass VOD URL or VOD ID. The active account is used if the account
+/// name is empty.
+pub async fn get_vod_meta(url: &str, account_name: &str) -> anyhow::Result<Vod> {
+    let vod_id =
+        &get_vod_id_from_url(url).map_err(|error| InvalidRequestError(error.to_string()))?;
+    let vod_meta = run_authenticated_request(account_name, |fp_api, auth_token| async move {
+        fp_api.get_vod_meta(vod_id, &auth_token).await
+    })
+    .await?;
+
+    // Bare VOD IDs get the VOD URL of the account's region
+    let vod_url = match url.trim().parse::<u64>() {
+        Ok(vod_id) => create_vod_url(&get_account(account_name)?.region, vod_id),
+        Err(_) => url.trim().to_string(),
+    };
+
+    Ok(Vod {
+        account: account_name.to_string(),
+        ..create_vod_from_json_meta(&vod_meta, &vod_url)?
+    })
+}
 
-    headers.insert("app", "dice".parse().context(err_msg)?);
-    headers.insert("Realm", get_config().region.parse().context(err_msg)?);
-    headers.insert("x-app-var", "6.0.1.f8add0e".parse().context(err_msg)?);
-    headers.insert("x-api-key", get_config().api_key.parse().context(err_msg)?);
+/// Creates a `Vod` from the VOD metadata returned by the Fight Pass.
+fn create_vod_from_json_meta(meta: &JSON, url: &str) -> anyhow::Result<Vod> {
+    let err_msg = "VOD metadata response does not match the expected format";
 
-    Ok(headers)
+    Ok(Vod {
+        id: meta.try_get("id").as_u64().context(err_msg)?,
+        title: meta.try_get("title").as_str().context(err_msg)?.to_string(),
+        desc: meta
+            .try_get("description")
+            .as_str()
+            .context(err_msg)?
+            .to_string(),
+        thumb: meta
+            .try_get("thumbnailUrl")
+            .as_str()
+            .context(err_msg)?
+            .to_string(),
+        duration: meta.try_get("duration").as_u64().unwrap_or(0),
+        date: meta.try_get("startDate").as_u64(),
+        chapters: get_vod_chapters(meta),
+        access: meta.try_get("accessLevel").as_str().context(err_msg)? != "DENIED",
+        vod_url: url.to_string(),
+        ..Vod::default()
+    })
 }
 
-/// Deserializes and returns the `messages` array from a response.
-async fn get_messages_from_response(resp: Response) -> anyhow::Result<Vec<String>> {
-    let resp_messages = serde_json::from_value::<Vec<String>>(
-        resp.json::<JSON>()
-            .await?
-            .try_get_mut("messages", &mut JSON::Null)
-            .take(),
-    )?;
+/// Returns the bout and round markers of a VOD from its metadata. Markers without a title or a
+/// start time are skipped.
+fn get_vod_chapters(meta: &JSON) -> Vec<VodChapter> {
+    meta.try_get("chapters")
+        .as_array()
+        .map_or_else(Vec::new, |chapters| {
+            chapters
+                .iter()
+                .filter_map(|chapter| {
+                    Some(VodChapter {
+                        title: chapter.try_get("title").as_str()?.to_string(),
+                        start: chapter.try_get("startTime").as_u64()?,
+                    })
+                })
+                .collect()
+        })
+}
 
-    Ok(resp_messages)
+/// Fetches the HLS stream URL for a given Fight Pass video. The active account is used if the
+/// account name is empty.
+pub async fn get_vod_stream_url(vod_id: u64, account_name: &str) -> anyhow::Result<String> {
+    run_authenticated_request(account_name, |fp_api, auth_token| async move {
+        fp_api.get_vod_stream_url(vod_id, &auth_token).await
+    })
+    .await
 }
@@ -0,0 +1,384 @@
+// Libs
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use anyhow::{anyhow, Context};
+use serde_json::json;
+use tokio::{fs, process::Command};
+
+use crate::{
+    config_util::{PostProcessStep, TranscodePreset},
+    metadata_util::run_ffmpeg,
+    net_util::JSON,
+    state_util::Vod,
+    template_util::format_date,
+};
+
+// Statics
+/// Containers that downloaded files can be remuxed to.
+const REMUX_EXTENSIONS: [&str; 4] = ["mp4", "mkv", "mov", "m4v"];
+
+/// Validates the steps of the post-processing pipeline.
+pub fn validate_post_processing(steps: &[PostProcessStep]) -> anyhow::Result<()> {
+    for (idx, step) in steps.iter().enumerate() {
+        match step {
+            PostProcessStep::Remux { ext } if !REMUX_EXTENSIONS.contains(&ext.as_str()) => {
+                return Err(anyhow!(
+                    "Post-processing step {} can't remux to \"{ext}\". Supported containers are: {}",
+                    idx + 1,
+                    REMUX_EXTENSIONS.join(", ")
+                ));
+            }
+            PostProcessStep::Hook { command } if command.trim().is_empty() => {
+                return Err(anyhow!(
+                    "Post-processing step {} has an empty command",
+                    idx + 1
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that a configuration sent by a client doesn't add new hook steps while the GUI
+/// authentication is disabled. Hooks run shell commands, so anyone who can reach an unprotected
+/// UFC Ripper could otherwise run commands on its machine. Existing hooks can still be kept or
+/// removed.
+pub fn validate_hook_changes(
+    steps: &[PostProcessStep],
+    current_steps: &[PostProcessStep],
+    is_auth_enabled: bool,
+) -> anyhow::Result<()> {
+    if is_auth_enabled {
+        return Ok(());
+    }
+
+    let current_commands = get_hook_commands(current_steps);
+
+    if get_hook_commands(steps)
+        .iter()
+        .any(|command| !current_commands.contains(command))
+    {
+        return Err(anyhow!(
+            r#"Hook steps can only be added when the GUI authentication is enabled. Enable it, or add the hook to the "config.json" file"#
+        ));
+    }
+
+    Ok(())
+}
+
+/// Returns the commands of the hook steps of the post-processing pipeline.
+fn get_hook_commands(steps: &[PostProcessStep]) -> Vec<&str> {
+    steps
+        .iter()
+        .filter_map(|step| match step {
+            PostProcessStep::Hook { command } => Some(command.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Runs the post-processing steps on a downloaded file in order, and reports each step to
+/// `on_progress` as its own task. The path of the file is updated when a step changes it, and the
+/// pipeline stops at the first failed step.
+pub async fn run_post_processing(
+    vod: &Vod,
+    output: &mut PathBuf,
+    steps: &[PostProcessStep],
+    on_progress: impl Fn(JSON),
+) -> anyhow::Result<()> {
+    for (idx, step) in steps.iter().enumerate() {
+        let task = match step {
+            PostProcessStep::Transcode { .. } => "transcode",
+            PostProcessStep::Remux { .. } => "remux",
+            PostProcessStep::Hook { .. } => "hook",
+        };
+
+        on_progress(json!({
+            "task": task,
+            "step": idx + 1,
+            "steps": steps.len()
+        }));
+
+        let result = match step {
+            PostProcessStep::Transcode { preset } => transcode(output, *preset).await,
+            PostProcessStep::Remux { ext } => remux(output, ext).await,
+            PostProcessStep::Hook { command } => run_hook(command, vod, output).await,
+        };
+
+        result.map_err(|error| {
+            anyhow!("Post-processing step {} ({task}) failed: {error}", idx + 1)
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Re-encodes a file with a transcode preset and replaces the original file. Streams that aren't
+/// touched by the preset are copied as they are.
+async fn transcode(output: &Path, preset: TranscodePreset) -> anyhow::Result<()> {
+    let ext = get_extension(output);
+    let temp_output = output.with_extension(format!("transcode.{ext}"));
+    let mut args: Vec<OsString> = vec![
+        "-y".into(),
+        "-loglevel".into(),
+        "error".into(),
+        "-i".into(),
+        output.into(),
+        "-map".into(),
+        "0".into(),
+        "-c".into(),
+        "copy".into(),
+    ];
+
+    args.extend(
+        get_preset_args(preset, &ext)
+            .into_iter()
+            .map(OsString::from),
+    );
+    args.push((&temp_output).into());
+
+    if let Err(error) = run_ffmpeg(&args).await {
+        let _ = fs::remove_file(&temp_output).await;
+
+        return Err(error);
+    }
+
+    fs::rename(&temp_output, output)
+        .await
+        .context("Failed to replace the download with the transcoded file")
+}
+
+/// Returns the `ffmpeg` arguments of a transcode preset. Only the first video stream is
+/// re-encoded, so the cover art stays as it is.
+fn get_preset_args(preset: TranscodePreset, ext: &str) -> Vec<&'static str> {
+    match preset {
+        TranscodePreset::H265 => {
+            let mut args = vec!["-c:v:0", "libx265", "-crf", "26", "-preset", "medium"];
+
+            // Apple players only recognize H.265 in MP4 files with this tag
+            if ext != "mkv" {
+                args.extend(["-tag:v:0", "hvc1"]);
+            }
+
+            args
+        }
+        TranscodePreset::NormalizeAudio => vec![
+            "-c:a",
+            "aac",
+            "-b:a",
+            "192k",
+            "-filter:a",
+            "loudnorm=I=-16:TP=-1.5:LRA=11",
+        ],
+        TranscodePreset::Downscale720 => get_downscale_args("scale=-2:'min(720,ih)'"),
+        TranscodePreset::Downscale480 => get_downscale_args("scale=-2:'min(480,ih)'"),
+    }
+}
+
+/// Returns the `ffmpeg` arguments that re-encode the video with a scale filter.
+fn get_downscale_args(filter: &'static str) -> Vec<&'static str> {
+    vec![
+        "-c:v:0",
+        "libx264",
+        "-crf",
+        "20",
+        "-preset",
+        "medium",
+        "-filter:v:0",
+        filter,
+    ]
+}
+
+/// Moves the streams of a file into another container and removes the original file.
+async fn remux(output: &mut PathBuf, ext: &str) -> anyhow::Result<()> {
+    if get_extension(output) == ext {
+        return Ok(());
+    }
+
+    let remuxed = output.with_extension(ext);
+    let mut args: Vec<OsString> = vec![
+        "-y".into(),
+        "-loglevel".into(),
+        "error".into(),
+        "-i".into(),
+        (&*output).into(),
+        "-map".into(),
+        "0".into(),
+    ];
+
+    // Only Matroska can hold attachments, like the cover art of MKV files
+    if ext != "mkv" {
+        args.extend(["-map".into(), "-0:t".into()]);
+    }
+
+    args.extend(["-c".into(), "copy".into(), (&remuxed).into()]);
+
+    if let Err(error) = run_ffmpeg(&args).await {
+        let _ = fs::remove_file(&remuxed).await;
+
+        return Err(error);
+    }
+
+    fs::remove_file(&*output)
+        .await
+        .context("Failed to remove the original file after remuxing")?;
+    *output = remuxed;
+
+    Ok(())
+}
+
+/// Runs a hook command with the system shell, in the directory of the downloaded file.
+async fn run_hook(command: &str, vod: &Vod, output: &Path) -> anyhow::Result<()> {
+    #[cfg(windows)]
+    let mut shell = {
+        let mut shell = Command::new("cmd");
+
+        shell.arg("/C").raw_arg(command);
+        shell
+    };
+    #[cfg(not(windows))]
+    let mut shell = {
+        let mut shell = Command::new("sh");
+
+        shell.arg("-c").arg(command);
+        shell
+    };
+
+    if let Some(dir) = output.parent() {
+        shell.current_dir(dir);
+    }
+
+    let hook = shell
+        .envs(create_hook_env(vod, output))
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .context("An error occurred while trying to launch the hook")?;
+
+    if hook.status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Hook exited with {}:\n{}",
+            hook.status,
+            String::from_utf8_lossy(&hook.stderr).trim()
+        ))
+    }
+}
+
+/// Creates the environment variables that pass the downloaded file and the VOD's metadata to
+/// hooks.
+fn create_hook_env(vod: &Vod, output: &Path) -> Vec<(&'static str, String)> {
+    vec![
+        ("UFCR_OUTPUT_PATH", output.to_string_lossy().to_string()),
+        ("UFCR_VOD_ID", vod.id.to_string()),
+        ("UFCR_VOD_TITLE", vod.title.clone()),
+        ("UFCR_VOD_DESC", vod.desc.clone()),
+        ("UFCR_VOD_URL", vod.vod_url.clone()),
+        ("UFCR_VOD_THUMB", vod.thumb.clone()),
+        (
+            "UFCR_VOD_DATE",
+            vod.date.map(format_date).unwrap_or_default(),
+        ),
+        ("UFCR_VOD_DURATION", vod.duration.to_string()),
+        ("UFCR_ACCOUNT", vod.account.clone()),
+    ]
+}
+
+/// Returns the lowercase extension of a file.
+fn get_extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+/*************
+ *   Tests   *
+ *************/
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        config_util::{PostProcessStep, TranscodePreset},
+        state_util::Vod,
+    };
+
+    use super::{
+        create_hook_env, get_preset_args, validate_hook_changes, validate_post_processing,
+    };
+
+    #[test]
+    fn unit_post_processing() {
+        let steps: Vec<PostProcessStep> = serde_json::from_str(
+            r#"[
+                { "type": "transcode", "preset": "h265" },
+                { "type": "remux", "ext": "mkv" },
+                { "type": "hook", "command": "echo \"$UFCR_OUTPUT_PATH\"" }
+            ]"#,
+        )
+        .unwrap();
+
+        assert!(validate_post_processing(&steps).is_ok());
+        assert!(validate_post_processing(&[PostProcessStep::Remux { ext: "avi".into() }]).is_err());
+        assert!(validate_post_processing(&[PostProcessStep::Hook {
+            command: " ".into()
+        }])
+        .is_err());
+
+        assert!(get_preset_args(TranscodePreset::H265, "mp4").ends_with(&["-tag:v:0", "hvc1"]));
+        assert!(!get_preset_args(TranscodePreset::H265, "mkv").contains(&"hvc1"));
+
+        let vod = Vod {
+            id: 123_456,
+            title: "UFC 300: Pereira vs. Hill".into(),
+            date: Some(1_713_052_800),
+            out_base: "Events/7. UFC 300 - Pereira vs. Hill".into(),
+            ..Vod::default()
+        };
+        let env = create_hook_env(
+            &vod,
+            Path::new("/downloads/Events/7. UFC 300 - Pereira vs. Hill.mp4"),
+        );
+
+        assert!(env.contains(&(
+            "UFCR_OUTPUT_PATH",
+            "/downloads/Events/7. UFC 300 - Pereira vs. Hill.mp4".into()
+        )));
+        assert!(env.contains(&("UFCR_VOD_TITLE", "UFC 300: Pereira vs. Hill".into())));
+        assert!(env.contains(&("UFCR_VOD_DATE", "2024-04-14".into())));
+    }
+
+    #[test]
+    fn unit_validate_hook_changes() {
+        let hook = |command: &str| PostProcessStep::Hook {
+            command: command.into(),
+        };
+        let remux = PostProcessStep::Remux { ext: "mkv".into() };
+        let current_steps = [hook("notify-send done")];
+
+        // Hooks from the config file can be kept or removed, but not replaced or added to
+        assert!(validate_hook_changes(
+            &[remux.clone(), hook("notify-send done")],
+            &current_steps,
+            false
+        )
+        .is_ok());
+        assert!(validate_hook_changes(&[remux], &current_steps, false).is_ok());
+        assert!(
+            validate_hook_changes(&[hook("notify-send replaced")], &current_steps, false).is_err()
+        );
+        assert!(validate_hook_changes(&[hook("curl example.com")], &[], false).is_err());
+
+        assert!(validate_hook_changes(&[hook("curl example.com")], &[], true).is_ok());
+    }
+}
@@ -0,0 +1,348 @@
+// Libs
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use anyhow::{anyhow, Context};
+use once_cell::sync::{Lazy, OnceCell};
+use tokio::{sync::Notify, task::JoinHandle};
+
+use crate::{
+    bin_util::{cancel_download, pause_download, start_download, DownloadHandlers},
+    config_util::get_config,
+    net_util::get_vod_stream_url,
+    rt_util::QuitUnwrap,
+    state_util::{
+        add_vod_to_queue, count_dlq_vods_by_status, get_dlq, replace_dlq_vod_status,
+        update_dlq_vod_status, Vod,
+    },
+};
+
+// Structs
+/// A download that's waiting in the downloads-queue for a free download slot.
+struct PendingDownload {
+    vod: Vod,
+    is_restart: bool,
+    handlers: DownloadHandlers,
+}
+
+// Statics
+/// Holds the downloads that are waiting for a download slot, in the order they will be started.
+static PENDING_DLS: Lazy<Mutex<VecDeque<PendingDownload>>> =
+    Lazy::new(|| Mutex::new(VecDeque::new()));
+/// Wakes the scheduler up whenever a download slot could've been freed or a download was queued.
+static SCHED_NOTIFY: Lazy<Notify> = Lazy::new(Notify::new);
+/// Holds the handle to the global download scheduler task.
+static SCHEDULER: OnceCell<JoinHandle<()>> = OnceCell::new();
+
+/// Starts the global download scheduler, which starts queued downloads in FIFO order whenever a
+/// download slot is available. Calling this more than once has no effect.
+pub fn init_scheduler() {
+    SCHEDULER.get_or_init(|| {
+        tokio::spawn(async {
+            loop {
+                SCHED_NOTIFY.notified().await;
+                fill_download_slots().await;
+            }
+        })
+    });
+}
+
+/// Wakes the scheduler up to re-evaluate the available download slots.
+pub fn reschedule() {
+    SCHED_NOTIFY.notify_one();
+}
+
+/// Adds a VOD to the downloads-queue as "queued" and returns it. The download will be started by
+/// the scheduler as soon as a download slot is available. Downloads that are already queued or
+/// active can't be queued again, since both would write to the same output files.
+pub fn enqueue_download(
+    vod: Vod,
+    is_restart: bool,
+    handlers: DownloadHandlers,
+) -> anyhow::Result<Vod> {
+    // The pending downloads stay locked till the VOD is queued, so two requests for the same
+    // download can't both pass the check
+    let mut pending_dls = get_pending_dls();
+
+    if is_active_download(&pending_dls, &vod.q_id) {
+        return Err(anyhow!("The download is already queued or in progress"));
+    }
+
+    let queued_vod = add_vod_to_queue(Vod {
+        task: "prepare".to_string(),
+        status: "queued".to_string(),
+        progress: 0.0,
+        size: "N/A".to_string(),
+        speed: "N/A".to_string(),
+        eta: "N/A".to_string(),
+        ..vod
+    });
+
+    pending_dls.push_back(PendingDownload {
+        vod: queued_vod.clone(),
+        is_restart,
+        handlers,
+    });
+    drop(pending_dls);
+
+    reschedule();
+
+    Ok(queued_vod)
+}
+
+/// Cancels a download and returns the number of partially downloaded files removed. Queued
+/// downloads are just removed from the pending downloads, while active downloads are stopped,
+/// which frees up a download slot.
+pub async fn cancel_scheduled_download(vod: &Vod) -> anyhow::Result<usize> {
+    if remove_pending_download(&vod.q_id).is_some() {
+        update_dlq_vod_status(&vod.q_id, "cancelled")?;
+
+        return Ok(0);
+    }
+
+    // A download that's being started isn't pending anymore, but stays "queued" till its download
+    // task is registered, and the scheduler doesn't start it once it's cancelled
+    if replace_dlq_vod_status(&vod.q_id, "queued", "cancelled") {
+        return Ok(0);
+    }
+
+    let result = cancel_download(vod).await;
+
+    reschedule();
+
+    result
+}
+
+/// Pauses an active download, which frees up a download slot.
+pub async fn pause_scheduled_download(vod: &Vod) -> anyhow::Result<()> {
+    if get_pending_dl_order().contains(&vod.q_id) {
+        return Err(anyhow!(
+            "Queued downloads can't be paused. Cancel the download or move it down the queue instead"
+        ));
+    }
+
+    let result = pause_download(vod).await;
+
+    reschedule();
+
+    result
+}
+
+/// Resumes a paused or interrupted download by adding it back to the downloads-queue. The
+/// download continues from the partially downloaded files, using the same output filename.
+pub fn resume_download(q_id: &str, handlers: DownloadHandlers) -> anyhow::Result<Vod> {
+    let vod = get_dlq()
+        .get(q_id)
+        .filter(|vod| vod.status == "paused" || vod.status == "interrupted")
+        .cloned()
+        .context("Only paused or interrupted downloads can be resumed")?;
+
+    enqueue_download(vod, true, handlers)
+}
+
+/// Returns the queue IDs of all pending downloads, in the order they will be started.
+pub fn get_pending_dl_order() -> Vec<String> {
+    get_pending_dls()
+        .iter()
+        .map(|pending| pending.vod.q_id.clone())
+        .collect()
+}
+
+/// Moves a queued download to a new position among the pending downloads and returns the new
+/// order of the pending downloads.
+pub fn move_pending_download(q_id: &str, position: usize) -> anyhow::Result<Vec<String>> {
+    {
+        let mut pending_dls = get_pending_dls();
+        let idx = pending_dls
+            .iter()
+            .position(|pending| pending.vod.q_id == q_id)
+            .context("The download is not waiting in the downloads-queue anymore")?;
+
+        move_queue_item(&mut pending_dls, idx, position);
+    }
+
+    Ok(get_pending_dl_order())
+}
+
+/// Starts pending downloads until there are no more free download slots or pending downloads.
+/// The slots are counted again before each download, so changes to the concurrent downloads limit
+/// are picked up right away.
+async fn fill_download_slots() {
+    loop {
+        let free_slots = get_free_slots(
+            get_config().max_concur_dls,
+            count_dlq_vods_by_status("downloading"),
+        );
+
+        if free_slots == 0 {
+            break;
+        }
+
+        let Some(pending) = get_pending_dls().pop_front() else {
+            break;
+        };
+
+        start_pending_download(pending).await;
+    }
+}
+
+/// Returns the number of free download slots for a concurrent downloads limit. A limit lowered
+/// below the number of active downloads leaves no free slots until enough of them finish.
+fn get_free_slots(max_concur_dls: u64, active_dls: usize) -> usize {
+    usize::try_from(max_concur_dls.max(1))
+        .unwrap_or(usize::MAX)
+        .saturating_sub(active_dls)
+}
+
+/// Checks if a download is waiting for a download slot, being started or downloading.
+fn is_active_download(pending_dls: &VecDeque<PendingDownload>, q_id: &str) -> bool {
+    pending_dls.iter().any(|pending| pending.vod.q_id == q_id)
+        || get_dlq()
+            .get(q_id)
+            .is_some_and(|queued| queued.status == "queued" || queued.status == "downloading")
+}
+
+/// Moves an item of a queue to a new position. Positions past the end move the item to the end.
+fn move_queue_item<T>(queue: &mut VecDeque<T>, idx: usize, position: usize) {
+    if let Some(item) = queue.remove(idx) {
+        let position = position.min(queue.len());
+
+        queue.insert(position, item);
+    }
+}
+
+/// Fetches a fresh stream URL for a pending download and starts it.
+async fn start_pending_download(pending: PendingDownload) {
+    let PendingDownload {
+        mut vod,
+        is_restart,
+        handlers,
+    } = pending;
+
+    // The scheduler needs to be woken up when an active download finishes, since that frees up a
+    // download slot.
+    let dl_handlers = DownloadHandlers {
+        on_completion: {
+            let on_completion = handlers.on_completion.clone();
+
+            Arc::new(move |q_id| {
+                on_completion(q_id);
+                reschedule();
+            })
+        },
+        on_fail: {
+            let on_fail = handlers.on_fail.clone();
+
+            Arc::new(move |q_id, error| {
+                on_fail(q_id, error);
+                reschedule();
+            })
+        },
+        ..handlers.clone()
+    };
+
+    let download = async {
+        vod.hls = get_vod_stream_url(vod.id, &vod.account).await?;
+
+        start_download(&vod, is_restart, dl_handlers).await
+    };
+
+    match download.await {
+        Ok(Some(started_vod)) => (handlers.on_start)(&started_vod),
+        // The download was cancelled while it was being started
+        Ok(None) => {}
+        // A download that was cancelled while it was being started didn't fail
+        Err(error) => {
+            if replace_dlq_vod_status(&vod.q_id, "queued", "failed") {
+                (handlers.on_fail)(&vod.q_id, error);
+            }
+        }
+    }
+}
+
+/// Locks and returns a `MutexGuard` for the pending downloads.
+fn get_pending_dls() -> MutexGuard<'static, VecDeque<PendingDownload>> {
+    PENDING_DLS
+        .lock()
+        .unwrap_or_quit("Failed to exclusively access the pending downloads")
+}
+
+/// Removes and returns a download from the pending downloads.
+fn remove_pending_download(q_id: &str) -> Option<PendingDownload> {
+    let mut pending_dls = get_pending_dls();
+    let idx = pending_dls
+        .iter()
+        .position(|pending| pending.vod.q_id == q_id)?;
+
+    pending_dls.remove(idx)
+}
+
+/*************
+ *   Tests   *
+ *************/
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use crate::state_util::{add_vod_to_queue, replace_dlq_vod_status, Vod};
+
+    use super::{get_free_slots, is_active_download, move_queue_item};
+
+    #[test]
+    fn unit_move_queue_item() {
+        let mut queue: VecDeque<&str> = VecDeque::new();
+
+        // Downloads are queued at the back and started from the front
+        queue.extend(["a", "b", "c", "d"]);
+
+        move_queue_item(&mut queue, 3, 0);
+        assert_eq!(queue, ["d", "a", "b", "c"]);
+
+        move_queue_item(&mut queue, 1, 99);
+        assert_eq!(queue, ["d", "b", "c", "a"]);
+
+        move_queue_item(&mut queue, 4, 0);
+        assert_eq!(queue, ["d", "b", "c", "a"]);
+
+        assert_eq!(queue.pop_front(), Some("d"));
+        assert_eq!(queue.pop_front(), Some("b"));
+    }
+
+    #[test]
+    fn unit_get_free_slots() {
+        assert_eq!(get_free_slots(2, 0), 2);
+        assert_eq!(get_free_slots(2, 2), 0);
+        assert_eq!(get_free_slots(0, 0), 1);
+
+        // The limit was raised or lowered while downloads were active
+        assert_eq!(get_free_slots(4, 2), 2);
+        assert_eq!(get_free_slots(1, 3), 0);
+    }
+
+    #[test]
+    fn unit_is_active_download() {
+        let pending_dls = VecDeque::new();
+        let vod = add_vod_to_queue(Vod {
+            q_id: "unit-is-active-download".into(),
+            status: "queued".into(),
+            ..Vod::default()
+        });
+
+        assert!(is_active_download(&pending_dls, &vod.q_id));
+        assert!(!is_active_download(&pending_dls, "unit-unknown-download"));
+
+        // A download that's being started can be cancelled, but only while it's still queued
+        assert!(replace_dlq_vod_status(&vod.q_id, "queued", "cancelled"));
+        assert!(!replace_dlq_vod_status(&vod.q_id, "queued", "downloading"));
+        assert!(!is_active_download(&pending_dls, &vod.q_id));
+
+        add_vod_to_queue(Vod {
+            status: "downloading".into(),
+            ..vod.clone()
+        });
+        assert!(is_active_download(&pending_dls, &vod.q_id));
+    }
+}
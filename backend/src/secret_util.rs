@@ -0,0 +1,440 @@
+// Libs
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
+
+use anyhow::{anyhow, Context};
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use once_cell::sync::{Lazy, OnceCell};
+use serde::{Deserialize, Serialize};
+use tokio::task;
+
+use crate::{
+    app_util::get_app_root_dir,
+    config_util::UFCRConfig,
+    fs_util::{read_journaled_file, write_file_journaled},
+    rt_util::QuitUnwrap,
+};
+
+// Types
+/// Secrets of the configuration, keyed by their location in the configuration.
+pub type Secrets = HashMap<String, String>;
+type StoreKey = [u8; 32];
+
+// Structs
+/// The encrypted secret store, as it's saved on the disk.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SecretStore {
+    key_source: KeySource,
+    /// Base64 encoded salt of the passphrase key derivation.
+    salt: String,
+    nonce: String,
+    data: String,
+}
+
+// Enums
+/// Determines where the key of the secret store comes from.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum KeySource {
+    /// A random key saved in a machine-local key file.
+    KeyFile,
+    /// A key derived from the passphrase in the `UFCR_SECRET_PASSPHRASE` environment variable.
+    Passphrase,
+}
+
+// Statics
+/// Placeholder for the secrets in the configuration sent to the clients.
+pub const REDACTED_SECRET: &str = "<redacted>";
+const PASSPHRASE_ENV_VAR: &str = "UFCR_SECRET_PASSPHRASE";
+static SECRETS_PATH: Lazy<PathBuf> =
+    Lazy::new(|| get_app_root_dir().join("config").join("secrets.json"));
+static KEY_FILE_PATH: Lazy<PathBuf> =
+    Lazy::new(|| get_app_root_dir().join("config").join("secret.key"));
+/// Holds the key derived from the passphrase with its salt, so the key is only derived once.
+static PASSPHRASE_KEY: OnceCell<([u8; 16], StoreKey)> = OnceCell::new();
+/// Secret stores that exist but couldn't be read. These are never written until a read succeeds,
+/// so a missing key or passphrase can't replace the saved credentials with empty ones.
+static UNREADABLE_STORES: Lazy<Mutex<HashSet<PathBuf>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Calls the given function with the location and the value of each secret in the configuration.
+fn visit_secrets(config: &mut UFCRConfig, mut visit: impl FnMut(String, &mut String)) {
+    visit("authToken".into(), &mut config.auth_token);
+    visit("refreshToken".into(), &mut config.refresh_token);
+    visit(
+        "proxyConfig.auth.password".into(),
+        &mut config.proxy_config.auth.password,
+    );
+
+    for account in &mut config.accounts {
+        visit(
+            format!("accounts.{}.authToken", account.name),
+            &mut account.auth_token,
+        );
+        visit(
+            format!("accounts.{}.refreshToken", account.name),
+            &mut account.refresh_token,
+        );
+    }
+}
+
+/// Removes the secrets from the configuration and returns them.
+pub fn take_secrets(config: &mut UFCRConfig) -> Secrets {
+    let mut secrets = Secrets::new();
+
+    visit_secrets(config, |location, value| {
+        if !value.is_empty() {
+            secrets.insert(location, std::mem::take(value));
+        }
+    });
+
+    secrets
+}
+
+/// Fills in the secrets missing from the configuration.
+pub fn restore_secrets(config: &mut UFCRConfig, secrets: &Secrets) {
+    visit_secrets(config, |location, value| {
+        if let Some(secret) = secrets.get(&location).filter(|_| value.is_empty()) {
+            value.clone_from(secret);
+        }
+    });
+}
+
+/// Returns a copy of the configuration with its secrets replaced by a placeholder, so it can be
+/// sent to the clients.
+pub fn redact_secrets(config: &UFCRConfig) -> UFCRConfig {
+    let mut redacted_config = config.clone();
+
+    visit_secrets(&mut redacted_config, |_, value| {
+        if !value.is_empty() {
+            *value = REDACTED_SECRET.to_string();
+        }
+    });
+
+    // The GUI authentication hashes stay in the configuration file, but never leave the server
+    let gui_auth = &mut redacted_config.gui_auth;
+
+    for hash in [&mut gui_auth.password_hash, &mut gui_auth.token_hash] {
+        if !hash.is_empty() {
+            *hash = REDACTED_SECRET.to_string();
+        }
+    }
+
+    redacted_config
+}
+
+/// Replaces the redacted secrets in a configuration sent by a client with the secrets of the
+/// current configuration.
+pub fn restore_redacted_secrets(config: &mut UFCRConfig, current_config: &UFCRConfig) {
+    let secrets = take_secrets(&mut current_config.clone());
+
+    visit_secrets(config, |location, value| {
+        if value == REDACTED_SECRET {
+            *value = secrets.get(&location).cloned().unwrap_or_default();
+        }
+    });
+
+    let gui_auth = &mut config.gui_auth;
+    let current_gui_auth = &current_config.gui_auth;
+
+    for (hash, current_hash) in [
+        (&mut gui_auth.password_hash, &current_gui_auth.password_hash),
+        (&mut gui_auth.token_hash, &current_gui_auth.token_hash),
+    ] {
+        if *hash == REDACTED_SECRET {
+            hash.clone_from(current_hash);
+        }
+    }
+}
+
+/// Reads and decrypts the secret store. Returns no secrets if the store doesn't exist.
+pub async fn read_secret_store() -> anyhow::Result<Secrets> {
+    read_secret_store_at(&SECRETS_PATH).await
+}
+
+/// Encrypts and writes the secrets to the secret store. The key is derived from the passphrase if
+/// one is set, otherwise the machine-local key file is used.
+pub async fn write_secret_store(secrets: Secrets) -> anyhow::Result<()> {
+    write_secret_store_at(&SECRETS_PATH, secrets).await
+}
+
+/// Reads and decrypts the secret store at the given path. A failed read is recorded, so the store
+/// is left untouched by `write_secret_store_at` until it's read successfully.
+async fn read_secret_store_at(path: &Path) -> anyhow::Result<Secrets> {
+    let result = async {
+        let Some(store) = read_journaled_file::<SecretStore>(path).await? else {
+            return Ok(Secrets::new());
+        };
+
+        // Deriving the key and reading the key file would block the async runtime
+        task::spawn_blocking(move || open_secret_store(&store)).await?
+    }
+    .await;
+
+    if result.is_ok() {
+        get_unreadable_stores().remove(path);
+    } else {
+        get_unreadable_stores().insert(path.to_path_buf());
+    }
+
+    result
+}
+
+/// Encrypts and writes the secrets to the secret store at the given path, unless the existing
+/// store couldn't be read.
+async fn write_secret_store_at(path: &Path, secrets: Secrets) -> anyhow::Result<()> {
+    if get_unreadable_stores().contains(path) {
+        return Err(anyhow!(
+            "The saved credentials couldn't be read, so they're left untouched. \
+            Restart UFC Ripper after fixing the secret store"
+        ));
+    }
+
+    let path = path.to_path_buf();
+
+    task::spawn_blocking(move || seal_secret_store(&path, &secrets)).await?
+}
+
+/// Decrypts the secrets of a secret store.
+fn open_secret_store(store: &SecretStore) -> anyhow::Result<Secrets> {
+    let key = match store.key_source {
+        KeySource::KeyFile => read_key_file(false)?,
+        KeySource::Passphrase => {
+            let passphrase = get_passphrase().context(format!(
+                "The credentials are encrypted with a passphrase. \
+                Set the passphrase in the \"{PASSPHRASE_ENV_VAR}\" environment variable"
+            ))?;
+            let salt = STANDARD
+                .decode(&store.salt)
+                .ok()
+                .and_then(|salt| <[u8; 16]>::try_from(salt).ok())
+                .context("The secret store contains an invalid salt")?;
+
+            get_passphrase_key(&passphrase, Some(salt))?.1
+        }
+    };
+
+    let nonce = STANDARD
+        .decode(&store.nonce)
+        .context("The secret store contains an invalid nonce")?;
+    let data = STANDARD
+        .decode(&store.data)
+        .context("The secret store contains invalid data")?;
+
+    decrypt_secrets(&key, &nonce, &data)
+}
+
+/// Encrypts the secrets and writes them to a secret store.
+fn seal_secret_store(path: &Path, secrets: &Secrets) -> anyhow::Result<()> {
+    let (key_source, salt, key) = match get_passphrase() {
+        Some(passphrase) => {
+            let (salt, key) = get_passphrase_key(&passphrase, None)?;
+
+            (KeySource::Passphrase, STANDARD.encode(salt), key)
+        }
+        None => (KeySource::KeyFile, String::new(), read_key_file(true)?),
+    };
+
+    let (nonce, data) = encrypt_secrets(&key, secrets)?;
+    let store = SecretStore {
+        key_source,
+        salt,
+        nonce: STANDARD.encode(nonce),
+        data: STANDARD.encode(data),
+    };
+
+    write_file_journaled(path, &serde_json::to_vec_pretty(&store)?)
+}
+
+/// Locks and returns a `MutexGuard` for the secret stores that couldn't be read.
+fn get_unreadable_stores() -> MutexGuard<'static, HashSet<PathBuf>> {
+    UNREADABLE_STORES
+        .lock()
+        .unwrap_or_quit("Failed to exclusively access the state of the secret store")
+}
+
+/// Returns the passphrase of the secret store, if one is set.
+fn get_passphrase() -> Option<String> {
+    env::var(PASSPHRASE_ENV_VAR)
+        .ok()
+        .filter(|passphrase| !passphrase.is_empty())
+}
+
+/// Derives a key from the passphrase using Argon2. A new salt is generated if none is given.
+fn get_passphrase_key(
+    passphrase: &str,
+    salt: Option<[u8; 16]>,
+) -> anyhow::Result<([u8; 16], StoreKey)> {
+    if let Some(cached) = PASSPHRASE_KEY.get() {
+        if salt.map_or(true, |salt| salt == cached.0) {
+            return Ok(*cached);
+        }
+    }
+
+    let salt = salt.unwrap_or_else(|| {
+        let mut salt = [0u8; 16];
+
+        OsRng.fill_bytes(&mut salt);
+        salt
+    });
+    let mut key = StoreKey::default();
+
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+        .map_err(|error| anyhow!("Failed to derive a key from the passphrase: {error}"))?;
+
+    PASSPHRASE_KEY.set((salt, key)).ok();
+
+    Ok((salt, key))
+}
+
+/// Reads the machine-local key file. A new random key is created if the file doesn't exist and
+/// `create` is set.
+fn read_key_file(create: bool) -> anyhow::Result<StoreKey> {
+    if KEY_FILE_PATH.exists() {
+        let key = fs::read(KEY_FILE_PATH.as_path()).context("Failed to read the key file")?;
+
+        return StoreKey::try_from(key)
+            .map_err(|_| anyhow!("The key file contains an invalid key"));
+    }
+
+    if !create {
+        return Err(anyhow!(
+            r#"The key file of the credentials ("secret.key") is missing"#
+        ));
+    }
+
+    let mut key = StoreKey::default();
+    let mut options = fs::OpenOptions::new();
+
+    OsRng.fill_bytes(&mut key);
+    options.write(true).create_new(true);
+
+    // Only the owner of the key file should be able to read it
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options
+        .open(KEY_FILE_PATH.as_path())
+        .and_then(|mut file| file.write_all(&key))
+        .context("Failed to create the key file")?;
+
+    Ok(key)
+}
+
+/// Encrypts the secrets with ChaCha20-Poly1305 and returns the nonce with the encrypted data.
+fn encrypt_secrets(key: &StoreKey, secrets: &Secrets) -> anyhow::Result<(Vec<u8>, Vec<u8>)> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let data = cipher
+        .encrypt(&nonce, serde_json::to_vec(secrets)?.as_slice())
+        .map_err(|_| anyhow!("Failed to encrypt the credentials"))?;
+
+    Ok((nonce.to_vec(), data))
+}
+
+/// Decrypts secrets encrypted by `encrypt_secrets`.
+fn decrypt_secrets(key: &StoreKey, nonce: &[u8], data: &[u8]) -> anyhow::Result<Secrets> {
+    if nonce.len() != 12 {
+        return Err(anyhow!("The secret store contains an invalid nonce"));
+    }
+
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let secrets = cipher
+        .decrypt(Nonce::from_slice(nonce), data)
+        .map_err(|_| {
+            anyhow!("Failed to decrypt the credentials. The key or the passphrase has changed")
+        })?;
+
+    serde_json::from_slice(&secrets).context("The decrypted credentials are not valid")
+}
+
+/*************
+ *   Tests   *
+ *************/
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use crate::config_util::{AccountProfile, GuiAuth, UFCRConfig};
+
+    use super::{
+        decrypt_secrets, encrypt_secrets, read_secret_store_at, redact_secrets,
+        restore_redacted_secrets, restore_secrets, take_secrets, write_secret_store_at, Secrets,
+        REDACTED_SECRET,
+    };
+
+    #[test]
+    fn unit_take_and_restore_secrets() {
+        let mut config = UFCRConfig {
+            auth_token: "auth".into(),
+            refresh_token: "refresh".into(),
+            accounts: vec![AccountProfile {
+                name: "main".into(),
+                auth_token: "auth".into(),
+                refresh_token: "refresh".into(),
+                ..AccountProfile::default()
+            }],
+            gui_auth: GuiAuth {
+                password_hash: "$argon2id$hash".into(),
+                ..GuiAuth::default()
+            },
+            ..UFCRConfig::default()
+        };
+        let original_config = config.clone();
+
+        let secrets = take_secrets(&mut config);
+        assert_eq!(secrets.len(), 4);
+        assert!(config.auth_token.is_empty());
+        assert!(config.accounts[0].refresh_token.is_empty());
+
+        restore_secrets(&mut config, &secrets);
+        assert_eq!(config, original_config);
+
+        let mut redacted_config = redact_secrets(&config);
+        assert_eq!(redacted_config.auth_token, REDACTED_SECRET);
+        assert_eq!(redacted_config.gui_auth.password_hash, REDACTED_SECRET);
+        assert!(redacted_config.gui_auth.token_hash.is_empty());
+        assert!(redacted_config.proxy_config.auth.password.is_empty());
+
+        restore_redacted_secrets(&mut redacted_config, &config);
+        assert_eq!(redacted_config, original_config);
+    }
+
+    #[test]
+    fn unit_encrypt_secrets() {
+        let key = [7u8; 32];
+        let secrets = [("authToken".to_string(), "auth".to_string())].into();
+        let (nonce, data) = encrypt_secrets(&key, &secrets).unwrap();
+
+        assert_eq!(decrypt_secrets(&key, &nonce, &data).unwrap(), secrets);
+        assert!(decrypt_secrets(&[8u8; 32], &nonce, &data).is_err());
+    }
+
+    #[tokio::test]
+    async fn unit_write_unreadable_secret_store() {
+        let path = env::temp_dir().join(format!("ufcr-secrets-{}.json", uuid::Uuid::new_v4()));
+        // The salt is invalid, so the store can't be read with or without a passphrase
+        let store = r#"{"keySource":"passphrase","salt":"","nonce":"","data":""}"#;
+
+        fs::write(&path, store).unwrap();
+        assert!(read_secret_store_at(&path).await.is_err());
+
+        assert!(write_secret_store_at(&path, Secrets::new()).await.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), store);
+
+        fs::remove_file(&path).unwrap();
+        assert!(read_secret_store_at(&path).await.unwrap().is_empty());
+    }
+}
@@ -1,14 +1,23 @@
 // Libs
 use std::{
     collections::HashMap,
+    path::PathBuf,
     sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
 };
 
 use anyhow::Context;
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 use serde::{Deserialize, Serialize};
+use tokio::{sync::Notify, task, time::sleep};
 
-use crate::rt_util::QuitUnwrap;
+use ufcr_libs::{log_err, log_warn};
+
+use crate::{
+    app_util::get_app_root_dir,
+    fs_util::{read_journaled_file, write_file_journaled},
+    rt_util::QuitUnwrap,
+};
 
 // Types
 type VodMap = HashMap<String, Vod>;
@@ -22,6 +31,15 @@ pub struct Vod {
     pub title: String,
     pub desc: String,
     pub thumb: String,
+    /// Duration in seconds.
+    #[serde(default)]
+    pub duration: u64,
+    /// Release date as a Unix timestamp.
+    #[serde(default)]
+    pub date: Option<u64>,
+    /// Bout and round markers of the VOD.
+    #[serde(default)]
+    pub chapters: Vec<VodChapter>,
     pub access: bool,
     #[serde(rename = "vodURL")]
     pub vod_url: String,
@@ -36,12 +54,151 @@ pub struct Vod {
     pub speed: String,
     pub eta: String,
     pub idx: u64,
+    #[serde(default)]
+    pub out_path: String,
+    /// Output path of the download relative to the downloads directory, without the extension. It's
+    /// rendered when the download starts, so a restarted download keeps writing to the same files.
+    #[serde(default)]
+    pub out_base: String,
+    #[serde(default)]
+    pub attempt: u32,
+    /// Name of the account profile used for the download. The active account is used when empty.
+    #[serde(default)]
+    pub account: String,
+    /// Result of the verification of the downloaded file.
+    #[serde(default)]
+    pub verification: Option<VodVerification>,
+}
+
+/// A bout or round marker in a VOD.
+#[derive(Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VodChapter {
+    pub title: String,
+    /// Start time in seconds.
+    pub start: u64,
+}
+
+/// Result of the post-download verification of a VOD's output file.
+#[derive(Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VodVerification {
+    pub passed: bool,
+    /// Duration of the output file in seconds.
+    pub duration: f64,
+    pub width: u64,
+    pub height: u64,
+    pub has_video: bool,
+    pub has_audio: bool,
+    /// Problems found in the output file. Empty when the verification passed.
+    pub issues: Vec<String>,
 }
 
 // Statics
+/// Path of the file that the downloads-queue is saved to.
+static DLQ_PATH: Lazy<PathBuf> = Lazy::new(|| get_app_root_dir().join("config").join("dlq.json"));
 /// Holds the global downloads-queue.
 static DOWNLOADS_QUEUE: Lazy<Arc<Mutex<VodMap>>> =
     Lazy::new(|| Arc::new(Mutex::new(HashMap::new())));
+/// Wakes up the writer task of the downloads-queue when the queue changes.
+static DLQ_CHANGED: Lazy<Notify> = Lazy::new(Notify::new);
+/// Set once the writer task of the downloads-queue is started.
+static DLQ_WRITER: OnceCell<()> = OnceCell::new();
+/// Time to wait for more changes before writing the downloads-queue, since progress updates of
+/// active downloads come in bursts.
+const DLQ_WRITE_DELAY: Duration = Duration::from_millis(500);
+
+/// Loads the downloads-queue saved on the disk into the global downloads-queue. Downloads that
+/// were still active or queued when the application stopped are marked as "interrupted", so they
+/// can be resumed from the GUI. Paused downloads stay paused, since they can be resumed the same
+/// way.
+pub async fn load_dlq() {
+    let mut saved_q = match read_journaled_file::<VodMap>(&DLQ_PATH).await {
+        Ok(q) => q.unwrap_or_default(),
+        Err(error) => {
+            log_err!(
+                "Failed to read the saved downloads-queue. \
+                Starting with an empty downloads-queue:\n{error}\n"
+            );
+            VodMap::new()
+        }
+    };
+
+    let interrupted = mark_interrupted_vods(&mut saved_q);
+
+    if interrupted > 0 {
+        log_warn!(
+            "{interrupted} download(s) were interrupted when UFC Ripper last stopped. \
+            You can resume them from the downloads-queue\n"
+        );
+    }
+
+    *get_dlq() = saved_q;
+    start_dlq_writer();
+    persist_dlq();
+}
+
+/// Reads the downloads-queue saved on the disk without loading it, so the downloads-queue of
+/// another running instance can be shown.
+pub async fn read_saved_dlq() -> anyhow::Result<HashMap<String, Vod>> {
+    read_journaled_file::<VodMap>(&DLQ_PATH)
+        .await
+        .map(Option::unwrap_or_default)
+        .context("Failed to read the saved downloads-queue")
+}
+
+/// Marks all active and queued downloads in a downloads-queue as "interrupted" and returns the
+/// count. Paused downloads have no running task either, so they're left as they are.
+fn mark_interrupted_vods(q: &mut VodMap) -> usize {
+    let mut count = 0;
+
+    for vod in q
+        .values_mut()
+        .filter(|vod| vod.status == "downloading" || vod.status == "queued")
+    {
+        vod.status = "interrupted".to_string();
+        count += 1;
+    }
+
+    count
+}
+
+/// Starts the task that writes the downloads-queue to dlq.json whenever it changes. Only one
+/// writer task is started, and the downloads-queue is never saved without it.
+fn start_dlq_writer() {
+    if DLQ_WRITER.set(()).is_err() {
+        return;
+    }
+
+    tokio::spawn(async {
+        loop {
+            DLQ_CHANGED.notified().await;
+            sleep(DLQ_WRITE_DELAY).await;
+
+            // Only a snapshot is taken while the downloads-queue is locked
+            let q = get_dlq().clone();
+            let result = task::spawn_blocking(move || {
+                serde_json::to_vec_pretty(&q)
+                    .context("Failed to serialize the downloads-queue")
+                    .and_then(|data| write_file_journaled(&DLQ_PATH, &data))
+            })
+            .await
+            .context("The downloads-queue writer exited unexpectedly")
+            .and_then(|result| result);
+
+            // Failures are only logged because the in-memory downloads-queue is still usable
+            if let Err(error) = result {
+                log_err!("Failed to save the downloads-queue to the disk:\n{error}\n");
+            }
+        }
+    });
+}
+
+/// Schedules a write of the downloads-queue to dlq.json. Changes made while a write is pending
+/// are saved together.
+fn persist_dlq() {
+    DLQ_CHANGED.notify_one();
+}
 
 /// Locks and returns a `MutexGuard` for the downloads-queue.
 pub fn get_dlq() -> MutexGuard<'static, VodMap> {
@@ -50,35 +207,105 @@ pub fn get_dlq() -> MutexGuard<'static, VodMap> {
         .unwrap_or_quit("Failed to exclusively access the downloads-queue")
 }
 
-/// Adds a new VOD to the downloads-queue.
+/// Adds a new VOD to the downloads-queue, or replaces the existing entry while keeping its
+/// position in the queue.
 pub fn add_vod_to_queue(mut vod: Vod) -> Vod {
     let mut q = get_dlq();
 
-    if q.contains_key(&vod.q_id) {
-        q.get_mut(&vod.q_id).unwrap().status = "downloading".to_string();
+    vod.idx = if let Some(queued) = q.get(&vod.q_id) {
+        queued.idx
     } else {
-        // FIXME: This will be an issue when clearing the DLQ because the VODs added in the future
-        //        could have the same `idx` since the DL queue's length could be reduced.
-        vod.idx = (q.len() + 1) as u64;
-        q.insert(vod.q_id.clone(), vod.clone());
-    }
+        // The queue's length can't be used here since the queue could've been cleared or loaded
+        // from the disk, which would result in duplicate indexes.
+        q.values().map(|queued| queued.idx).max().unwrap_or(0) + 1
+    };
+
+    q.insert(vod.q_id.clone(), vod.clone());
+    persist_dlq();
 
     vod
 }
 
-/// Updates the status of a VOD in the downloads-queue.
-pub fn update_dlq_vod_status(q_id: &str, status: &str) -> anyhow::Result<()> {
+/// Returns the number of VODs in the downloads-queue with the given status.
+pub fn count_dlq_vods_by_status(status: &str) -> usize {
+    get_dlq()
+        .values()
+        .filter(|vod| vod.status == status)
+        .count()
+}
+
+/// Updates a VOD in the downloads-queue using the given function.
+pub fn update_dlq_vod<F>(q_id: &str, update: F) -> anyhow::Result<()>
+where
+    F: FnOnce(&mut Vod),
+{
     let mut q = get_dlq();
     let vod = q
         .get_mut(q_id)
         .context("VOD does not exist in the downloads-queue")?;
 
-    vod.status = status.to_string();
+    update(vod);
+    persist_dlq();
 
     Ok(())
 }
 
+/// Updates the status of a VOD in the downloads-queue.
+pub fn update_dlq_vod_status(q_id: &str, status: &str) -> anyhow::Result<()> {
+    update_dlq_vod(q_id, |vod| vod.status = status.to_string())
+}
+
+/// Changes the status of a VOD in the downloads-queue, but only if it still has the expected
+/// status. Returns whether the status was changed.
+pub fn replace_dlq_vod_status(q_id: &str, expected: &str, status: &str) -> bool {
+    let mut q = get_dlq();
+
+    match q.get_mut(q_id) {
+        Some(vod) if vod.status == expected => {
+            vod.status = status.to_string();
+            persist_dlq();
+
+            true
+        }
+        _ => false,
+    }
+}
+
 /// Removes finished or failed downloads from the downloads-queue.
 pub fn clear_inactive_dlq_vods() {
-    get_dlq().retain(|_, vod| vod.status == "downloading");
+    let mut q = get_dlq();
+
+    q.retain(|_, vod| matches!(vod.status.as_str(), "downloading" | "queued" | "paused"));
+    persist_dlq();
+}
+
+/*************
+ *   Tests   *
+ *************/
+
+#[cfg(test)]
+mod tests {
+    use super::{mark_interrupted_vods, Vod, VodMap};
+
+    #[test]
+    fn unit_mark_interrupted_vods() {
+        let mut q: VodMap = ["downloading", "queued", "paused", "completed", "failed"]
+            .into_iter()
+            .map(|status| {
+                let vod = Vod {
+                    q_id: status.to_string(),
+                    status: status.to_string(),
+                    ..Vod::default()
+                };
+
+                (vod.q_id.clone(), vod)
+            })
+            .collect();
+
+        assert_eq!(mark_interrupted_vods(&mut q), 2);
+        assert_eq!(q["downloading"].status, "interrupted");
+        assert_eq!(q["queued"].status, "interrupted");
+        assert_eq!(q["paused"].status, "paused");
+        assert_eq!(q["completed"].status, "completed");
+    }
 }
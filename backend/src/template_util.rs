@@ -0,0 +1,389 @@
+// Libs
+use anyhow::anyhow;
+use regex_lite::Regex;
+
+use crate::{config_util::UFCRConfig, state_util::Vod};
+
+// Enums
+/// A part of a parsed output template.
+#[derive(Debug, PartialEq)]
+enum TemplatePart {
+    Text(String),
+    Placeholder(String),
+}
+
+// Statics
+/// Placeholders supported by the output templates.
+pub const TEMPLATE_PLACEHOLDERS: [&str; 9] = [
+    "number",
+    "title",
+    "id",
+    "date",
+    "year",
+    "resolution",
+    "formatID",
+    "event",
+    "series",
+];
+/// Templates of the media-server library layout, which places each VOD in its own directory.
+const LIBRARY_FILE_TEMPLATE: &str = "{title}";
+const LIBRARY_DIR_TEMPLATE: &str = "{series}/Season {year}/{title}";
+/// Maximum length of a file or directory name in bytes. Leaves room for the extensions `yt-dlp`
+/// adds to the partially downloaded files, within the 255 byte limit of most file systems.
+const MAX_FILENAME_LENGTH: usize = 200;
+/// File names reserved by Windows, regardless of their extension.
+const RESERVED_FILENAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+/// Characters left at the edges of a name when one of its placeholders is empty.
+const ORPHAN_SEPARATORS: [char; 6] = [' ', '.', '-', '_', ',', '|'];
+
+/// Checks if the file and directory templates of the configuration are valid.
+pub fn validate_output_templates(file_template: &str, dir_template: &str) -> anyhow::Result<()> {
+    if file_template.trim().is_empty() {
+        return Err(anyhow!("The file name template is empty"));
+    }
+
+    if file_template.contains(['/', '\\']) {
+        return Err(anyhow!(
+            "The file name template can't contain directories. Use the directory template instead"
+        ));
+    }
+
+    parse_template(file_template)
+        .map_err(|error| anyhow!("Invalid file name template. {error}"))?;
+
+    if dir_template.starts_with(['/', '\\']) || dir_template.contains(':') {
+        return Err(anyhow!(
+            "The directory template must be relative to the downloads directory"
+        ));
+    }
+
+    for component in split_dir_template(dir_template) {
+        if component.trim() == ".." {
+            return Err(anyhow!(
+                "The directory template can't point outside the downloads directory"
+            ));
+        }
+
+        parse_template(component)
+            .map_err(|error| anyhow!("Invalid directory template. {error}"))?;
+    }
+
+    Ok(())
+}
+
+/// Creates the output path of a VOD's download from the templates of the configuration. The path
+/// is relative to the downloads directory, uses `/` as the separator and doesn't include the file
+/// extension. The file number is left out when numbering is disabled. The library mode replaces
+/// the templates with the media-server library layout.
+pub fn create_output_path(config: &UFCRConfig, vod: &Vod, format: &str) -> anyhow::Result<String> {
+    let number = config.number_files.then_some(config.cur_number);
+    let (file_template, dir_template) = if config.library_mode {
+        (LIBRARY_FILE_TEMPLATE, LIBRARY_DIR_TEMPLATE)
+    } else {
+        (config.file_template.as_str(), config.dir_template.as_str())
+    };
+    let mut components = Vec::new();
+
+    for component in split_dir_template(dir_template) {
+        let name = render_template(&parse_template(component)?, vod, number, format);
+
+        if !name.is_empty() {
+            components.push(name);
+        }
+    }
+
+    let file_name = render_template(&parse_template(file_template)?, vod, number, format);
+
+    components.push(if file_name.is_empty() {
+        sanitize_filename(&vod.id.to_string())
+    } else {
+        file_name
+    });
+
+    Ok(components.join("/"))
+}
+
+/// Splits a directory template into its components, ignoring empty ones.
+fn split_dir_template(dir_template: &str) -> impl Iterator<Item = &str> {
+    dir_template
+        .split(['/', '\\'])
+        .filter(|component| !component.trim().is_empty())
+}
+
+/// Parses a template into text and placeholders. Literal braces are written as `{{` and `}}`.
+fn parse_template(template: &str) -> anyhow::Result<Vec<TemplatePart>> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(char) = chars.next() {
+        match char {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut placeholder = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(char) => placeholder.push(char),
+                        None => return Err(anyhow!(r#"A "{{" is never closed"#)),
+                    }
+                }
+
+                if !TEMPLATE_PLACEHOLDERS.contains(&placeholder.as_str()) {
+                    return Err(anyhow!(
+                        r#"Unknown placeholder "{{{placeholder}}}". Supported placeholders are {}"#,
+                        TEMPLATE_PLACEHOLDERS
+                            .map(|placeholder| format!("{{{placeholder}}}"))
+                            .join(", ")
+                    ));
+                }
+
+                if !text.is_empty() {
+                    parts.push(TemplatePart::Text(std::mem::take(&mut text)));
+                }
+
+                parts.push(TemplatePart::Placeholder(placeholder));
+            }
+            '}' => return Err(anyhow!(r#"A "}}" is never opened"#)),
+            _ => text.push(char),
+        }
+    }
+
+    if !text.is_empty() {
+        parts.push(TemplatePart::Text(text));
+    }
+
+    Ok(parts)
+}
+
+/// Fills in the placeholders of a parsed template and returns it as a sanitized file name.
+fn render_template(parts: &[TemplatePart], vod: &Vod, number: Option<u64>, format: &str) -> String {
+    let rendered: String = parts
+        .iter()
+        .map(|part| match part {
+            TemplatePart::Text(text) => text.clone(),
+            TemplatePart::Placeholder(placeholder) => {
+                get_placeholder_value(placeholder, vod, number, format)
+            }
+        })
+        .collect();
+
+    sanitize_filename(&rendered)
+}
+
+/// Returns the value of a template placeholder for a VOD. Unknown values are left empty.
+fn get_placeholder_value(
+    placeholder: &str,
+    vod: &Vod,
+    number: Option<u64>,
+    format: &str,
+) -> String {
+    match placeholder {
+        "number" => number.map(|number| number.to_string()).unwrap_or_default(),
+        "title" => vod.title.clone(),
+        "id" => vod.id.to_string(),
+        "date" => vod.date.map(format_date).unwrap_or_default(),
+        "year" => vod
+            .date
+            .map(|date| format_date(date)[..4].to_string())
+            .unwrap_or_default(),
+        "resolution" => get_format_resolution(format)
+            .map(|height| format!("{height}p"))
+            .unwrap_or_default(),
+        "formatID" => format.to_string(),
+        "event" => get_event_name(&vod.title).to_string(),
+        "series" => get_series_name(&vod.title).to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Returns the video height selected by a `yt-dlp` format, if the format specifies one.
+pub fn get_format_resolution(format: &str) -> Option<u64> {
+    Regex::new(r"height[<>]?=?(\d+)")
+        .ok()?
+        .captures(format)?
+        .get(1)?
+        .as_str()
+        .parse()
+        .ok()
+}
+
+/// Returns the name of the event a VOD belongs to, which is the part of the title before the
+/// first colon ("UFC 300: Pereira vs. Hill" -> "UFC 300"). The whole title is used if there's no
+/// colon.
+pub fn get_event_name(title: &str) -> &str {
+    title
+        .split_once(':')
+        .map_or(title, |(event, _)| event)
+        .trim()
+}
+
+/// Returns the name of the series an event belongs to, which is the event name without its number
+/// ("UFC Fight Night 240" -> "UFC Fight Night").
+pub fn get_series_name(title: &str) -> &str {
+    let event = get_event_name(title);
+    let series =
+        event.trim_end_matches(|char: char| char.is_ascii_digit() || char == '#' || char == ' ');
+
+    if series.is_empty() {
+        event
+    } else {
+        series
+    }
+}
+
+/// Formats a Unix timestamp as an ISO 8601 date (YYYY-MM-DD) in UTC.
+pub fn format_date(timestamp: u64) -> String {
+    // Converts the days since the Unix epoch to a civil date
+    // Ref: https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = (timestamp / 86_400) + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Makes a name safe to use as a file or directory name on every platform. Forbidden characters
+/// are replaced, the separators left over by empty placeholders are removed, reserved names are
+/// escaped and the name is truncated to a safe length.
+pub fn sanitize_filename(name: &str) -> String {
+    let mut sanitized = String::with_capacity(name.len());
+
+    for char in name.chars() {
+        match char {
+            ':' => sanitized.push_str(" -"),
+            '"' => sanitized.push('\''),
+            '<' | '>' | '|' | '?' | '*' | '/' | '\\' => sanitized.push('_'),
+            _ if char.is_control() => {}
+            _ => sanitized.push(char),
+        }
+    }
+
+    let mut sanitized = sanitized
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .trim_matches(ORPHAN_SEPARATORS.as_slice())
+        .to_string();
+
+    let stem = sanitized.split('.').next().unwrap_or_default();
+
+    if RESERVED_FILENAMES.contains(&stem.to_uppercase().as_str()) {
+        sanitized.insert(0, '_');
+    }
+
+    if sanitized.len() > MAX_FILENAME_LENGTH {
+        let mut end = MAX_FILENAME_LENGTH;
+
+        while !sanitized.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        sanitized.truncate(end);
+        sanitized = sanitized
+            .trim_end_matches(ORPHAN_SEPARATORS.as_slice())
+            .to_string();
+    }
+
+    sanitized
+}
+
+/*************
+ *   Tests   *
+ *************/
+
+#[cfg(test)]
+mod tests {
+    use crate::{config_util::UFCRConfig, state_util::Vod};
+
+    use super::{create_output_path, format_date, sanitize_filename, validate_output_templates};
+
+    #[test]
+    fn unit_create_output_path() {
+        let vod = Vod {
+            id: 123_456,
+            title: "UFC 300: Pereira vs. Hill".into(),
+            date: Some(1_713_052_800),
+            ..Vod::default()
+        };
+        let format = "bestvideo[height=1080]+bestaudio";
+        let config = UFCRConfig {
+            cur_number: 7,
+            ..UFCRConfig::default()
+        };
+
+        assert_eq!(
+            create_output_path(&config, &vod, format).unwrap(),
+            "7. UFC 300 - Pereira vs. Hill"
+        );
+
+        let config = UFCRConfig {
+            number_files: false,
+            file_template: "{number}. {title} [{resolution}] ({id})".into(),
+            dir_template: "{event}/{year}/{{{date}}}".into(),
+            ..config
+        };
+
+        assert_eq!(
+            create_output_path(&config, &vod, format).unwrap(),
+            "UFC 300/2024/{2024-04-14}/UFC 300 - Pereira vs. Hill [1080p] (123456)"
+        );
+
+        let config = UFCRConfig {
+            library_mode: true,
+            ..config
+        };
+
+        assert_eq!(
+            create_output_path(&config, &vod, format).unwrap(),
+            "UFC/Season 2024/UFC 300 - Pereira vs. Hill/UFC 300 - Pereira vs. Hill"
+        );
+    }
+
+    #[test]
+    fn unit_validate_output_templates() {
+        assert!(validate_output_templates("{number}. {title}", "").is_ok());
+        assert!(validate_output_templates("{title}", "{event}/{year}").is_ok());
+        assert!(validate_output_templates("", "").is_err());
+        assert!(validate_output_templates("{name}", "").is_err());
+        assert!(validate_output_templates("{title", "").is_err());
+        assert!(validate_output_templates("{event}/{title}", "").is_err());
+        assert!(validate_output_templates("{title}", "/{event}").is_err());
+        assert!(validate_output_templates("{title}", "{event}/../..").is_err());
+    }
+
+    #[test]
+    fn unit_sanitize_filename() {
+        assert_eq!(
+            sanitize_filename(r#"A/B\C<D>E|F?G*H"I"#),
+            "A_B_C_D_E_F_G_H'I"
+        );
+        assert_eq!(sanitize_filename(". Title  ."), "Title");
+        assert_eq!(sanitize_filename("con.mp4"), "_con.mp4");
+        assert_eq!(sanitize_filename(&"é".repeat(150)).len(), 200);
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951_782_400), "2000-02-29");
+    }
+}
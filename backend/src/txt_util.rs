@@ -1,33 +1,146 @@
 // Libs
 use anyhow::{anyhow, Context};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use regex_lite::Regex;
 use reqwest::Url;
 use serde_json::json;
 use uuid::Uuid;
 
-use crate::net_util::{JSON, JsonTryGet};
+use crate::{
+    bin_util::{AudioTrack, FailureClass, SubtitleTrack, VodFormats},
+    net_util::{JSON, JsonTryGet},
+};
+
+// Enums
+/// The content a Fight Pass link points to.
+#[derive(Debug, PartialEq)]
+pub enum FightPassLink {
+    Vod(u64),
+    Playlist(u64),
+    /// An event page, identified by the path of the page.
+    Event(String),
+}
+
+/// Describes a classified stderr line from a `yt-dlp` process.
+#[derive(Debug, PartialEq)]
+pub enum YtDlpStderr {
+    /// A harmless warning, that should only be shown to the user.
+    Warning(String),
+    /// A fatal error, along with the class of the failure.
+    Error(String, FailureClass),
+    /// Any other diagnostic output.
+    Info(String),
+}
+
+// Statics
+/// Domains of the supported Fight Pass regions.
+const FIGHT_PASS_DOMAINS: [&str; 2] = ["ufcfightpass.com", "ufcfightpass.com.br"];
 
 /// Creates a UUID and returns it as a `String`.
 pub fn create_uuid() -> String {
     Uuid::new_v4().to_string()
 }
 
-/// Validates a Fight Pass VOD URL and returns the VOD ID.
+/// Creates the Fight Pass URL of a VOD for the given region.
+pub fn create_vod_url(region: &str, vod_id: u64) -> String {
+    let domain = if region == "dce.ufcbrazil" {
+        "ufcfightpass.com.br"
+    } else {
+        "ufcfightpass.com"
+    };
+
+    format!("https://{domain}/video/{vod_id}")
+}
+
+/// Removes the HTML tags (search highlights) from a text.
+pub fn strip_html_tags(text: &str) -> String {
+    Regex::new(r"<[^>]*>")
+        .unwrap()
+        .replace_all(text, "")
+        .to_string()
+}
+
+/// Decodes a JWT access token and returns its expiry time as a Unix timestamp, without verifying
+/// the signature.
+pub fn get_jwt_expiry(token: &str) -> Option<u64> {
+    let payload = token.split('.').nth(1)?;
+    let decoded = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+
+    serde_json::from_slice::<JSON>(&decoded)
+        .ok()?
+        .try_get("exp")
+        .as_u64()
+}
+
+/// Validates a Fight Pass VOD URL or a bare VOD ID and returns the VOD ID.
 pub fn get_vod_id_from_url(url: &str) -> anyhow::Result<String> {
+    match resolve_fight_pass_link(url)? {
+        FightPassLink::Vod(vod_id) => Ok(vod_id.to_string()),
+        FightPassLink::Playlist(_) => Err(anyhow!(
+            "Provided URL belongs to a playlist. Please use the URL of a single video"
+        )),
+        FightPassLink::Event(_) => Err(anyhow!(
+            "Provided URL belongs to an event page. Please use the URL of a single video"
+        )),
+    }
+}
+
+/// Resolves a Fight Pass link to the content it points to. Accepts bare VOD IDs, and video,
+/// playlist and event page URLs of all supported regions, with or without the scheme.
+pub fn resolve_fight_pass_link(link: &str) -> anyhow::Result<FightPassLink> {
     let err_msg = "Provided URL is invalid";
+    let link = link.trim();
 
-    match Url::parse(url)
-        .context(err_msg)?
-        .path()
-        .split("/video/")
-        .nth(1)
-    {
-        Some(split_id) => match split_id.split('/').next() {
-            Some(vod_id) => Ok(vod_id.to_string()),
-            None => Err(anyhow!(err_msg)),
-        },
-        None => Err(anyhow!(err_msg)),
+    if let Ok(vod_id) = link.parse::<u64>() {
+        return Ok(FightPassLink::Vod(vod_id));
+    }
+
+    let url = if link.contains("://") {
+        Url::parse(link)
+    } else {
+        Url::parse(&format!("https://{link}"))
+    }
+    .context(err_msg)?;
+
+    let is_fight_pass_host = url.host_str().is_some_and(|host| {
+        FIGHT_PASS_DOMAINS
+            .iter()
+            .any(|domain| host == *domain || host.ends_with(&format!(".{domain}")))
+    });
+
+    if !is_fight_pass_host {
+        return Err(anyhow!("{err_msg}. Only UFC Fight Pass URLs are supported"));
+    }
+
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map(|segments| segments.filter(|segment| !segment.is_empty()).collect())
+        .unwrap_or_default();
+
+    // Localized and share URLs have extra segments before the content type
+    for (idx, segment) in segments.iter().enumerate() {
+        let Some(content_id) = segments.get(idx + 1) else {
+            break;
+        };
+
+        match *segment {
+            "video" => return Ok(FightPassLink::Vod(parse_content_id(content_id)?)),
+            "playlist" => return Ok(FightPassLink::Playlist(parse_content_id(content_id)?)),
+            "event" | "page" => return Ok(FightPassLink::Event((*content_id).to_string())),
+            _ => {}
+        }
     }
+
+    Err(anyhow!(err_msg))
+}
+
+/// Parses the numeric ID at the start of a URL path segment, like "123456" or "123456-slug".
+fn parse_content_id(segment: &str) -> anyhow::Result<u64> {
+    segment
+        .split(|char: char| !char.is_ascii_digit())
+        .next()
+        .and_then(|id| id.parse().ok())
+        .context("Provided URL contains an invalid ID")
 }
 
 /// Processes stdout lines from a `yt-dlp` process and returns the progress status as JSON.
@@ -91,21 +204,256 @@ pub fn process_yt_dlp_stdout(line: &str) -> JSON {
     }
 }
 
-/// Processes an stderr line from a `yt-dlp` process and returns a user-friendly message.
-pub fn process_yt_dlp_stderr(line: &str) -> Option<&str> {
-    if line.contains("Requested format is not available") {
-        Some(
+/// Parses the output of a `yt-dlp` formats query, which has the formats as a JSON array and the
+/// subtitles as a JSON object (or "NA" if there are none). Audio-only formats are listed as audio
+/// tracks as well.
+pub fn parse_yt_dlp_formats(formats: &str, subtitles: &str) -> anyhow::Result<VodFormats> {
+    let formats = serde_json::from_str::<JSON>(formats)
+        .ok()
+        .filter(JSON::is_array)
+        .context(
+            "Format output is invalid. Please try again or \
+            check the video on Fight Pass to verify that it actually streams",
+        )?;
+    let get_str = |json: &JSON, key: &str| json.try_get(key).as_str().unwrap_or("").to_string();
+
+    let audio_tracks = formats
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|format| {
+            format.try_get("vcodec").as_str() == Some("none")
+                && format.try_get("acodec").as_str() != Some("none")
+        })
+        .map(|format| AudioTrack {
+            format_id: get_str(format, "format_id"),
+            language: get_str(format, "language"),
+            acodec: get_str(format, "acodec"),
+            tbr: format.try_get("tbr").as_f64(),
+        })
+        .collect();
+
+    let subtitles = serde_json::from_str::<JSON>(subtitles)
+        .ok()
+        .and_then(|subtitles| subtitles.as_object().cloned())
+        .unwrap_or_default()
+        .iter()
+        .map(|(language, tracks)| SubtitleTrack {
+            language: language.clone(),
+            name: get_str(tracks.try_get(0), "name"),
+            ext: get_str(tracks.try_get(0), "ext"),
+        })
+        .collect();
+
+    Ok(VodFormats {
+        formats,
+        audio_tracks,
+        subtitles,
+    })
+}
+
+/// Processes an stderr line from a `yt-dlp` process and classifies it. Known errors are replaced
+/// with user-friendly messages.
+pub fn process_yt_dlp_stderr(line: &str) -> YtDlpStderr {
+    if let Some(warning) = line.strip_prefix("WARNING:") {
+        YtDlpStderr::Warning(warning.trim().to_string())
+    } else if let Some(error) = line.strip_prefix("ERROR:") {
+        match get_yt_dlp_error_message(error) {
+            Some((msg, class)) => YtDlpStderr::Error(msg.to_string(), class),
+            None => YtDlpStderr::Error(error.trim().to_string(), FailureClass::Unknown),
+        }
+    } else {
+        YtDlpStderr::Info(line.to_string())
+    }
+}
+
+/// Returns a user-friendly message for a known `yt-dlp` error, along with the class of the
+/// failure.
+fn get_yt_dlp_error_message(error: &str) -> Option<(&'static str, FailureClass)> {
+    if error.contains("Requested format is not available") {
+        Some((
             "Requested format is not available. \
             Please try checking available formats before downloading",
-        )
-    } else if line.contains("ended before the end-of-stream") {
-        Some("An error occurred in the download stream. Retry the download")
-    } else if line.contains("unable to create directory") {
-        Some(
+            FailureClass::Format,
+        ))
+    } else if error.contains("ended before the end-of-stream") {
+        Some((
+            "An error occurred in the download stream. Retry the download",
+            FailureClass::Stream,
+        ))
+    } else if error.contains("HTTP Error 403") || error.contains("HTTP Error 410") {
+        Some((
+            "The download stream was rejected by the server. The stream URL might have expired. \
+            Retry the download",
+            FailureClass::Stream,
+        ))
+    } else if error.contains("unable to create directory") {
+        Some((
             "Invalid downloads location. \
             Please change it to a valid location from the configuration",
-        )
+            FailureClass::Location,
+        ))
+    } else if error.contains("Unable to download")
+        || error.contains("timed out")
+        || error.contains("Connection reset")
+        || error.contains("Temporary failure in name resolution")
+    {
+        Some((
+            "A network error occurred while downloading. \
+            Check your internet connection and retry the download",
+            FailureClass::Network,
+        ))
     } else {
         None
     }
 }
+
+/*************
+ *   Tests   *
+ *************/
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+    use crate::bin_util::{AudioTrack, FailureClass, SubtitleTrack};
+
+    use super::{
+        get_jwt_expiry, parse_yt_dlp_formats, process_yt_dlp_stderr, resolve_fight_pass_link,
+        FightPassLink, YtDlpStderr,
+    };
+
+    #[test]
+    fn unit_resolve_fight_pass_link() {
+        for (link, expected) in [
+            ("123456", FightPassLink::Vod(123_456)),
+            (
+                "https://ufcfightpass.com/video/123456",
+                FightPassLink::Vod(123_456),
+            ),
+            (
+                "ufcfightpass.com.br/pt/video/123456/ufc-300?playlistId=42",
+                FightPassLink::Vod(123_456),
+            ),
+            (
+                "https://www.ufcfightpass.com/playlist/42",
+                FightPassLink::Playlist(42),
+            ),
+            (
+                "https://ufcfightpass.com/event/ufc-300",
+                FightPassLink::Event("ufc-300".into()),
+            ),
+        ] {
+            assert_eq!(resolve_fight_pass_link(link).unwrap(), expected, "{link}");
+        }
+
+        for link in [
+            "",
+            "https://example.com/video/123456",
+            "https://ufcfightpass.com/video/",
+            "https://ufcfightpass.com/video/abc",
+            "https://notufcfightpass.com/video/123456",
+        ] {
+            assert!(resolve_fight_pass_link(link).is_err(), "{link}");
+        }
+    }
+
+    #[test]
+    fn unit_process_yt_dlp_stderr() {
+        let stderr = "\
+[hlsnative] Downloading m3u8 manifest
+WARNING: [generic] Falling back on generic information extractor
+ERROR: unable to download video data: HTTP Error 403: Forbidden
+Traceback (most recent call last):
+  File \"yt_dlp/__main__.py\", line 17, in <module>
+ERROR: [download] Got error: The read operation timed out
+ERROR:   Something unexpected happened  ";
+        let lines: Vec<YtDlpStderr> = stderr.lines().map(process_yt_dlp_stderr).collect();
+
+        assert_eq!(lines.len(), 7);
+        assert_eq!(
+            lines[0],
+            YtDlpStderr::Info("[hlsnative] Downloading m3u8 manifest".into())
+        );
+        assert_eq!(
+            lines[1],
+            YtDlpStderr::Warning("[generic] Falling back on generic information extractor".into())
+        );
+        assert!(matches!(
+            lines[2],
+            YtDlpStderr::Error(_, FailureClass::Stream)
+        ));
+
+        // The lines of a multiline error are only prefixed on the first line
+        assert_eq!(
+            lines[3],
+            YtDlpStderr::Info("Traceback (most recent call last):".into())
+        );
+        assert_eq!(
+            lines[4],
+            YtDlpStderr::Info(r#"  File "yt_dlp/__main__.py", line 17, in <module>"#.into())
+        );
+
+        assert!(matches!(
+            lines[5],
+            YtDlpStderr::Error(_, FailureClass::Network)
+        ));
+        assert_eq!(
+            lines[6],
+            YtDlpStderr::Error(
+                "Something unexpected happened".into(),
+                FailureClass::Unknown
+            )
+        );
+    }
+
+    #[test]
+    fn unit_get_jwt_expiry() {
+        let payload = URL_SAFE_NO_PAD.encode(r#"{"sub":"fighter","exp":1735689600}"#);
+
+        assert_eq!(
+            get_jwt_expiry(&format!("eyJhbGciOiJIUzI1NiJ9.{payload}.signature")),
+            Some(1_735_689_600)
+        );
+        assert_eq!(get_jwt_expiry("not-a-jwt"), None);
+        assert_eq!(get_jwt_expiry("header.!!!.signature"), None);
+        assert_eq!(get_jwt_expiry(""), None);
+    }
+
+    #[test]
+    fn unit_parse_yt_dlp_formats() {
+        let formats = r#"[
+            {"format_id": "audio-en", "vcodec": "none", "acodec": "mp4a.40.2", "tbr": 128, "language": "en"},
+            {"format_id": "audio-pt", "vcodec": "none", "acodec": "mp4a.40.2", "tbr": null, "language": "pt"},
+            {"format_id": "1080p", "vcodec": "avc1.640028", "acodec": "none", "tbr": 6000}
+        ]"#;
+        let subtitles = r#"{"en": [{"ext": "vtt", "name": "English"}]}"#;
+        let parsed = parse_yt_dlp_formats(formats, subtitles).unwrap();
+
+        assert_eq!(parsed.formats.as_array().map(Vec::len), Some(3));
+        assert_eq!(
+            parsed.audio_tracks[0],
+            AudioTrack {
+                format_id: "audio-en".into(),
+                language: "en".into(),
+                acodec: "mp4a.40.2".into(),
+                tbr: Some(128.0),
+            }
+        );
+        assert_eq!(parsed.audio_tracks[1].tbr, None);
+        assert_eq!(
+            parsed.subtitles,
+            [SubtitleTrack {
+                language: "en".into(),
+                name: "English".into(),
+                ext: "vtt".into(),
+            }]
+        );
+
+        assert!(parse_yt_dlp_formats("[]", "NA")
+            .unwrap()
+            .subtitles
+            .is_empty());
+        assert!(parse_yt_dlp_formats("NA", "NA").is_err());
+    }
+}
@@ -0,0 +1,218 @@
+// Libs
+use std::{path::Path, process::Stdio};
+
+use anyhow::{anyhow, Context};
+use tokio::process::Command;
+
+use crate::{
+    bin_util::BINS,
+    net_util::{JsonTryGet, JSON},
+    state_util::{Vod, VodVerification},
+    template_util::get_format_resolution,
+};
+
+/// Verifies the output file of a completed download with `ffprobe` and `ffmpeg`. The duration is
+/// compared with the duration on Fight Pass, the video and audio streams are checked against the
+/// requested format, and the audio and the video keyframes are decoded to find corrupted data.
+pub async fn verify_download(
+    vod: &Vod,
+    format: &str,
+    output: &Path,
+    tolerance: u64,
+) -> VodVerification {
+    let mut verification = match probe_media(output).await {
+        Ok(probe) => check_media_probe(&probe, vod, format, tolerance),
+        Err(error) => VodVerification {
+            issues: vec![format!("Unable to probe the output file: {error}")],
+            ..VodVerification::default()
+        },
+    };
+
+    match find_decode_errors(output).await {
+        Ok(errors) => {
+            if let Some(error) = errors.lines().next() {
+                verification.issues.push(format!(
+                    "Decoding errors were found in the output file: {error}"
+                ));
+            }
+        }
+        Err(error) => verification
+            .issues
+            .push(format!("Unable to decode the output file: {error}")),
+    }
+
+    verification.passed = verification.issues.is_empty();
+
+    verification
+}
+
+/// Reads the container and stream information of a media file with `ffprobe`.
+async fn probe_media(path: &Path) -> anyhow::Result<JSON> {
+    let output = Command::new(BINS.ffprobe.get_path())
+        .args([
+            "-v",
+            "error",
+            "-print_format",
+            "json",
+            "-show_format",
+            "-show_streams",
+        ])
+        .arg(path)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .context("An error occurred while trying to launch ffprobe")?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{}",
+            String::from_utf8_lossy(&output.stderr).trim().to_string()
+        ));
+    }
+
+    serde_json::from_slice(&output.stdout).context("ffprobe output is invalid")
+}
+
+/// Decodes the audio and the video keyframes of a media file with `ffmpeg`, and returns the
+/// errors it reported. Decoding only the keyframes keeps the check fast for long videos.
+async fn find_decode_errors(path: &Path) -> anyhow::Result<String> {
+    let output = Command::new(BINS.ffmpeg.get_path())
+        .args(["-v", "error", "-skip_frame", "nokey", "-i"])
+        .arg(path)
+        .args(["-map", "0:v:0?", "-map", "0:a?", "-f", "null", "-"])
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .context("An error occurred while trying to launch ffmpeg")?;
+
+    Ok(String::from_utf8_lossy(&output.stderr).trim().to_string())
+}
+
+/// Checks the `ffprobe` information of a downloaded file against the VOD and the requested format.
+fn check_media_probe(probe: &JSON, vod: &Vod, format: &str, tolerance: u64) -> VodVerification {
+    let mut verification = VodVerification {
+        duration: probe
+            .try_get("format")
+            .try_get("duration")
+            .as_str()
+            .and_then(|duration| duration.parse().ok())
+            .unwrap_or(0.0),
+        ..VodVerification::default()
+    };
+    let streams = probe
+        .try_get("streams")
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+
+    for stream in &streams {
+        // Cover art is stored as a video stream with a single picture
+        if stream
+            .try_get("disposition")
+            .try_get("attached_pic")
+            .as_u64()
+            == Some(1)
+        {
+            continue;
+        }
+
+        match stream.try_get("codec_type").as_str() {
+            Some("video") if !verification.has_video => {
+                verification.has_video = true;
+                verification.width = stream.try_get("width").as_u64().unwrap_or(0);
+                verification.height = stream.try_get("height").as_u64().unwrap_or(0);
+            }
+            Some("audio") => verification.has_audio = true,
+            _ => {}
+        }
+    }
+
+    if vod.duration > 0 && (verification.duration - vod.duration as f64).abs() > tolerance as f64 {
+        verification.issues.push(format!(
+            "Duration of the output file ({:.0}s) doesn't match the duration on Fight Pass ({}s)",
+            verification.duration, vod.duration
+        ));
+    }
+
+    if !verification.has_video {
+        verification
+            .issues
+            .push("Output file has no video stream".to_string());
+    }
+
+    if !verification.has_audio {
+        verification
+            .issues
+            .push("Output file has no audio stream".to_string());
+    }
+
+    if let Some(resolution) = get_format_resolution(format) {
+        // Formats like "height<=1080" allow lower resolutions too
+        let matches = if format.contains(&format!("height={resolution}")) {
+            verification.height == resolution
+        } else {
+            verification.height <= resolution
+        };
+
+        if verification.has_video && !matches {
+            verification.issues.push(format!(
+                "Output file has a resolution of {}p instead of the requested {resolution}p",
+                verification.height
+            ));
+        }
+    }
+
+    verification
+}
+
+/*************
+ *   Tests   *
+ *************/
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::state_util::Vod;
+
+    use super::check_media_probe;
+
+    #[test]
+    fn unit_check_media_probe() {
+        let vod = Vod {
+            duration: 3600,
+            ..Vod::default()
+        };
+        let format = "bv*[height=1080]+ba/b*[height=1080]";
+        let probe = json!({
+            "format": { "duration": "3604.120000" },
+            "streams": [
+                { "codec_type": "video", "width": 1920, "height": 1080 },
+                { "codec_type": "audio" },
+                { "codec_type": "video", "width": 640, "height": 360, "disposition": { "attached_pic": 1 } }
+            ]
+        });
+        let verification = check_media_probe(&probe, &vod, format, 10);
+
+        assert!(verification.issues.is_empty());
+        assert_eq!((verification.width, verification.height), (1920, 1080));
+
+        let probe = json!({
+            "format": { "duration": "1800.0" },
+            "streams": [{ "codec_type": "video", "width": 1280, "height": 720 }]
+        });
+        let verification = check_media_probe(&probe, &vod, format, 10);
+
+        assert_eq!(
+            verification.issues,
+            [
+                "Duration of the output file (1800s) doesn't match the duration on Fight Pass \
+                (3600s)",
+                "Output file has no audio stream",
+                "Output file has a resolution of 720p instead of the requested 1080p",
+            ]
+        );
+    }
+}
@@ -0,0 +1,319 @@
+// Libs
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::PathBuf,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context};
+use once_cell::sync::{Lazy, OnceCell};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::{
+    sync::Mutex,
+    task::{self, JoinHandle},
+    time::sleep,
+};
+
+use ufcr_libs::{log_err, log_info, log_warn};
+
+use crate::{
+    app_util::get_app_root_dir,
+    bin_util::create_default_format,
+    config_util::{get_config, update_config, ConfigUpdate, Subscription},
+    dl_util::create_download_handlers,
+    fp_util::SearchRequest,
+    fs_util::{read_journaled_file, write_file_journaled},
+    net_util::{get_unix_time, get_vod_meta, search_library, JSON},
+    sched_util::enqueue_download,
+    state_util::Vod,
+    txt_util::create_uuid,
+    ws_util::emit_vod_download_progress,
+};
+
+// Types
+type WatchlistState = HashMap<String, SubscriptionState>;
+
+// Structs
+/// Keeps track of the VODs a subscription has already seen.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct SubscriptionState {
+    seen: BTreeSet<u64>,
+    /// Unix timestamp of the last check.
+    last_checked: u64,
+    /// Error of the last check, if it failed.
+    last_error: String,
+}
+
+// Statics
+/// Number of search results requested per page, and the maximum number of pages requested. The
+/// library search doesn't return more than 1000 results for a query.
+const WATCH_PAGE_SIZE: u64 = 100;
+const WATCH_MAX_PAGES: u64 = 10;
+/// Shortest time between the checks of the watchlist in minutes.
+const MIN_WATCH_INTERVAL: u64 = 15;
+static WATCHLIST_STATE_PATH: Lazy<PathBuf> =
+    Lazy::new(|| get_app_root_dir().join("config").join("watchlist.json"));
+/// Makes sure the scheduled and the manual checks of the watchlist don't overlap.
+static WATCH_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+static WATCHLIST_POLLER: OnceCell<JoinHandle<()>> = OnceCell::new();
+
+/// Starts the background task, which periodically checks the watchlist for new VODs.
+pub fn init_watchlist_poller() {
+    WATCHLIST_POLLER.get_or_init(|| {
+        tokio::spawn(async {
+            loop {
+                let interval = get_config().watch_interval.max(MIN_WATCH_INTERVAL);
+
+                sleep(Duration::from_secs(interval * 60)).await;
+
+                if let Err(error) = check_watchlist().await {
+                    log_err!("Failed to check the watchlist:\n{error}\n");
+                }
+            }
+        })
+    });
+}
+
+/// Checks every enabled subscription of the watchlist, and adds the new VODs to the
+/// downloads-queue. Returns the queued VODs.
+pub async fn check_watchlist() -> anyhow::Result<Vec<Vod>> {
+    let _lock = WATCH_LOCK.lock().await;
+    let watchlist = get_config().watchlist.clone();
+    let mut state = read_watchlist_state().await?;
+    let mut queued = Vec::new();
+
+    // Forgets the VODs seen by removed subscriptions
+    state.retain(|id, _| watchlist.iter().any(|subscription| &subscription.id == id));
+
+    for subscription in watchlist.iter().filter(|subscription| subscription.enabled) {
+        let sub_state = state.entry(subscription.id.clone()).or_default();
+
+        match check_subscription(subscription, sub_state).await {
+            Ok(vods) => {
+                sub_state.last_error.clear();
+                queued.extend(vods);
+            }
+            Err(error) => {
+                log_err!(
+                    "Failed to check the subscription \"{}\":\n{error}\n",
+                    subscription.name
+                );
+                sub_state.last_error = error.to_string();
+            }
+        }
+    }
+
+    write_watchlist_state(&state).await?;
+
+    if !queued.is_empty() {
+        log_info!(
+            "{} new video(s) from the watchlist were added to the downloads-queue\n",
+            queued.len()
+        );
+    }
+
+    Ok(queued)
+}
+
+/// Searches for all VODs of a subscription and queues the ones it hasn't seen yet. The first check
+/// of a subscription only remembers the existing VODs, so the whole back catalogue isn't
+/// downloaded.
+async fn check_subscription(
+    subscription: &Subscription,
+    state: &mut SubscriptionState,
+) -> anyhow::Result<Vec<Vod>> {
+    let is_first_check = state.last_checked == 0;
+    let new_ids: Vec<u64> = search_subscription_vods(subscription)
+        .await?
+        .into_iter()
+        .filter(|id| !state.seen.contains(id))
+        .collect();
+    let mut queued = Vec::new();
+
+    state.last_checked = get_unix_time();
+
+    if is_first_check {
+        state.seen.extend(new_ids);
+
+        return Ok(queued);
+    }
+
+    for vod_id in new_ids {
+        match enqueue_subscription_vod(subscription, vod_id).await {
+            Ok(vod) => {
+                state.seen.insert(vod_id);
+                queued.extend(vod);
+            }
+            // Unseen VODs are retried with the next check
+            Err(error) => log_err!(
+                "Failed to queue the video {vod_id} of the subscription \"{}\":\n{error}\n",
+                subscription.name
+            ),
+        }
+    }
+
+    Ok(queued)
+}
+
+/// Returns the IDs of all VODs matching the query of a subscription. Every page of the results is
+/// checked, since new uploads aren't guaranteed to be among the first results of a query.
+async fn search_subscription_vods(subscription: &Subscription) -> anyhow::Result<Vec<u64>> {
+    let mut vod_ids = Vec::new();
+
+    for page in 0..WATCH_MAX_PAGES {
+        let result = search_library(SearchRequest {
+            query: subscription.query.clone(),
+            page,
+            hits_per_page: Some(WATCH_PAGE_SIZE),
+            title_only: Some(subscription.title_only),
+            ..SearchRequest::default()
+        })
+        .await?;
+
+        if result.pages > WATCH_MAX_PAGES {
+            return Err(anyhow!(
+                "The search query matches more than {} videos, so new uploads can't be found \
+                reliably. Use a more specific search query for the subscription",
+                WATCH_PAGE_SIZE * WATCH_MAX_PAGES
+            ));
+        }
+
+        vod_ids.extend(result.hits.iter().map(|hit| hit.id));
+
+        if page + 1 >= result.pages {
+            break;
+        }
+    }
+
+    Ok(vod_ids)
+}
+
+/// Adds a VOD to the downloads-queue with the quality settings of a subscription. Returns `None`
+/// if the account can't access the VOD.
+async fn enqueue_subscription_vod(
+    subscription: &Subscription,
+    vod_id: u64,
+) -> anyhow::Result<Option<Vod>> {
+    let vod = get_vod_meta(&vod_id.to_string(), &subscription.account).await?;
+
+    if !vod.access {
+        log_warn!(
+            "Skipping \"{}\" of the subscription \"{}\". \
+            You need a Fight Pass subscription to download this video\n",
+            vod.title,
+            subscription.name
+        );
+
+        return Ok(None);
+    }
+
+    let custom_format = if !subscription.custom_format.is_empty() {
+        subscription.custom_format.clone()
+    } else if !subscription.resolution.is_empty() {
+        create_default_format(&get_config(), &subscription.resolution)
+    } else {
+        String::new()
+    };
+
+    let queued_vod = enqueue_download(
+        Vod {
+            q_id: create_uuid(),
+            custom_format,
+            ..vod
+        },
+        false,
+        create_download_handlers(),
+    )?;
+
+    // Lets the connected clients show the download before a download slot is available
+    emit_vod_download_progress(&queued_vod.q_id, json!(queued_vod));
+
+    Ok(Some(queued_vod))
+}
+
+/// Returns the subscriptions of the watchlist with the time and the result of their last check.
+pub async fn get_watchlist() -> anyhow::Result<Vec<JSON>> {
+    let state = read_watchlist_state().await?;
+    let config = get_config();
+
+    config
+        .watchlist
+        .iter()
+        .map(|subscription| {
+            let mut entry = serde_json::to_value(subscription)?;
+            let sub_state = state.get(&subscription.id);
+
+            entry["lastChecked"] = json!(sub_state.map(|sub_state| sub_state.last_checked));
+            entry["lastError"] = json!(sub_state.map(|sub_state| &sub_state.last_error));
+            entry["seenCount"] = json!(sub_state.map_or(0, |sub_state| sub_state.seen.len()));
+
+            Ok(entry)
+        })
+        .collect()
+}
+
+/// Adds a new subscription to the watchlist, or replaces an existing one with the same ID. The
+/// subscription is named after its query if no name is given.
+pub async fn save_subscription(mut subscription: Subscription) -> anyhow::Result<Subscription> {
+    subscription.query = subscription.query.trim().to_string();
+
+    if subscription.query.is_empty() {
+        return Err(anyhow!("The search query of the subscription is empty"));
+    }
+
+    if get_config().get_account(&subscription.account).is_none() {
+        return Err(anyhow!(
+            r#"Account "{}" does not exist"#,
+            subscription.account
+        ));
+    }
+
+    if subscription.id.is_empty() {
+        subscription.id = create_uuid();
+    }
+
+    if subscription.name.trim().is_empty() {
+        subscription.name.clone_from(&subscription.query);
+    }
+
+    update_config(ConfigUpdate::Subscription(subscription.clone())).await;
+
+    Ok(subscription)
+}
+
+/// Removes a subscription from the watchlist.
+pub async fn remove_subscription(id: &str) -> anyhow::Result<()> {
+    if !get_config()
+        .watchlist
+        .iter()
+        .any(|subscription| subscription.id == id)
+    {
+        return Err(anyhow!("Subscription does not exist in the watchlist"));
+    }
+
+    update_config(ConfigUpdate::RemoveSubscription(id.to_string())).await;
+
+    Ok(())
+}
+
+/// Reads the state of the watchlist from watchlist.json.
+async fn read_watchlist_state() -> anyhow::Result<WatchlistState> {
+    let state = read_journaled_file(&WATCHLIST_STATE_PATH)
+        .await
+        .context("Failed to read the state of the watchlist")?;
+
+    Ok(state.unwrap_or_default())
+}
+
+/// Writes the state of the watchlist to watchlist.json.
+async fn write_watchlist_state(state: &WatchlistState) -> anyhow::Result<()> {
+    let data =
+        serde_json::to_vec_pretty(state).context("Failed to serialize the watchlist state")?;
+
+    task::spawn_blocking(move || write_file_journaled(&WATCHLIST_STATE_PATH, &data))
+        .await
+        .context("The watchlist state writer exited unexpectedly")?
+        .context("Failed to save the state of the watchlist")
+}
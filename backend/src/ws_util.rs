@@ -17,16 +17,32 @@ use ufcr_libs::{log_err, log_info, log_warn};
 
 use crate::{
     app_util::{check_app_update, get_app_metadata},
-    bin_util::{cancel_download, get_vod_formats, start_download, validate_bins},
-    config_util::{get_config, is_debug, migrate_config, update_config, ConfigUpdate, UFCRConfig},
+    auth_util::is_request_authorized,
+    bin_util::{get_vod_formats, preview_output_path, validate_bins, VodFormats},
+    config_util::{
+        get_config, get_redacted_config, is_debug, logout_account, parse_config_data,
+        switch_account, update_config, AccountProfile, ConfigUpdate, Subscription, UFCRConfig,
+    },
+    dl_util::{
+        create_download_handlers, enqueue_collection, verify_urls, CollectionDownload,
+        OutputPathPreview,
+    },
+    fp_util::SearchRequest,
     fs_util::open_downloads_dir,
+    history_util::{
+        export_history, prune_history, query_history, ExportFormat, HistoryQuery, PruneRequest,
+    },
     net_util::{
-        download_media_tools, get_vod_meta, get_vod_stream_url, login_to_fight_pass, search_vods,
-        update_proxied_client, JsonTryGet, JSON,
+        download_media_tools, get_browse_page, get_playlist, get_vod_meta, get_vod_stream_url,
+        login_to_fight_pass, search_library, search_vods, update_proxied_client, JsonTryGet, JSON,
+    },
+    sched_util::{
+        cancel_scheduled_download, enqueue_download, get_pending_dl_order, move_pending_download,
+        pause_scheduled_download, reschedule, resume_download,
     },
-    rt_util::QuitUnwrap,
     state_util::{clear_inactive_dlq_vods, get_dlq, Vod},
     txt_util::create_uuid,
+    watch_util::{check_watchlist, get_watchlist, remove_subscription, save_subscription},
 };
 
 // Statics
@@ -39,7 +55,18 @@ pub fn create_ws_layer() -> SocketIoLayer {
         .ping_timeout(Duration::from_secs(90))
         .build_layer();
 
-    io.ns("/", |socket: SocketRef| handle_ws_client(&socket));
+    io.ns("/", |socket: SocketRef| {
+        // Unauthenticated clients are refused during the handshake
+        if is_request_authorized(&socket.req_parts().headers) {
+            handle_ws_client(&socket);
+        } else {
+            log_warn!(
+                "Refused an unauthenticated GUI connection (ID - {})\n",
+                socket.id
+            );
+            socket.disconnect().ok();
+        }
+    });
     IO.set(io)
         .expect("Failed to initiate the WebSocket instance on the server");
 
@@ -55,7 +82,7 @@ fn handle_ws_client(socket: &SocketRef) {
     });
 
     socket.on("get-config", |ack: AckSender| {
-        ack.send(get_config().as_ref()).ok();
+        ack.send(get_redacted_config()).ok();
     });
 
     socket.on("save-config", handle_save_config_event);
@@ -78,18 +105,67 @@ fn handle_ws_client(socket: &SocketRef) {
 
     socket.on("login", handle_login_event);
 
+    socket.on("logout", handle_logout_event);
+
+    socket.on("list-accounts", |ack: AckSender| {
+        ack.send(get_account_list()).ok();
+    });
+
+    socket.on("switch-account", handle_switch_account_event);
+
     socket.on("search-vods", handle_search_vods_event);
 
+    socket.on("search-library", handle_search_library_event);
+
     socket.on("verify-url", handle_verify_url_event);
 
+    socket.on("verify-urls", handle_verify_urls_event);
+
     socket.on("get-playable", handle_get_playable_event);
 
+    socket.on("get-browse-page", handle_get_browse_page_event);
+
+    socket.on("get-playlist", handle_get_playlist_event);
+
+    socket.on("download-collection", handle_download_collection_event);
+
+    socket.on("get-history", handle_get_history_event);
+
+    socket.on("export-history", handle_export_history_event);
+
+    socket.on("prune-history", handle_prune_history_event);
+
+    socket.on("get-watchlist", |ack: AckSender| async move {
+        send_result(ack, get_watchlist().await);
+    });
+
+    socket.on("save-subscription", handle_save_subscription_event);
+
+    socket.on("remove-subscription", handle_remove_subscription_event);
+
+    socket.on("check-watchlist", |ack: AckSender| async move {
+        send_result(ack, check_watchlist().await);
+    });
+
     socket.on("download", handle_download_event);
 
     socket.on("cancel-download", handle_cancel_download_event);
 
+    socket.on("pause-download", handle_pause_download_event);
+
+    socket.on("resume-download", handle_resume_download_event);
+
+    // Arrays are sent as separate arguments, so the order is wrapped into a single argument
+    socket.on("get-pending-dl-order", |ack: AckSender| {
+        ack.send((get_pending_dl_order(),)).ok();
+    });
+
+    socket.on("move-pending-download", handle_move_pending_download_event);
+
     socket.on("get-formats", handle_get_formats_event);
 
+    socket.on("preview-output-path", handle_preview_output_path_event);
+
     socket.on("clear-dlq", |ack: AckSender| {
         clear_inactive_dlq_vods();
         ack.send(get_dlq().clone()).ok();
@@ -100,19 +176,19 @@ fn handle_ws_client(socket: &SocketRef) {
     });
 }
 
-/// Emits an event with data to all connected clients.
+/// Emits an event with data to all connected clients. Does nothing when the `WebSocket` server
+/// isn't running (CLI mode).
 fn emit_to_all<T>(event: &str, data: T)
 where
     T: Serialize,
 {
-    IO.get()
-        .unwrap_or_quit("Unable to access the global WebSocket instance")
-        .emit(event.to_string(), data)
-        .ok();
+    if let Some(io) = IO.get() {
+        io.emit(event.to_string(), data).ok();
+    }
 }
 
 /// Constructs and sends an error event to all connected clients with the provided error
-fn emit_error<E>(error: E)
+pub fn emit_error<E>(error: E)
 where
     E: Display + Debug,
 {
@@ -136,7 +212,7 @@ where
 }
 
 /// Constructs and sends a warning event to all connected clients with the provided message
-fn emit_warn(msg: &str) {
+pub fn emit_warn(msg: &str) {
     log_warn!("{msg}\n");
 
     emit_to_all(
@@ -150,7 +226,7 @@ fn emit_warn(msg: &str) {
 
 /// Emits any updated configuration to all connected clients
 pub fn emit_config_update() {
-    emit_to_all("config-update", get_config().as_ref());
+    emit_to_all("config-update", get_redacted_config());
 }
 
 /// Sends a response to the client-event with data or an error, according to the `Result`.
@@ -197,37 +273,33 @@ where
 async fn handle_save_config_event(ack: AckSender, Data(data): Data<JSON>) {
     let finish_config_update = |ack: AckSender, new_config: UFCRConfig| async {
         update_config(ConfigUpdate::Config(Box::new(new_config))).await;
-        ack.send(get_config().as_ref()).ok();
+        ack.send(get_redacted_config()).ok();
+        reschedule();
 
         if let Err(error) = update_proxied_client() {
             emit_error(error);
         }
     };
 
-    if let Ok(new_config) = serde_json::from_value::<UFCRConfig>(data.clone()) {
-        finish_config_update(ack, new_config).await;
-    } else if let Ok(new_config_str) = serde_json::to_string(&data) {
-        match migrate_config(&new_config_str) {
-            Ok(migrated_config) => {
-                finish_config_update(ack, migrated_config).await;
+    match parse_config_data(&data) {
+        Ok((new_config, migrated)) => {
+            finish_config_update(ack, new_config).await;
+
+            if migrated {
                 emit_warn(
                     "Imported config file was outdated. A migration was performed before importing",
                 );
             }
-            Err(error) => send_error(ack, format!("Invalid configuration format. {error}")),
         }
-    } else {
-        send_error(
-            ack,
-            "Invalid configuration format. Configuration data is not valid JSON",
-        );
+        Err(error) => send_error(ack, error),
     }
 }
 
 /// Handles the `reset-config` WS event.
 async fn handle_reset_config_event(ack: AckSender) {
     update_config(ConfigUpdate::Default).await;
-    ack.send(get_config().as_ref()).ok();
+    ack.send(get_redacted_config()).ok();
+    reschedule();
 
     if let Err(error) = update_proxied_client() {
         emit_error(error);
@@ -264,18 +336,25 @@ fn send_media_tool_download_progress(socket: &SocketRef, tool: &str, progress: f
         .ok();
 }
 
-/// Handles the `login` WS event.
+/// Handles the `login` WS event. The account is saved under the given profile name, or the email
+/// address if the name is omitted.
 async fn handle_login_event(ack: AckSender, Data(data): Data<JSON>) {
     if let (Some(region), Some(email), Some(pass)) = (
         data.try_get(0).as_str(),
         data.try_get(1).as_str(),
         data.try_get(2).as_str(),
     ) {
+        let name = data.try_get(3).as_str().unwrap_or(email).to_string();
+
         match login_to_fight_pass(region, email, pass).await {
-            Ok(tokens) => {
-                update_config(ConfigUpdate::Region(region.to_string())).await;
-                update_config(ConfigUpdate::Tokens(tokens)).await;
-                ack.send(get_config().as_ref()).ok();
+            Ok(session) => {
+                update_config(ConfigUpdate::Account(AccountProfile::from_session(
+                    name,
+                    region.to_string(),
+                    session,
+                )))
+                .await;
+                ack.send(get_redacted_config()).ok();
             }
             Err(error) => {
                 send_error(ack, error);
@@ -286,6 +365,53 @@ async fn handle_login_event(ack: AckSender, Data(data): Data<JSON>) {
     }
 }
 
+/// Handles the `logout` WS event. Logs out of the active account if no account name is given.
+async fn handle_logout_event(ack: AckSender, Data(data): Data<JSON>) {
+    let name = data.as_str().unwrap_or_default();
+
+    match logout_account(name).await {
+        Ok(()) => {
+            ack.send(get_redacted_config()).ok();
+            emit_config_update();
+        }
+        Err(error) => send_error(ack, error),
+    }
+}
+
+/// Handles the `switch-account` WS event.
+async fn handle_switch_account_event(ack: AckSender, Data(data): Data<JSON>) {
+    if let Some(name) = data.as_str() {
+        match switch_account(name).await {
+            Ok(()) => {
+                ack.send(get_redacted_config()).ok();
+                emit_config_update();
+            }
+            Err(error) => send_error(ack, error),
+        }
+    } else {
+        send_error(ack, "Invalid account switch request");
+    }
+}
+
+/// Returns the saved accounts without their login sessions.
+fn get_account_list() -> JSON {
+    let config = get_config();
+    let accounts: Vec<JSON> = config
+        .get_accounts()
+        .into_iter()
+        .map(|account| {
+            json!({
+                "name": account.name,
+                "region": account.region,
+                "user": account.user,
+                "active": account.name == config.active_account
+            })
+        })
+        .collect();
+
+    json!(accounts)
+}
+
 /// Handles the `search-vods` WS event.
 async fn handle_search_vods_event(ack: AckSender, Data(data): Data<JSON>) {
     if let (Some(query), Some(page)) = (data.try_get(0).as_str(), data.try_get(1).as_u64()) {
@@ -295,10 +421,19 @@ async fn handle_search_vods_event(ack: AckSender, Data(data): Data<JSON>) {
     }
 }
 
+/// Handles the `search-library` WS event, which searches with filters and returns typed results.
+async fn handle_search_library_event(ack: AckSender, Data(data): Data<JSON>) {
+    if let Ok(request) = serde_json::from_value::<SearchRequest>(data) {
+        send_result(ack, search_library(request).await);
+    } else {
+        send_error(ack, "Invalid search request");
+    }
+}
+
 /// Handles the `verify-url` WS event.
 async fn handle_verify_url_event(ack: AckSender, Data(data): Data<JSON>) {
     if let Ok(url) = serde_json::from_value::<String>(data) {
-        match get_vod_meta(url.as_str()).await {
+        match get_vod_meta(url.as_str(), "").await {
             Ok(mut meta) => {
                 meta.q_id = create_uuid();
 
@@ -311,10 +446,30 @@ async fn handle_verify_url_event(ack: AckSender, Data(data): Data<JSON>) {
     }
 }
 
+/// Handles the `verify-urls` WS event. Accepts a pasted list or the contents of a text file, or an
+/// array of URLs.
+async fn handle_verify_urls_event(ack: AckSender, Data(data): Data<JSON>) {
+    let input = match data {
+        JSON::String(text) => Some(text),
+        JSON::Array(urls) => urls
+            .iter()
+            .map(|url| url.as_str())
+            .collect::<Option<Vec<&str>>>()
+            .map(|urls| urls.join("\n")),
+        _ => None,
+    };
+
+    if let Some(input) = input {
+        send_result(ack, verify_urls(&input).await);
+    } else {
+        send_error(ack, "Invalid verify request");
+    }
+}
+
 /// Handles the `get-playable` WS event.
 async fn handle_get_playable_event(ack: AckSender, Data(data): Data<JSON>) {
     if let Ok(url) = serde_json::from_value::<String>(data) {
-        match get_vod_meta(url.as_str()).await {
+        match get_vod_meta(url.as_str(), "").await {
             Ok(mut vod) => {
                 if !vod.access {
                     return send_error(
@@ -323,7 +478,7 @@ async fn handle_get_playable_event(ack: AckSender, Data(data): Data<JSON>) {
                     );
                 }
 
-                match get_vod_stream_url(vod.id).await {
+                match get_vod_stream_url(vod.id, &vod.account).await {
                     Ok(hls) => {
                         vod.hls = hls;
                         vod.q_id = create_uuid();
@@ -340,80 +495,225 @@ async fn handle_get_playable_event(ack: AckSender, Data(data): Data<JSON>) {
     }
 }
 
-/// Handles the `download` WS event.
-async fn handle_download_event(ack: AckSender, Data(mut data): Data<JSON>) {
-    if let (Ok(mut vod), Some(is_restart)) = (
-        serde_json::from_value::<Vod>(data.try_get_mut(0, &mut JSON::Null).take()),
-        data.try_get(1).as_bool(),
-    ) {
-        match get_vod_stream_url(vod.id).await {
-            Ok(hls) => vod.hls = hls,
-            Err(error) => return send_error(ack, error),
-        }
+/// Handles the `get-browse-page` WS event. Returns the home page if no page is given.
+async fn handle_get_browse_page_event(ack: AckSender, Data(data): Data<JSON>) {
+    let page = data.as_str().unwrap_or("home");
 
-        let dl = start_download(
-            &vod,
-            is_restart,
-            move |q_id, updates| {
-                emit_vod_download_progress(q_id, updates);
-            },
-            move |q_id| {
-                emit_vod_download_progress(
-                    q_id,
-                    json!({
-                        "status": "completed"
-                    }),
-                );
-            },
-            move |q_id, error| {
-                emit_error(error);
-                emit_vod_download_progress(
-                    q_id,
-                    json!({
-                        "status": "failed"
-                    }),
-                );
-            },
-        )
-        .await;
+    send_result(ack, get_browse_page(page, "").await);
+}
 
-        if dl.is_ok() {
+/// Handles the `get-playlist` WS event.
+async fn handle_get_playlist_event(ack: AckSender, Data(data): Data<JSON>) {
+    if let Some(playlist_id) = data.as_u64() {
+        send_result(ack, get_playlist(playlist_id, "").await);
+    } else {
+        send_error(ack, "Invalid playlist request");
+    }
+}
+
+/// Handles the `download-collection` WS event.
+async fn handle_download_collection_event(ack: AckSender, Data(data): Data<JSON>) {
+    if let Ok(request) = serde_json::from_value::<CollectionDownload>(data) {
+        send_result(ack, enqueue_collection(request).await);
+    } else {
+        send_error(ack, "Invalid collection download request");
+    }
+}
+
+/// Handles the `get-history` WS event, which queries the download history.
+async fn handle_get_history_event(ack: AckSender, Data(data): Data<JSON>) {
+    if let Ok(query) = serde_json::from_value::<HistoryQuery>(data) {
+        send_result(ack, query_history(query).await);
+    } else {
+        send_error(ack, "Invalid history request");
+    }
+}
+
+/// Handles the `export-history` WS event. Exports the download history as JSON unless the CSV
+/// format is requested.
+async fn handle_export_history_event(ack: AckSender, Data(data): Data<JSON>) {
+    if let Ok(format) = serde_json::from_value::<Option<ExportFormat>>(data) {
+        send_result(ack, export_history(format.unwrap_or_default()).await);
+    } else {
+        send_error(ack, "Invalid history export request");
+    }
+}
+
+/// Handles the `prune-history` WS event, which removes entries from the download history.
+async fn handle_prune_history_event(ack: AckSender, Data(data): Data<JSON>) {
+    if let Ok(request) = serde_json::from_value::<PruneRequest>(data) {
+        send_result(
+            ack,
+            prune_history(request)
+                .await
+                .map(|removed| json!({ "removed": removed })),
+        );
+    } else {
+        send_error(ack, "Invalid history prune request");
+    }
+}
+
+/// Handles the `preview-output-path` WS event, which shows where a VOD would be downloaded to with
+/// the given or the configured output templates.
+async fn handle_preview_output_path_event(ack: AckSender, Data(data): Data<JSON>) {
+    if let Ok(request) = serde_json::from_value::<OutputPathPreview>(data) {
+        let preview_result = async {
+            let vod = get_vod_meta(&request.url, "").await?;
+
+            preview_output_path(&vod, request.file_template, request.dir_template)
+        };
+
+        send_result(ack, preview_result.await);
+    } else {
+        send_error(ack, "Invalid output path preview request");
+    }
+}
+
+/// Handles the `save-subscription` WS event, which adds or replaces a watchlist subscription.
+async fn handle_save_subscription_event(ack: AckSender, Data(data): Data<JSON>) {
+    if let Ok(subscription) = serde_json::from_value::<Subscription>(data) {
+        let result = save_subscription(subscription).await;
+
+        if result.is_ok() {
             emit_config_update();
         }
 
-        send_result(ack, dl);
+        send_result(ack, result);
+    } else {
+        send_error(ack, "Invalid subscription request");
+    }
+}
+
+/// Handles the `remove-subscription` WS event.
+async fn handle_remove_subscription_event(ack: AckSender, Data(data): Data<JSON>) {
+    if let Some(id) = data.as_str() {
+        match remove_subscription(id).await {
+            Ok(()) => {
+                ack.send(get_redacted_config()).ok();
+                emit_config_update();
+            }
+            Err(error) => send_error(ack, error),
+        }
+    } else {
+        send_error(ack, "Invalid subscription request");
+    }
+}
+
+/// Handles the `download` WS event.
+fn handle_download_event(ack: AckSender, Data(mut data): Data<JSON>) {
+    if let (Ok(vod), Some(is_restart)) = (
+        serde_json::from_value::<Vod>(data.try_get_mut(0, &mut JSON::Null).take()),
+        data.try_get(1).as_bool(),
+    ) {
+        send_result(
+            ack,
+            enqueue_download(vod, is_restart, create_download_handlers()),
+        );
     } else {
         send_error(ack, "Invalid download request");
     }
 }
 
 /// Emits VOD download progress.
-fn emit_vod_download_progress(q_id: &str, updates: JSON) {
+pub fn emit_vod_download_progress(q_id: &str, updates: JSON) {
     emit_to_all("dl-progress", (q_id, updates));
 }
 
-/// Handles the `cancel-download` WS event.
-fn handle_cancel_download_event(ack: AckSender, Data(data): Data<JSON>) {
+/// Emits the new order of the pending downloads, as a single argument.
+pub fn emit_pending_dl_order(order: &[String]) {
+    emit_to_all("pending-dl-order", (order,));
+}
+
+/// Handles the `cancel-download` WS event. Responds once the download has been stopped and its
+/// partially downloaded files have been cleaned up.
+async fn handle_cancel_download_event(ack: AckSender, Data(data): Data<JSON>) {
     if let Ok(vod) = serde_json::from_value::<Vod>(data) {
-        send_result(ack, cancel_download(&vod));
+        let result = cancel_scheduled_download(&vod).await;
+
+        if result.is_ok() {
+            emit_vod_download_progress(&vod.q_id, json!({ "status": "cancelled" }));
+        }
+
+        send_result(
+            ack,
+            result.map(|removed| {
+                json!({
+                    "removedFiles": removed
+                })
+            }),
+        );
     } else {
         send_error(ack, "Invalid download cancellation request");
     }
 }
 
+/// Handles the `pause-download` WS event.
+async fn handle_pause_download_event(ack: AckSender, Data(data): Data<JSON>) {
+    if let Ok(vod) = serde_json::from_value::<Vod>(data) {
+        let result = pause_scheduled_download(&vod).await;
+
+        if result.is_ok() {
+            emit_vod_download_progress(&vod.q_id, json!({ "status": "paused" }));
+        }
+
+        send_result(ack, result);
+    } else {
+        send_error(ack, "Invalid download pause request");
+    }
+}
+
+/// Handles the `resume-download` WS event.
+fn handle_resume_download_event(ack: AckSender, Data(data): Data<JSON>) {
+    if let Ok(vod) = serde_json::from_value::<Vod>(data) {
+        let result = resume_download(&vod.q_id, create_download_handlers());
+
+        if let Ok(queued_vod) = &result {
+            emit_vod_download_progress(&queued_vod.q_id, json!(queued_vod));
+        }
+
+        send_result(ack, result);
+    } else {
+        send_error(ack, "Invalid download resume request");
+    }
+}
+
+/// Handles the `move-pending-download` WS event.
+fn handle_move_pending_download_event(ack: AckSender, Data(data): Data<JSON>) {
+    if let (Some(q_id), Some(position)) = (data.try_get(0).as_str(), data.try_get(1).as_u64()) {
+        let result = move_pending_download(q_id, usize::try_from(position).unwrap_or(usize::MAX));
+
+        if let Ok(order) = &result {
+            emit_pending_dl_order(order);
+        }
+
+        send_result(ack, result.map(|order| (order,)));
+    } else {
+        send_error(ack, "Invalid download reorder request");
+    }
+}
+
 /// Handles the `get-formats` WS event.
 async fn handle_get_formats_event(ack: AckSender, Data(data): Data<JSON>) {
     if let Some(url) = data.as_str() {
         let formats_result = async {
-            let mut vod = get_vod_meta(url).await?;
-            let hls = get_vod_stream_url(vod.id).await?;
-            let formats = get_vod_formats(&hls).await?;
+            let mut vod = get_vod_meta(url, "").await?;
+            let hls = get_vod_stream_url(vod.id, &vod.account).await?;
+            let VodFormats {
+                formats,
+                audio_tracks,
+                subtitles,
+            } = get_vod_formats(&hls, |warning| {
+                emit_warn(&format!("{}: {warning}", vod.title))
+            })
+            .await?;
 
             vod.q_id = create_uuid();
 
             let response = json!({
                 "VOD": vod,
-                "formats": formats
+                "formats": formats,
+                "audioTracks": audio_tracks,
+                "subtitles": subtitles
             });
 
             Ok::<JSON, anyhow::Error>(response)
@@ -0,0 +1,308 @@
+#![allow(dead_code)]
+
+// Libs
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use serde_json::{json, Value};
+use tokio::{net::TcpListener, task::JoinHandle, time::sleep};
+
+use ufcr_util::fp_util::FightPassApi;
+
+// Statics
+pub const EMAIL: &str = "fighter@example.com";
+pub const BLOCKED_EMAIL: &str = "blocked@example.com";
+pub const PASSWORD: &str = "correct-password";
+pub const AUTH_TOKEN: &str = "fake-auth-token";
+pub const EXPIRED_AUTH_TOKEN: &str = "expired-auth-token";
+pub const REFRESHED_AUTH_TOKEN: &str = "refreshed-auth-token";
+pub const REFRESH_TOKEN: &str = "fake-refresh-token";
+pub const VOD_ID: u64 = 123_456;
+pub const SECOND_VOD_ID: u64 = 123_457;
+pub const PLAYLIST_ID: u64 = 42;
+pub const EVENT_PAGE: &str = "ufc-300";
+
+// Structs
+/// A fake UFC Fight Pass server, that implements the front-office and search APIs used by UFC
+/// Ripper. The server stops when this is dropped.
+pub struct FakeFightPass {
+    pub url: String,
+    refreshes: Arc<AtomicUsize>,
+    handle: JoinHandle<()>,
+}
+
+impl FakeFightPass {
+    /// Starts a fake Fight Pass server on a random local port.
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .await
+            .expect("Failed to bind the fake Fight Pass server");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let refreshes = Arc::new(AtomicUsize::new(0));
+
+        let app = Router::new()
+            .route("/api/v2/login", post(handle_login))
+            .route("/api/v2/token/refresh", post(handle_refresh))
+            .route("/api/v2/vod/:id", get(handle_vod_meta))
+            .route("/api/v3/stream/vod/:id", get(handle_stream_callback))
+            .route("/playback/:id", get(handle_playback))
+            .route("/api/v4/content/:page", get(handle_browse_page))
+            .route("/api/v4/playlist/:id", get(handle_playlist))
+            .route("/1/indexes/:index/queries", post(handle_search))
+            .layer(Extension(refreshes.clone()))
+            .with_state(url.clone());
+
+        let handle = tokio::spawn(async move {
+            axum::serve(listener, app).await.ok();
+        });
+
+        FakeFightPass {
+            url,
+            refreshes,
+            handle,
+        }
+    }
+
+    /// Returns the number of access token refreshes requested from this server.
+    pub fn refresh_count(&self) -> usize {
+        self.refreshes.load(Ordering::SeqCst)
+    }
+
+    /// Returns a Fight Pass API client that uses this server.
+    pub fn api(&self) -> FightPassApi {
+        FightPassApi {
+            api_url: self.url.clone(),
+            search_url: self.url.clone(),
+            ..FightPassApi::default()
+        }
+    }
+}
+
+impl Drop for FakeFightPass {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Creates an error response with a `messages` array, like the Fight Pass API.
+fn error_response(status: StatusCode, message: &str) -> Response {
+    (status, Json(json!({ "messages": [message] }))).into_response()
+}
+
+/// Returns the bearer token in the request headers.
+fn get_bearer_token(headers: &HeaderMap) -> &str {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or("")
+}
+
+/// Checks the bearer token and returns an error response if it's not valid.
+fn check_auth(headers: &HeaderMap) -> Result<(), Response> {
+    match get_bearer_token(headers) {
+        AUTH_TOKEN | REFRESHED_AUTH_TOKEN => Ok(()),
+        EXPIRED_AUTH_TOKEN => Err(error_response(
+            StatusCode::UNAUTHORIZED,
+            "Bearer token is not valid",
+        )),
+        _ => Err(error_response(StatusCode::UNAUTHORIZED, "notAuthorized")),
+    }
+}
+
+async fn handle_login(Json(body): Json<Value>) -> Response {
+    match (body["id"].as_str(), body["secret"].as_str()) {
+        (Some(BLOCKED_EMAIL), _) => error_response(StatusCode::UNAUTHORIZED, "badLocation"),
+        (Some(EMAIL), Some(PASSWORD)) => Json(json!({
+            "authorisationToken": AUTH_TOKEN,
+            "refreshToken": REFRESH_TOKEN
+        }))
+        .into_response(),
+        _ => error_response(StatusCode::UNAUTHORIZED, "badCredentials"),
+    }
+}
+
+async fn handle_refresh(
+    Extension(refreshes): Extension<Arc<AtomicUsize>>,
+    Json(body): Json<Value>,
+) -> Response {
+    refreshes.fetch_add(1, Ordering::SeqCst);
+
+    // Slow refreshes make concurrent requests wait for the same refresh
+    sleep(Duration::from_millis(100)).await;
+
+    if body["refreshToken"].as_str() == Some(REFRESH_TOKEN) {
+        Json(json!({ "authorisationToken": REFRESHED_AUTH_TOKEN })).into_response()
+    } else {
+        error_response(StatusCode::UNAUTHORIZED, "errorRefreshingToken")
+    }
+}
+
+async fn handle_vod_meta(Path(id): Path<u64>, headers: HeaderMap) -> Response {
+    if let Err(resp) = check_auth(&headers) {
+        return resp;
+    }
+
+    if id != VOD_ID {
+        return error_response(StatusCode::NOT_FOUND, "notFound");
+    }
+
+    Json(json!({
+        "id": VOD_ID,
+        "title": "UFC 300: Pereira vs. Hill",
+        "description": "Fake event",
+        "thumbnailUrl": "https://example.com/thumb.jpg",
+        "duration": 3600,
+        "startDate": 1_713_052_800u64,
+        "chapters": [
+            { "title": "Main card", "startTime": 0 },
+            { "title": "Main event", "startTime": 2400 }
+        ],
+        "accessLevel": "GRANTED"
+    }))
+    .into_response()
+}
+
+async fn handle_stream_callback(
+    State(url): State<String>,
+    Path(id): Path<u64>,
+    headers: HeaderMap,
+) -> Response {
+    if let Err(resp) = check_auth(&headers) {
+        return resp;
+    }
+
+    Json(json!({ "playerUrlCallback": format!("{url}/playback/{id}") })).into_response()
+}
+
+async fn handle_playback(Path(id): Path<u64>) -> Json<Value> {
+    Json(json!({
+        "hls": [
+            { "url": format!("https://stream.example.com/{id}/master.m3u8") }
+        ]
+    }))
+}
+
+async fn handle_browse_page(Path(page): Path<String>, headers: HeaderMap) -> Response {
+    if let Err(resp) = check_auth(&headers) {
+        return resp;
+    }
+
+    if page != EVENT_PAGE {
+        return error_response(StatusCode::NOT_FOUND, "notFound");
+    }
+
+    Json(json!({
+        "title": "UFC 300",
+        "buckets": [
+            {
+                "exid": "main-card",
+                "name": "Main Card",
+                "contentList": [
+                    { "id": VOD_ID, "type": "VOD", "title": "Pereira vs. Hill", "duration": 900 },
+                    { "id": PLAYLIST_ID, "type": "PLAYLIST", "title": "UFC 300 Extras" }
+                ]
+            },
+            {
+                "exid": "prelims",
+                "name": "Prelims",
+                "contentList": [
+                    { "id": SECOND_VOD_ID, "type": "VOD", "title": "Holloway vs. Gaethje" },
+                    { "id": VOD_ID, "type": "VOD", "title": "Pereira vs. Hill" },
+                    { "id": 1, "type": "LIVE_EVENT", "title": "Post-fight Show" }
+                ]
+            }
+        ]
+    }))
+    .into_response()
+}
+
+/// Returns the playlist in two pages, to test the paging.
+async fn handle_playlist(
+    Path(id): Path<u64>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Response {
+    if let Err(resp) = check_auth(&headers) {
+        return resp;
+    }
+
+    if id != PLAYLIST_ID {
+        return error_response(StatusCode::NOT_FOUND, "notFound");
+    }
+
+    let (vod_id, more_data_available) = match params.get("lastSeen") {
+        Some(_) => (SECOND_VOD_ID, false),
+        None => (VOD_ID, true),
+    };
+
+    Json(json!({
+        "id": PLAYLIST_ID,
+        "title": "UFC 300 Extras",
+        "description": "Fake playlist",
+        "coverUrl": "https://example.com/cover.jpg",
+        "vods": [
+            { "id": vod_id, "title": format!("Fight {vod_id}"), "duration": 600 }
+        ],
+        "paging": {
+            "moreDataAvailable": more_data_available,
+            "lastSeen": vod_id
+        }
+    }))
+    .into_response()
+}
+
+async fn handle_search(Path(index): Path<String>, Json(body): Json<Value>) -> Response {
+    // The index has no replica sorted from the oldest to the newest
+    if body["requests"][0]["indexName"]
+        .as_str()
+        .is_some_and(|index_name| index_name.ends_with("_date_asc"))
+    {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "message": "Index does not exist", "status": 404 })),
+        )
+            .into_response();
+    }
+
+    Json(json!({
+        "results": [
+            {
+                "index": index,
+                "params": body["requests"][0]["params"],
+                "hits": [
+                    {
+                        "id": VOD_ID,
+                        "duration": 7200,
+                        "_highlightResult": {
+                            "name": { "value": "UFC <em>300</em>: Pereira vs. Hill" }
+                        }
+                    }
+                ],
+                "page": 0,
+                "nbPages": 1,
+                "nbHits": 1,
+                "hitsPerPage": 12,
+                "facets": {
+                    "fighters": { "Alex Pereira": 1, "Jamahal Hill": 1 },
+                    "weightClass": { "Light Heavyweight": 1 }
+                }
+            }
+        ]
+    }))
+    .into_response()
+}
@@ -0,0 +1,239 @@
+// Libs
+mod common;
+
+use common::{
+    FakeFightPass, AUTH_TOKEN, BLOCKED_EMAIL, EMAIL, EVENT_PAGE, EXPIRED_AUTH_TOKEN, PASSWORD,
+    PLAYLIST_ID, REFRESHED_AUTH_TOKEN, REFRESH_TOKEN, SECOND_VOD_ID, VOD_ID,
+};
+use ufcr_util::fp_util::{
+    BrowseItemKind, ExpiredTokenError, InvalidRequestError, NotFoundError, SearchRequest,
+    SearchSort,
+};
+
+#[tokio::test]
+async fn integration_login() {
+    let server = FakeFightPass::start().await;
+    let session = server.api().login(EMAIL, PASSWORD).await.unwrap();
+
+    assert_eq!(session.user, EMAIL);
+    assert_eq!(session.auth, AUTH_TOKEN);
+    assert_eq!(session.refresh, REFRESH_TOKEN);
+}
+
+#[tokio::test]
+async fn integration_login_failures() {
+    let server = FakeFightPass::start().await;
+    let api = server.api();
+
+    let error = api.login(EMAIL, "wrong-password").await.unwrap_err();
+    assert!(error.to_string().starts_with("Login failed"));
+
+    let error = api.login(BLOCKED_EMAIL, PASSWORD).await.unwrap_err();
+    assert!(error.to_string().contains("IP address"));
+}
+
+#[tokio::test]
+async fn integration_refresh_access_token() {
+    let server = FakeFightPass::start().await;
+    let api = server.api();
+
+    let new_token = api
+        .refresh_access_token(EXPIRED_AUTH_TOKEN, REFRESH_TOKEN)
+        .await
+        .unwrap();
+    assert_eq!(new_token, REFRESHED_AUTH_TOKEN);
+
+    let error = api
+        .refresh_access_token(EXPIRED_AUTH_TOKEN, "invalid-refresh-token")
+        .await
+        .unwrap_err();
+    assert!(error.to_string().starts_with("Invalid refresh token"));
+}
+
+#[tokio::test]
+async fn integration_vod_meta() {
+    let server = FakeFightPass::start().await;
+    let api = server.api();
+
+    let meta = api
+        .get_vod_meta(&VOD_ID.to_string(), AUTH_TOKEN)
+        .await
+        .unwrap();
+    assert_eq!(meta["id"].as_u64(), Some(VOD_ID));
+
+    let error = api
+        .get_vod_meta(&VOD_ID.to_string(), EXPIRED_AUTH_TOKEN)
+        .await
+        .unwrap_err();
+    assert!(error.is::<ExpiredTokenError>());
+
+    let error = api.get_vod_meta("1", AUTH_TOKEN).await.unwrap_err();
+    assert!(error.to_string().contains("does not exist"));
+}
+
+#[tokio::test]
+async fn integration_refresh_and_retry_vod_meta() {
+    let server = FakeFightPass::start().await;
+    let api = server.api();
+
+    let error = api
+        .get_vod_meta(&VOD_ID.to_string(), EXPIRED_AUTH_TOKEN)
+        .await
+        .unwrap_err();
+    assert!(error.is::<ExpiredTokenError>());
+
+    let new_token = api
+        .refresh_access_token(EXPIRED_AUTH_TOKEN, REFRESH_TOKEN)
+        .await
+        .unwrap();
+    let meta = api
+        .get_vod_meta(&VOD_ID.to_string(), &new_token)
+        .await
+        .unwrap();
+    assert_eq!(meta["title"].as_str(), Some("UFC 300: Pereira vs. Hill"));
+}
+
+#[tokio::test]
+async fn integration_vod_stream_url() {
+    let server = FakeFightPass::start().await;
+    let api = server.api();
+
+    let hls = api.get_vod_stream_url(VOD_ID, AUTH_TOKEN).await.unwrap();
+    assert_eq!(
+        hls,
+        format!("https://stream.example.com/{VOD_ID}/master.m3u8")
+    );
+
+    let error = api
+        .get_vod_stream_url(VOD_ID, EXPIRED_AUTH_TOKEN)
+        .await
+        .unwrap_err();
+    assert!(error.is::<ExpiredTokenError>());
+
+    let error = api
+        .get_vod_stream_url(VOD_ID, "invalid-token")
+        .await
+        .unwrap_err();
+    assert!(!error.is::<ExpiredTokenError>());
+}
+
+#[tokio::test]
+async fn integration_search_vods() {
+    let server = FakeFightPass::start().await;
+    let result = server.api().search_vods("UFC 300", 0, true).await.unwrap();
+
+    assert_eq!(result["hits"][0]["id"].as_u64(), Some(VOD_ID));
+    assert_eq!(
+        result["index"].as_str(),
+        Some("prod-dce.ufc-livestreaming-events")
+    );
+    assert!(result["params"].as_str().unwrap().contains("query=UFC+300"));
+}
+
+#[tokio::test]
+async fn integration_search_with_filters() {
+    let server = FakeFightPass::start().await;
+    let request = SearchRequest {
+        query: "Pereira".into(),
+        hits_per_page: Some(24),
+        min_duration: Some(600),
+        sort: SearchSort::Newest,
+        fighters: vec!["Alex Pereira".into(), "Jamahal Hill".into()],
+        ..SearchRequest::default()
+    };
+    let result = server.api().search(&request).await.unwrap();
+
+    assert_eq!(result.total_hits, 1);
+    assert_eq!(result.hits[0].id, VOD_ID);
+    assert_eq!(result.hits[0].title, "UFC 300: Pereira vs. Hill");
+    assert_eq!(result.hits[0].duration, 7200);
+    assert_eq!(result.facets["fighter"]["Alex Pereira"], 1);
+    assert_eq!(result.facets["weightClass"]["Light Heavyweight"], 1);
+    assert!(!result.facets.contains_key("event"));
+
+    let invalid_request = SearchRequest {
+        min_duration: Some(600),
+        max_duration: Some(60),
+        ..SearchRequest::default()
+    };
+    assert!(server.api().search(&invalid_request).await.is_err());
+}
+
+#[tokio::test]
+async fn integration_search_without_sorted_replica() {
+    let server = FakeFightPass::start().await;
+    let request = SearchRequest {
+        query: "UFC 300".into(),
+        sort: SearchSort::Oldest,
+        ..SearchRequest::default()
+    };
+    let error = server.api().search(&request).await.unwrap_err();
+
+    // The results of a missing replica aren't faked by sorting a page of the main index
+    assert!(error.to_string().contains("Sort the results by relevance"));
+}
+
+#[tokio::test]
+async fn integration_search_with_missing_facet() {
+    let server = FakeFightPass::start().await;
+    let request = SearchRequest {
+        query: "UFC 300".into(),
+        events: vec!["UFC 300".into()],
+        ..SearchRequest::default()
+    };
+    let error = server.api().search(&request).await.unwrap_err();
+
+    assert!(error
+        .to_string()
+        .contains("can't filter the results by \"event\""));
+}
+
+#[tokio::test]
+async fn integration_browse_page() {
+    let server = FakeFightPass::start().await;
+    let api = server.api();
+
+    let page = api.get_browse_page(EVENT_PAGE, AUTH_TOKEN).await.unwrap();
+    assert_eq!(page.title, "UFC 300");
+    assert_eq!(page.sections.len(), 2);
+    assert_eq!(page.sections[0].items[1].kind, BrowseItemKind::Playlist);
+    assert_eq!(page.sections[1].items.len(), 2);
+    assert_eq!(page.get_vod_ids(), vec![VOD_ID, SECOND_VOD_ID]);
+
+    let error = api
+        .get_browse_page(EVENT_PAGE, EXPIRED_AUTH_TOKEN)
+        .await
+        .unwrap_err();
+    assert!(error.is::<ExpiredTokenError>());
+
+    let error = api
+        .get_browse_page("unknown", AUTH_TOKEN)
+        .await
+        .unwrap_err();
+    assert!(error.is::<NotFoundError>());
+    assert!(error.to_string().contains("does not exist"));
+
+    // Invalid page names are rejected before the request is sent
+    let error = api
+        .get_browse_page("../search", AUTH_TOKEN)
+        .await
+        .unwrap_err();
+    assert!(error.is::<InvalidRequestError>());
+}
+
+#[tokio::test]
+async fn integration_playlist() {
+    let server = FakeFightPass::start().await;
+    let playlist = server
+        .api()
+        .get_playlist(PLAYLIST_ID, AUTH_TOKEN)
+        .await
+        .unwrap();
+
+    assert_eq!(playlist.title, "UFC 300 Extras");
+    assert_eq!(playlist.thumb, "https://example.com/cover.jpg");
+    assert_eq!(
+        playlist.vods.iter().map(|vod| vod.id).collect::<Vec<u64>>(),
+        vec![VOD_ID, SECOND_VOD_ID]
+    );
+}
@@ -0,0 +1,110 @@
+// Libs
+mod common;
+
+use std::{env, fs};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use common::{
+    FakeFightPass, AUTH_TOKEN, EMAIL, EXPIRED_AUTH_TOKEN, REFRESHED_AUTH_TOKEN, REFRESH_TOKEN,
+    VOD_ID,
+};
+use ufcr_util::{
+    config_util::{get_config, inc_file_number, update_config, AccountProfile, ConfigUpdate},
+    net_util::{get_unix_time, get_vod_meta, JSON},
+};
+
+// Statics
+const ACCOUNT_NAME: &str = "main";
+/// Number of requests sent at the same time with the same access token.
+const CONCURRENT_REQUESTS: usize = 4;
+
+/// Saves the test account with an access token, as the active account.
+async fn save_account(auth_token: &str) {
+    update_config(ConfigUpdate::Account(AccountProfile {
+        name: ACCOUNT_NAME.to_string(),
+        region: "dce.ufc".to_string(),
+        user: EMAIL.to_string(),
+        refresh_token: REFRESH_TOKEN.to_string(),
+        auth_token: auth_token.to_string(),
+    }))
+    .await;
+}
+
+/// Returns the saved access token of the test account.
+fn get_auth_token() -> String {
+    get_config().get_account(ACCOUNT_NAME).unwrap().auth_token
+}
+
+/// Creates an unsigned JWT that expires in the given number of seconds.
+fn create_jwt(expires_in: u64) -> String {
+    let payload = format!(r#"{{"exp":{}}}"#, get_unix_time() + expires_in);
+
+    format!("e30.{}.", URL_SAFE_NO_PAD.encode(payload))
+}
+
+/// Requests the metadata of a VOD concurrently with the test account, and checks that every
+/// request succeeds.
+async fn get_vod_meta_concurrently() {
+    let requests: Vec<_> = (0..CONCURRENT_REQUESTS)
+        .map(|_| tokio::spawn(async { get_vod_meta(&VOD_ID.to_string(), ACCOUNT_NAME).await }))
+        .collect();
+
+    for request in requests {
+        assert_eq!(request.await.unwrap().unwrap().id, VOD_ID);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn integration_concurrent_token_refresh() {
+    let server = FakeFightPass::start().await;
+    let root_dir = env::temp_dir().join(format!("ufcr-{}", uuid::Uuid::new_v4()));
+
+    fs::create_dir_all(root_dir.join("config")).unwrap();
+
+    // The paths and the API URL are only read once, so they have to be set before anything else
+    env::set_var("UFCR_APP_ROOT_DIR", &root_dir);
+    env::set_var("UFCR_FIGHT_PASS_API_URL", &server.url);
+
+    // A rejected access token is refreshed once, and every request is retried with the new token
+    save_account(EXPIRED_AUTH_TOKEN).await;
+    get_vod_meta_concurrently().await;
+    assert_eq!(server.refresh_count(), 1);
+    assert_eq!(get_auth_token(), REFRESHED_AUTH_TOKEN);
+
+    // An access token that's about to expire is refreshed once, before the requests are sent
+    save_account(&create_jwt(60)).await;
+    get_vod_meta_concurrently().await;
+    assert_eq!(server.refresh_count(), 2);
+    assert_eq!(get_auth_token(), REFRESHED_AUTH_TOKEN);
+
+    // A valid access token is used as is
+    save_account(AUTH_TOKEN).await;
+    get_vod_meta_concurrently().await;
+    assert_eq!(server.refresh_count(), 2);
+    assert_eq!(get_auth_token(), AUTH_TOKEN);
+
+    // Token refreshes and file number updates that happen at the same time keep each other's
+    // changes, both in memory and in "config.json"
+    let cur_number = get_config().cur_number;
+    let file_numbers: Vec<_> = (0..CONCURRENT_REQUESTS)
+        .map(|_| tokio::spawn(inc_file_number()))
+        .collect();
+
+    save_account(EXPIRED_AUTH_TOKEN).await;
+    get_vod_meta_concurrently().await;
+
+    for file_number in file_numbers {
+        file_number.await.unwrap();
+    }
+
+    let saved_config: JSON =
+        serde_json::from_str(&fs::read_to_string(root_dir.join("config/config.json")).unwrap())
+            .unwrap();
+    let expected_number = cur_number + u64::try_from(CONCURRENT_REQUESTS).unwrap();
+
+    assert_eq!(get_config().cur_number, expected_number);
+    assert_eq!(get_auth_token(), REFRESHED_AUTH_TOKEN);
+    assert_eq!(saved_config["curNumber"], expected_number);
+
+    fs::remove_dir_all(root_dir).ok();
+}
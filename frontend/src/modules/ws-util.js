@@ -31,6 +31,7 @@ export function useWSUtil() {
         socket.on('server-error', onServerError);
         socket.on('server-warning', onServerWarning);
         socket.on('dl-progress', onDownloadProgress);
+        socket.on('pending-dl-order', onPendingDLOrder);
         socket.on('media-tool-dl-progress', onMediaToolDLProgress);
     }
 
@@ -70,6 +71,13 @@ export function useWSUtil() {
             ...store.downloads[qID],
             ...updates
         };
+
+        // Downloads join or leave the pending downloads when their status changes
+        if (updates.status) getPendingDLOrder();
+    }
+
+    function onPendingDLOrder(order) {
+        store.pendingDLs = order;
     }
 
     function onMediaToolDLProgress(tool, updates) {
@@ -101,6 +109,16 @@ export function useWSUtil() {
         } catch (error) {
             store.popError(error);
         }
+
+        await getPendingDLOrder();
+    }
+
+    async function getPendingDLOrder() {
+        try {
+            store.pendingDLs = await emitPromise('get-pending-dl-order');
+        } catch (error) {
+            store.popError(error);
+        }
     }
 
     async function saveConfig(newConfig) {
@@ -157,6 +175,18 @@ export function useWSUtil() {
         return emitPromise('cancel-download', VOD);
     }
 
+    function pauseDownload(VOD) {
+        return emitPromise('pause-download', VOD);
+    }
+
+    function resumeDownload(VOD) {
+        return emitPromise('resume-download', VOD);
+    }
+
+    async function movePendingDownload(qID, position) {
+        store.pendingDLs = await emitPromise('move-pending-download', qID, position);
+    }
+
     async function clearDLQ() {
         try {
             store.downloads = {
@@ -190,6 +220,9 @@ export function useWSUtil() {
         getFormats,
         downloadVOD,
         cancelDownload,
+        pauseDownload,
+        resumeDownload,
+        movePendingDownload,
         searchVODs,
         clearDLQ,
         openDownloadsDir,
@@ -24,6 +24,7 @@ export const useAppStore = defineStore('app', {
             }
         },
         downloads: {},
+        pendingDLs: [],
         search: {
             showResults: false,
             result: {
@@ -66,6 +67,7 @@ export const useAppStore = defineStore('app', {
         activeDownloads() {
             return this.downloadQueue.filter((dl) => dl.status === 'downloading').length;
         },
+        getPendingPosition: (state) => (qID) => state.pendingDLs.indexOf(qID),
         missingTools: (state) => Object.keys(state.mediaTools).filter((bin) => state.mediaTools[bin].avail === false),
         searchIsResultsAvailable: (state) => !!state.search.result.hits.length,
         searchCurrentPage: (state) => state.search.result.page + 1,
@@ -122,7 +124,7 @@ export const useAppStore = defineStore('app', {
             this.downloads[VOD.qID] = {
                 ...VOD,
                 task: 'prepare',
-                status: 'downloading',
+                status: 'queued',
                 progress: 0,
                 size: 'N/A',
                 speed: 'N/A',